@@ -4,7 +4,6 @@ use std::path::Path;
 use std::str::FromStr;
 
 use cdimage::msf::Msf;
-use cdimage::Image;
 
 fn main() {
     let argv: Vec<_> = std::env::args().collect();
@@ -15,15 +14,9 @@ fn main() {
 
     let file = Path::new(&argv[1]);
 
-    let img = if file.extension().and_then(|ext| ext.to_str()) == Some("cue") {
-        cdimage::cue::Cue::new(file)
-    } else {
-        cdimage::cue::Cue::new_from_zip(file)
-    };
+    let mut img = cdimage::formats::open(file).unwrap_or_else(|e| panic!("Image error: {}", e));
 
-    let mut img = img.unwrap_or_else(|e| panic!("Cue error: {}", e));
-
-    println!("{:?}", img.toc());
+    println!("{}", img.toc());
 
     if argv.len() >= 3 {
         let msf = &argv[2];
@@ -43,7 +36,7 @@ fn main() {
 
 fn hexdump(bytes: &[u8]) {
     fn is_print(b: u8) -> bool {
-        b >= b' ' && b <= b'~'
+        (b' '..=b'~').contains(&b)
     }
 
     let mut pos = 0;
@@ -83,9 +76,7 @@ fn hexdump(bytes: &[u8]) {
     if rem != bytes.len() {
         print!("{:08x} ", rem);
 
-        for p in rem..bytes.len() {
-            let b = bytes[p];
-
+        for (p, &b) in bytes.iter().enumerate().skip(rem) {
             if p % 8 == 0 {
                 print!(" ");
             }
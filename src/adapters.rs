@@ -0,0 +1,76 @@
+//! Wrappers around `Image` implementations that alter the way the underlying image is presented
+
+use subchannel::Q;
+use {Bcd, CdResult, DiscPosition, Image, Sector, Toc};
+
+/// Image wrapper that reports a user-supplied table of contents while reading the sectors from
+/// the wrapped image. This can be used to emulate discs whose ToC intentionally doesn't match
+/// the actual layout of the data, as some copy protection schemes do.
+///
+/// Sectors (including their Q subchannel data) are returned unmodified by the inner image, the
+/// overridden ToC is only used by `toc()` and the default `Image` methods that rely on it.
+pub struct TocOverride<I: Image> {
+    inner: I,
+    toc: Toc,
+}
+
+impl<I: Image> TocOverride<I> {
+    /// Wrap `inner` and report `toc` as its table of contents
+    pub fn new(inner: I, toc: Toc) -> TocOverride<I> {
+        TocOverride { inner, toc }
+    }
+
+    /// Returns a reference to the wrapped image
+    pub fn inner(&self) -> &I {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped image
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.inner
+    }
+
+    /// Replace the overridden table of contents
+    pub fn set_toc(&mut self, toc: Toc) {
+        self.toc = toc;
+    }
+
+    /// Consume the wrapper and return the inner image
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I: Image> Image for TocOverride<I> {
+    fn image_format(&self) -> String {
+        format!("{} (ToC override)", self.inner.image_format())
+    }
+
+    fn read_sector(&mut self, position: DiscPosition) -> CdResult<Sector> {
+        self.inner.read_sector(position)
+    }
+
+    fn read_sector_into(&mut self, position: DiscPosition, sector: &mut Sector) -> CdResult<()> {
+        self.inner.read_sector_into(position, sector)
+    }
+
+    fn read_sectors(&mut self, position: DiscPosition, count: u32) -> CdResult<Vec<Sector>> {
+        self.inner.read_sectors(position, count)
+    }
+
+    fn toc(&self) -> &Toc {
+        &self.toc
+    }
+
+    fn read_subq(&mut self, position: DiscPosition) -> CdResult<Q> {
+        self.inner.read_subq(position)
+    }
+
+    fn media_catalog_number(&mut self) -> CdResult<Option<String>> {
+        self.inner.media_catalog_number()
+    }
+
+    fn track_isrc(&mut self, track: Bcd) -> CdResult<Option<String>> {
+        self.inner.track_isrc(track)
+    }
+}
@@ -0,0 +1,119 @@
+//! Non-blocking access to disc images, only available with the `async` feature.
+//!
+//! The image formats of this crate use blocking I/O. Instead of reimplementing them,
+//! `BlockingImage` wraps any `Image` and runs the reads on Tokio's blocking thread pool, so that
+//! they can be awaited without stalling the executor.
+//!
+//! `AsyncImage::read_sector` returns `impl Future` from a trait method, which requires Rust 1.75
+//! (the crate's `rust-version`).
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use tokio::task::{self, JoinHandle};
+
+use {CdError, CdResult, DiscPosition, Image, Sector, Toc};
+
+/// Asynchronous counterpart of the `Image` trait
+pub trait AsyncImage {
+    /// Return a string identifying the image format in a human-readable way, see
+    /// `Image::image_format`
+    fn image_format(&self) -> String;
+
+    /// Read a single sector at the given absolute MSF. Several reads can be in flight at the same
+    /// time.
+    fn read_sector(&self, position: DiscPosition) -> impl Future<Output = CdResult<Sector>> + Send;
+
+    /// Get the table of contents
+    fn toc(&self) -> &Toc;
+}
+
+/// Adapter implementing `AsyncImage` for a blocking `Image`. The reads are executed one at a time
+/// on Tokio's blocking thread pool, so the futures returned by `AsyncImage::read_sector` must be
+/// polled from within a Tokio runtime.
+pub struct BlockingImage<I> {
+    image: Arc<Mutex<I>>,
+    /// Copy of the image's ToC, so that it can be returned without locking the image
+    toc: Toc,
+    /// Value of `Image::image_format` for the wrapped image
+    format: String,
+}
+
+impl<I: Image + Send + 'static> BlockingImage<I> {
+    /// Wrap `image` in an asynchronous adapter
+    pub fn new(image: I) -> BlockingImage<I> {
+        BlockingImage {
+            toc: image.toc().clone(),
+            format: image.image_format(),
+            image: Arc::new(Mutex::new(image)),
+        }
+    }
+}
+
+impl<I: Image + Send + 'static> AsyncImage for BlockingImage<I> {
+    fn image_format(&self) -> String {
+        format!("Async({})", self.format)
+    }
+
+    // Return the concrete future so that it can be named by the callers
+    #[allow(refining_impl_trait)]
+    fn read_sector(&self, position: DiscPosition) -> ReadSector {
+        let image = self.image.clone();
+
+        let read = move || {
+            let mut image = match image.lock() {
+                Ok(i) => i,
+                // A previous read panicked, the image might be in an inconsistent state
+                Err(_) => return Err(CdError::IoError(io::Error::other("Image lock poisoned"))),
+            };
+
+            image.read_sector(position)
+        };
+
+        ReadSector {
+            read: Some(Box::new(read)),
+            handle: None,
+        }
+    }
+
+    fn toc(&self) -> &Toc {
+        &self.toc
+    }
+}
+
+/// Future returned by `BlockingImage::read_sector`
+pub struct ReadSector {
+    /// Read to perform, moved to the blocking thread pool the first time the future is polled
+    read: Option<Box<dyn FnOnce() -> CdResult<Sector> + Send>>,
+    /// Handle of the blocking task once it's been spawned
+    handle: Option<JoinHandle<CdResult<Sector>>>,
+}
+
+impl Future for ReadSector {
+    type Output = CdResult<Sector>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<CdResult<Sector>> {
+        if let Some(read) = self.read.take() {
+            self.handle = Some(task::spawn_blocking(read));
+        }
+
+        let handle = match self.handle.as_mut() {
+            Some(h) => h,
+            None => panic!("ReadSector polled after completion"),
+        };
+
+        let r = match Pin::new(handle).poll(cx) {
+            Poll::Ready(Ok(r)) => r,
+            // The blocking task panicked or was cancelled
+            Poll::Ready(Err(e)) => Err(CdError::IoError(io::Error::other(e))),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        self.handle = None;
+
+        Poll::Ready(r)
+    }
+}
@@ -202,6 +202,7 @@ impl fmt::Debug for Bcd {
 }
 
 #[test]
+#[allow(clippy::partialeq_to_none)]
 fn conversions() {
     assert!(Bcd::from_bcd(0) == Some(Bcd(0)));
     assert!(Bcd::from_bcd(1) == Some(Bcd(1)));
@@ -0,0 +1,176 @@
+//! Access to the data tracks of a disc image as a sequence of 2048-byte logical blocks, the way
+//! filesystems (ISO9660 and friends) see them.
+
+use bcd::Bcd;
+use sector::{CdRomMode, XaForm, XaSubmode};
+use {CdError, CdResult, DiscPosition, Image, Msf, Sector, Track, TrackFormat};
+
+/// Size of a logical block
+pub const BLOCK_SIZE: usize = 2048;
+
+/// Returns the 2048-byte payload of `sector`. Returns `CdError::BadFormat` if `sector` is not a
+/// Mode 1 or Mode 2 XA/CD-i Form 1 sector.
+pub(crate) fn block_payload(sector: &Sector) -> CdResult<&[u8]> {
+    let data = sector.data_2352();
+
+    match sector.format() {
+        TrackFormat::Mode1 => Ok(&data[16..16 + BLOCK_SIZE]),
+        TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => {
+            // CD-i sectors use the same subheader layout as CD-ROM XA
+            if sector.cdrom_header()?.mode != CdRomMode::Mode2
+                || XaSubmode(data[18]).form() != XaForm::Form1
+            {
+                return Err(CdError::BadFormat);
+            }
+
+            Ok(&data[24..24 + BLOCK_SIZE])
+        }
+        TrackFormat::Audio | TrackFormat::Mode2 => Err(CdError::BadFormat),
+    }
+}
+
+/// Reads the 2048-byte payloads of a data track, skipping the sync pattern, headers and EDC/ECC.
+/// Mode 2 tracks must use CD-ROM XA or CD-i Form 1 sectors.
+pub struct BlockReader<'a> {
+    image: &'a mut dyn Image,
+    /// Track containing the blocks
+    track: Track,
+}
+
+impl<'a> BlockReader<'a> {
+    /// Create a reader for the blocks of `track`. Returns `CdError::BadFormat` if `track` is not a
+    /// CD-ROM track with 2048-byte payloads.
+    pub fn new(image: &'a mut dyn Image, track: Bcd) -> CdResult<BlockReader<'a>> {
+        let track = image.toc().track(track)?.clone();
+
+        match track.format {
+            TrackFormat::Mode1 | TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => (),
+            TrackFormat::Audio | TrackFormat::Mode2 => return Err(CdError::BadFormat),
+        }
+
+        Ok(BlockReader { image, track })
+    }
+
+    /// Returns the number of blocks in the track
+    pub fn len(&self) -> u32 {
+        self.track.length.sector_index()
+    }
+
+    /// Returns true if the track contains no block
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read logical block `lba` into `out`. Block 0 is the first sector of the track. Returns
+    /// `CdError::EndOfTrack` if `lba` is past the end of the track and `CdError::BadFormat` if the
+    /// sector doesn't contain a 2048-byte payload (for instance a Mode 2 Form 2 sector).
+    pub fn read_block(&mut self, lba: u32, out: &mut [u8; BLOCK_SIZE]) -> CdResult<()> {
+        let track_msf = Msf::from_sector_index(lba).ok_or(CdError::EndOfTrack)?;
+        let msf = self.track.absolute_msf(track_msf)?;
+
+        let sector = self.image.read_sector(DiscPosition::Program(msf))?;
+
+        out.copy_from_slice(block_payload(&sector)?);
+
+        Ok(())
+    }
+}
+
+#[test]
+fn read_blocks() {
+    use subchannel::AdrControl;
+    use test_image::TestImage;
+
+    let audio = Track {
+        track: Bcd::ONE,
+        format: TrackFormat::Audio,
+        start: Msf::from_sector_index(150).unwrap(),
+        length: Msf::from_sector_index(100).unwrap(),
+        control: AdrControl::AUDIO,
+    };
+
+    let data = Track {
+        track: Bcd::from_binary(2).unwrap(),
+        format: TrackFormat::Mode1,
+        start: Msf::from_sector_index(250).unwrap(),
+        length: Msf::from_sector_index(30).unwrap(),
+        control: AdrControl::DATA,
+    };
+
+    // Fill the blocks of the Mode 1 track with their LBA
+    let mut image = TestImage::new(vec![audio, data], |t, msf, sector| {
+        if t.format == TrackFormat::Mode1 {
+            let lba = (msf - t.start).sector_index();
+
+            for (i, b) in sector.data_2352_mut()[16..2064].iter_mut().enumerate() {
+                *b = (lba as usize + i) as u8;
+            }
+
+            sector.write_edc_ecc();
+        }
+    });
+
+    assert!(matches!(
+        BlockReader::new(&mut image, Bcd::ONE),
+        Err(CdError::BadFormat)
+    ));
+
+    let mut reader = BlockReader::new(&mut image, Bcd::from_binary(2).unwrap()).unwrap();
+
+    assert_eq!(reader.len(), 30);
+
+    let mut block = [0; BLOCK_SIZE];
+
+    reader.read_block(16, &mut block).unwrap();
+
+    for (i, &b) in block.iter().enumerate() {
+        assert_eq!(b, (16 + i) as u8);
+    }
+
+    reader.read_block(29, &mut block).unwrap();
+    assert_eq!(block[0], 29);
+
+    assert!(matches!(
+        reader.read_block(30, &mut block),
+        Err(CdError::EndOfTrack)
+    ));
+}
+
+#[test]
+fn block_payloads() {
+    use sector::Mode2Form2Builder;
+    use subchannel::{AdrControl, QData, Q};
+
+    let q = Q::from_qdata_mode1(
+        QData::Mode1 {
+            track: Bcd::ONE,
+            index: Bcd::ONE,
+            track_msf: Msf::ZERO,
+            disc_msf: Msf::from_sector_index(150).unwrap(),
+        },
+        AdrControl::DATA,
+    );
+
+    for &format in &[
+        TrackFormat::Mode1,
+        TrackFormat::Mode2Xa,
+        TrackFormat::Mode2CdI,
+    ] {
+        let sector = Sector::empty(q.clone(), format).unwrap();
+
+        assert_eq!(block_payload(&sector).unwrap(), &[0; BLOCK_SIZE][..]);
+    }
+
+    // Form 2 sectors don't have a 2048-byte payload, for CD-ROM XA and CD-i alike
+    for &format in &[TrackFormat::Mode2Xa, TrackFormat::Mode2CdI] {
+        let sector = Mode2Form2Builder::new()
+            .format(format)
+            .build(q.clone(), &[0; 2324])
+            .unwrap();
+
+        assert!(matches!(block_payload(&sector), Err(CdError::BadFormat)));
+    }
+
+    let sector = Sector::empty(q, TrackFormat::Mode2).unwrap();
+    assert!(matches!(block_payload(&sector), Err(CdError::BadFormat)));
+}
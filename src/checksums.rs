@@ -0,0 +1,10 @@
+//! Checksums used by the CD formats, for custom `Image` implementations that need to validate or
+//! regenerate sector and subchannel data.
+
+pub use crc::{crc16, crc32};
+
+/// Returns the CRC-16 of `data` in the big-endian byte order used to store it in the last two
+/// bytes of a Q subchannel frame
+pub fn crc16_be(data: &[u8]) -> [u8; 2] {
+    crc16(data).to_be_bytes()
+}
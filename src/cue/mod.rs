@@ -5,19 +5,39 @@
 //! The original format was described in the CDRWIN user guide but
 //! many extensions and variations exist.
 //!
-//! The CUE file format does not support multi-session discs
+//! The CUE file format does not support multi-session discs, however some tools describe them
+//! using `REM SESSION nn` comments. The session numbers are recorded but the tracks of all the
+//! sessions are laid out contiguously, without the lead-out and lead-in between the sessions.
 
-use std::io::SeekFrom;
+use std::cmp;
+use std::io;
 use std::path::Path;
 
 use internal::IndexCache;
+use metadata;
 use sector::Sector;
-use subchannel::{QData, Q};
-use {CdResult, DiscPosition, Image, Toc};
+use msf::Msf;
+use subchannel::{self, AdrControl, QData, RawSubchannel, Q};
+use {
+    Bcd, CdError, CdResult, DiscPosition, Image, LeadOutMode, Progress, SectorLayout, Toc,
+    TrackFormat,
+};
 
 use self::parser::{BinSource, BinaryBlob, CueParser};
 
 mod parser;
+#[cfg(test)]
+mod tests;
+mod writer;
+
+/// Maximum number of sectors read from a BIN file at once by `Cue::read_sectors`, to bound the
+/// size of the scratch buffer
+const MAX_BULK_SECTORS: u32 = 256;
+
+/// Interval, in sectors, between the Mode 2 (MCN) and Mode 3 (ISRC) Q frames inserted in the Q
+/// subchannel of images with a `CATALOG` or `ISRC` command. The red book requires at least one of
+/// each every 100 sectors.
+const IDENTIFICATION_Q_PERIOD: u32 = 100;
 
 /// CUE parser state.
 pub struct Cue {
@@ -26,15 +46,51 @@ pub struct Cue {
     bin_source: BinSource,
     /// List of all the BIN files referenced in the cue sheet
     bin_files: Vec<BinaryBlob>,
+    /// Names of the BIN files, as found in the cue sheet
+    bin_names: Vec<String>,
     /// Table of contents
     toc: Toc,
+    /// Content of the pregap sectors not stored in the BIN files
+    pregap_fill: PregapFill,
+    /// If true the parts of the sectors that are not stored in the BIN files (sync pattern,
+    /// headers, EDC/ECC) are regenerated
+    regenerate: bool,
+    /// True if the BIN files contain raw subchannel data after each 2352-byte sector
+    raw_subchannel: bool,
+    /// `REM` metadata found in the cue sheet
+    rem: Vec<Rem>,
+    /// Media catalog number set by the `CATALOG` command
+    catalog: Option<String>,
+    /// Track ISRCs set by the `ISRC` commands
+    isrc: Vec<(Bcd, String)>,
+    /// Buffer used for sequential reads, see `Cue::set_read_ahead`
+    read_ahead: ReadAhead,
+    /// What to do when a sector can't be read from the BIN files
+    read_error_policy: ReadErrorPolicy,
+    /// Non-fatal problems found while parsing the cue sheet
+    warnings: Vec<CdError>,
 }
 
 impl Cue {
     /// Parse a CUE sheet, open the BIN files and build a `Cue`
     /// instance.
     pub fn new<P: AsRef<Path>>(cue_path: P) -> CdResult<Cue> {
-        CueParser::build_cue(cue_path)
+        Cue::new_with_options(cue_path, CueOptions::default())
+    }
+
+    /// Same as `Cue::new` but with custom parsing options
+    pub fn new_with_options<P: AsRef<Path>>(cue_path: P, options: CueOptions) -> CdResult<Cue> {
+        CueParser::build_cue(cue_path, options, None)
+    }
+
+    /// Same as `Cue::new_with_options` but calls `progress` for every line of the cue sheet
+    /// parsed
+    pub fn new_with_progress<P: AsRef<Path>>(
+        cue_path: P,
+        options: CueOptions,
+        progress: &mut dyn FnMut(Progress),
+    ) -> CdResult<Cue> {
+        CueParser::build_cue(cue_path, options, Some(progress))
     }
 
     /// Attempt to load a disc image from a ZIP file.
@@ -44,26 +100,444 @@ impl Cue {
     ///
     /// If the archive contains several `.cue` files, only the first one will be loaded.
     pub fn new_from_zip<P: AsRef<Path>>(zip_path: P) -> CdResult<Cue> {
-        CueParser::build_cue_from_zip(zip_path)
+        Cue::new_from_zip_with_options(zip_path, CueOptions::default())
     }
-}
 
-impl Image for Cue {
-    fn image_format(&self) -> String {
-        "CUE".to_string()
+    /// Same as `Cue::new_from_zip` but with custom parsing options
+    pub fn new_from_zip_with_options<P: AsRef<Path>>(
+        zip_path: P,
+        options: CueOptions,
+    ) -> CdResult<Cue> {
+        CueParser::build_cue_from_zip(zip_path, options)
     }
 
-    fn read_sector(&mut self, position: DiscPosition) -> CdResult<Sector> {
+    /// Parse the cue sheet at `cue_path` and check that the BIN files it references can be opened,
+    /// without stopping at the first error. Returns all the errors found (empty if the image can
+    /// be loaded), which is more convenient than `Cue::new` to fix a broken cue sheet.
+    ///
+    /// The non-fatal problems returned by `Cue::warnings` are not included.
+    pub fn validate<P: AsRef<Path>>(cue_path: P) -> Vec<CdError> {
+        Cue::validate_with_options(cue_path, CueOptions::default())
+    }
+
+    /// Same as `Cue::validate` but with custom parsing options
+    pub fn validate_with_options<P: AsRef<Path>>(cue_path: P, options: CueOptions) -> Vec<CdError> {
+        CueParser::validate(cue_path, options)
+    }
+
+    /// Parse the in-memory cue sheet `cue_sheet` and load the BIN files it references from
+    /// `bin_provider` instead of the filesystem.
+    pub fn from_parts(cue_sheet: &[u8], bin_provider: Box<dyn BinProvider>) -> CdResult<Cue> {
+        Cue::from_parts_with_options(cue_sheet, bin_provider, CueOptions::default())
+    }
+
+    /// Same as `Cue::from_parts` but with custom parsing options. `CueOptions::max_open_files` is
+    /// ignored.
+    pub fn from_parts_with_options(
+        cue_sheet: &[u8],
+        bin_provider: Box<dyn BinProvider>,
+        options: CueOptions,
+    ) -> CdResult<Cue> {
+        CueParser::build_cue_from_parts(cue_sheet, bin_provider, options)
+    }
+
+    /// Select what `read_sector` returns for pregap sectors that are not stored in the BIN files.
+    /// Defaults to `PregapFill::Zero`.
+    pub fn set_pregap_fill(&mut self, fill: PregapFill) {
+        self.pregap_fill = fill;
+    }
+
+    /// Set the number of sectors in the lead-out, reads past the end of the lead-out will return
+    /// `CdError::OutOfDiscPosition`. Defaults to `Toc::DEFAULT_LEAD_OUT_LENGTH`.
+    pub fn set_lead_out_length(&mut self, length: Msf) {
+        self.toc.set_lead_out_length(length);
+    }
+
+    /// Set the format of the generated lead-out sectors. By default the format of the last track
+    /// is used.
+    pub fn set_lead_out_format(&mut self, format: TrackFormat) {
+        self.toc.set_lead_out_format(format);
+    }
+
+    /// Set the contents of the generated lead-out sectors, see `Toc::set_lead_out_mode`
+    pub fn set_lead_out_mode(&mut self, mode: LeadOutMode) {
+        self.toc.set_lead_out_mode(mode);
+    }
+
+    /// Set the number of sectors read ahead of the current position when sectors stored in the BIN
+    /// files are read sequentially with `read_sector` or `read_sector_into`. The following
+    /// sectors are then returned from memory instead of hitting the BIN file for every sector.
+    ///
+    /// Read-ahead stops at the end of the current index (so it never spans generated pregaps or
+    /// several BIN files) and is limited to 256 sectors. The buffer is discarded as soon as a
+    /// non-sequential read is performed. Defaults to 0 which disables read-ahead.
+    pub fn set_read_ahead(&mut self, sectors: u32) {
+        self.read_ahead = ReadAhead {
+            sectors,
+            ..ReadAhead::default()
+        };
+    }
+
+    /// Select what `read_sector`, `read_sector_into` and `read_sectors` do when a sector can't be
+    /// read from its BIN file, for instance because the file has been truncated or because of an
+    /// I/O error. Defaults to `ReadErrorPolicy::Fail`.
+    pub fn set_read_error_policy(&mut self, policy: ReadErrorPolicy) {
+        self.read_error_policy = policy;
+    }
+
+    /// Returns the current read error policy
+    pub fn read_error_policy(&self) -> ReadErrorPolicy {
+        self.read_error_policy
+    }
+
+    /// Returns the current pregap fill mode
+    pub fn pregap_fill(&self) -> PregapFill {
+        self.pregap_fill
+    }
+
+    /// For tracks that don't store full 2352-byte sectors in the BIN files (`MODE1/2048`,
+    /// `MODE2/2336`...) select whether `read_sector` regenerates the missing sync pattern, headers
+    /// and EDC/ECC. If set to `false` the missing regions are left zeroed, which makes it possible
+    /// to tell the stored bytes from the synthesized ones. Defaults to `true`.
+    pub fn set_regenerate(&mut self, regenerate: bool) {
+        self.regenerate = regenerate;
+    }
+
+    /// Returns true if the sector regions not stored in the BIN files are regenerated
+    pub fn regenerate(&self) -> bool {
+        self.regenerate
+    }
+
+    /// Returns the non-fatal problems found while loading the image, for instance the partial
+    /// sectors ignored because of `CueOptions::truncate_partial_sectors`
+    pub fn warnings(&self) -> &[CdError] {
+        &self.warnings
+    }
+
+    /// Generate a cue sheet describing this image. Pregaps and postgaps which are not stored in
+    /// the BIN files are written as `PREGAP` and `POSTGAP` commands, pregaps stored in the BIN
+    /// files are written as `INDEX 00`.
+    pub fn write_cue_sheet(&self) -> String {
+        writer::write_cue_sheet(self)
+    }
+
+    /// Returns the session `track` belongs to, as set by `REM SESSION` in the cue sheet. If the cue
+    /// sheet doesn't describe the sessions every track is in session 1, like in the ToC.
+    pub fn track_session(&self, track: Bcd) -> CdResult<u8> {
+        let (_, index) = self.indices.find_index01_for_track(track)?;
+
+        // Session 0 means that the cue sheet doesn't describe the sessions
+        Ok(cmp::max(index.session(), 1))
+    }
+
+    /// Returns the value of the disc-level `REM key value` metadata (placed before the first
+    /// `TRACK` command), for instance `REM DATE 1995`. `key` is matched ignoring ASCII case.
+    pub fn rem(&self, key: &str) -> Option<&str> {
+        self.find_rem(None, key)
+    }
+
+    /// Returns the value of the `REM key value` metadata of `track`, placed after its `TRACK`
+    /// command. `key` is matched ignoring ASCII case.
+    pub fn track_rem(&self, track: Bcd, key: &str) -> Option<&str> {
+        self.find_rem(Some(track), key)
+    }
+
+    /// Returns the ReplayGain of `track` in dB, from its `REM REPLAYGAIN_TRACK_GAIN` metadata.
+    /// Returns `None` if the metadata is missing or malformed.
+    pub fn track_replay_gain(&self, track: Bcd) -> Option<f32> {
+        self.track_rem(track, "REPLAYGAIN_TRACK_GAIN")
+            .and_then(parse_gain)
+    }
+
+    /// Returns the 13-digit media catalog number (UPC/EAN) set by the `CATALOG` command, if any
+    pub fn catalog(&self) -> Option<&str> {
+        self.catalog.as_deref()
+    }
+
+    /// Returns the ISRC of `track` set by its `ISRC` command, if any
+    pub fn isrc(&self, track: Bcd) -> Option<&str> {
+        self.isrc
+            .iter()
+            .find(|&&(t, _)| t == track)
+            .map(|(_, isrc)| isrc.as_str())
+    }
+
+    fn find_rem(&self, track: Option<Bcd>, key: &str) -> Option<&str> {
+        self.rem
+            .iter()
+            .find(|r| r.track == track && r.key.eq_ignore_ascii_case(key))
+            .map(|r| r.value.as_str())
+    }
+
+    /// Override the format of `track` after parsing, for instance to fix a cue sheet that labels a
+    /// CD-i track as `MODE2/2352`. The new format is used by `read_sector` for all the sectors of
+    /// the track (including the regenerated headers and EDC/ECC) and is reflected in the ToC. The
+    /// cue sheet generated by `Cue::write_cue_sheet` is not affected.
+    ///
+    /// If the track switches between audio and data the control bits are reset for the new
+    /// format, only the "digital copy permitted" flag is kept.
+    ///
+    /// Returns `CdError::BadTrack` if `track` doesn't exist and `CdError::BadFormat` if the layout
+    /// of the track in its BIN file can't store sectors of this format (for instance a
+    /// `MODE1/2048` track can't be turned into audio).
+    pub fn override_track_format(&mut self, track: Bcd, format: TrackFormat) -> CdResult<()> {
+        let (_, index01) = self.indices.find_index01_for_track(track)?;
+
+        let old = index01.control();
+
+        let control = if old.is_audio() == format.is_audio() {
+            old
+        } else {
+            let mut c = if format.is_audio() {
+                AdrControl::AUDIO
+            } else {
+                AdrControl::DATA
+            };
+
+            c.set_digital_copy_permited(old.digital_copy_permitted());
+
+            c
+        };
+
+        let raw_subchannel = self.raw_subchannel;
+
+        let compatible = self
+            .indices
+            .indices()
+            .iter()
+            .filter(|i| i.track() == track)
+            .all(|i| match *i.private() {
+                Storage::Bin(_, _, ty, _) => format.supports_layout(ty.layout(raw_subchannel)),
+                _ => true,
+            });
+
+        if !compatible {
+            return Err(CdError::BadFormat);
+        }
+
+        self.indices.set_track_format(track, format, control)?;
+        self.toc.set_track_format(track, format, control)
+    }
+
+    /// Detect data tracks whose format is mislabeled in the cue sheet (for instance a `MODE2/2352`
+    /// track containing Mode 1 sectors) and fix them with `Cue::override_track_format`.
+    ///
+    /// A few sectors of every data track are sampled and passed to `Sector::detect_mode`. The
+    /// format is only changed if the samples agree on a CD-ROM mode different from the declared
+    /// one. Only tracks stored as full 2352-byte sectors can be inferred: for the other layouts
+    /// the headers are regenerated from the declared format. Audio tracks are never modified.
+    pub fn infer_track_formats(&mut self) -> CdResult<()> {
+        /// Number of sectors sampled in every track
+        const SAMPLES: u32 = 4;
+
+        let tracks: Vec<_> = self
+            .toc
+            .tracks()
+            .iter()
+            .filter(|t| !t.format.is_audio())
+            .map(|t| (t.track, t.format, t.length))
+            .collect();
+
+        for (track, declared, length) in tracks {
+            let (_, index01) = self.indices.find_index01_for_track(track)?;
+
+            let raw = match *index01.private() {
+                Storage::Bin(_, _, ty, _) => matches!(
+                    ty.layout(self.raw_subchannel),
+                    SectorLayout::Raw | SectorLayout::RawWithSubchannel
+                ),
+                _ => false,
+            };
+
+            if !raw {
+                continue;
+            }
+
+            let nsectors = length.sector_index();
+            let mut detected = None;
+
+            for i in 0..cmp::min(SAMPLES, nsectors) {
+                let msf =
+                    Msf::from_sector_index(nsectors * i / SAMPLES).ok_or(CdError::InvalidMsf)?;
+
+                let format = match self.read_track_sector(track, msf)?.detect_mode() {
+                    Some(f) if !f.is_audio() => f,
+                    _ => continue,
+                };
+
+                match detected {
+                    None => detected = Some(format),
+                    Some(d) if d.cdrom_mode() == format.cdrom_mode() => (),
+                    // The samples disagree, leave the track alone
+                    Some(_) => {
+                        detected = None;
+                        break;
+                    }
+                }
+            }
+
+            if let Some(format) = detected {
+                if format.cdrom_mode() != declared.cdrom_mode() {
+                    self.override_track_format(track, format)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the location of the sector at `position` in the BIN files, for instance to patch it
+    /// in place. Returns `None` for the sectors that aren't stored: lead-in, lead-out and the
+    /// pregaps and postgaps generated by `PREGAP` and `POSTGAP`. Returns
+    /// `CdError::OutOfDiscPosition` past the end of the lead-out.
+    pub fn sector_file_location(&self, position: DiscPosition) -> CdResult<Option<SectorLocation>> {
         let msf = match position {
-            DiscPosition::LeadIn(index) => return self.toc.build_toc_sector(index),
             DiscPosition::Program(msf) => msf,
+            DiscPosition::LeadIn(_) => return Ok(None),
         };
 
-        let (pos, index) = match self.indices.find_index_for_msf(msf) {
-            Some(i) => i,
-            None => return self.toc.build_lead_out_sector(msf),
+        let pos = match self.indices.find_index_for_msf(msf) {
+            Some((pos, _)) => pos,
+            None if msf < self.toc.lead_out_end() => return Ok(None),
+            None => return Err(CdError::OutOfDiscPosition),
         };
 
+        if !self.is_stored(pos) {
+            return Ok(None);
+        }
+
+        let (bin_index, offset, ty, _) = self.bin_location(pos, msf);
+
+        Ok(Some(SectorLocation {
+            bin_index,
+            offset,
+            size: ty.sector_size(self.raw_subchannel),
+        }))
+    }
+
+    /// Returns the location of the INDEX 01 of `track` in the BIN files
+    pub fn track_layout(&self, track: Bcd) -> CdResult<TrackLayout> {
+        let (_, index) = self.indices.find_index01_for_track(track)?;
+
+        let layout = match *index.private() {
+            Storage::Bin(bin, offset, ty, _) => TrackLayout {
+                bin_index: bin,
+                byte_offset: offset,
+                sector_size: ty.sector_size(self.raw_subchannel),
+                stored: true,
+            },
+            _ => TrackLayout {
+                bin_index: 0,
+                byte_offset: 0,
+                sector_size: 0,
+                stored: false,
+            },
+        };
+
+        Ok(layout)
+    }
+
+    /// Returns the number of bytes of the BIN file `bin_index` (in the order of the `FILE`
+    /// commands of the cue sheet) used by the image. This can be compared with the actual size of
+    /// the file to detect truncated or padded images. Returns 0 if `bin_index` is out of range.
+    pub fn expected_bin_size(&self, bin_index: u32) -> u64 {
+        let indices = self.indices.indices();
+
+        indices
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, index)| match *index.private() {
+                Storage::Bin(bin, _, ty, _) if bin == bin_index => {
+                    let end = match indices.get(pos + 1) {
+                        Some(next) => next.msf(),
+                        None => self.indices.lead_out(),
+                    };
+
+                    let sectors = u64::from((end - index.msf()).sector_index());
+
+                    Some(sectors * u64::from(ty.sector_size(self.raw_subchannel)))
+                }
+                _ => None,
+            })
+            .sum()
+    }
+
+    /// Generate the contents of the sector at `msf` which is not stored in a BIN file. `pos` is the
+    /// position of its index in `self.indices`.
+    fn generate_sector(
+        &mut self,
+        pos: usize,
+        msf: Msf,
+        q: Q,
+        format: TrackFormat,
+    ) -> CdResult<Sector> {
+        let fill = match self.indices.get(pos).map(|i| i.private()) {
+            Some(Storage::Generated { fill }) => fill,
+            // We don't have data for this pregap, generate it
+            Some(Storage::PreGap) => return self.pregap_sector(pos, msf, q, format),
+            _ => panic!("Attempted to generate stored sector"),
+        };
+
+        fill.sector(q, format)
+    }
+
+    /// Generate the contents of the non-stored pregap sector at `msf`. `pos` is the position of
+    /// the pregap index in `self.indices`.
+    fn pregap_sector(
+        &mut self,
+        pos: usize,
+        msf: Msf,
+        q: Q,
+        format: TrackFormat,
+    ) -> CdResult<Sector> {
+        let (start, end) = match (self.indices.get(pos), self.indices.get(pos + 1)) {
+            (Some(pregap), Some(index1)) => (pregap.msf(), index1.msf()),
+            _ => panic!("Pregap without index 1!"),
+        };
+
+        let len = end - start;
+
+        let source = match self.pregap_fill {
+            PregapFill::Zero => None,
+            PregapFill::Silence => return GeneratedFill::Silence.sector(q, format),
+            PregapFill::PrevTrackTail => msf.checked_sub(len),
+            PregapFill::NextTrackHead => msf.checked_add(len),
+        };
+
+        // We can only mirror sectors actually stored in a BIN file with the same format as the
+        // pregap, otherwise we fallback to an empty sector
+        let source = source.filter(|&source| match self.indices.find_index_for_msf(source) {
+            Some((_, index)) => {
+                matches!(index.private(), Storage::Bin(..)) && index.format() == format
+            }
+            None => false,
+        });
+
+        match source {
+            Some(source) => {
+                let source = self.read_sector(DiscPosition::Program(source))?;
+
+                let mut sector = Sector::uninitialized(q, format)?;
+                sector.data_2352_mut().copy_from_slice(source.data_2352());
+
+                // Fix the header to match the new position
+                if format.is_cdrom() {
+                    sector.write_headers();
+                    sector.write_edc_ecc();
+                }
+
+                Ok(sector)
+            }
+            None => GeneratedFill::Zero.sector(q, format),
+        }
+    }
+
+    /// Find the index containing the program area position `msf` and build the corresponding Q
+    /// subchannel data. Returns the position of the index in `self.indices`, the Q data and the
+    /// format of the track. Returns `None` if `msf` is in the lead-out.
+    fn locate(&self, msf: Msf) -> Option<(usize, Q, TrackFormat)> {
+        let (pos, index) = self.indices.find_index_for_msf(msf)?;
+
         // First we compute the relative track MSF
         let track_msf = if index.is_pregap() {
             // In the pregap the track MSF decreases until index1 is reached
@@ -74,16 +548,13 @@ impl Image for Cue {
 
             index1.msf() - msf
         } else {
-            // The track MSF is relative to index1
-            let index1 = if index.index().bcd() == 0x01 {
-                index
-            } else {
-                match self.indices.find_index01_for_track(index.track()) {
-                    Ok((_, i)) => i,
-                    // Shouldn't be reached, should be
-                    // caught by IndexCache's constructor
-                    Err(_e) => panic!("Missing index 1 for track {}", index.track()),
-                }
+            // The track MSF is relative to index1. We can't just use `index` if it's an INDEX 01
+            // since it could be a postgap following the actual start of the index.
+            let index1 = match self.indices.find_index01_for_track(index.track()) {
+                Ok((_, i)) => i,
+                // Shouldn't be reached, should be
+                // caught by IndexCache's constructor
+                Err(_e) => panic!("Missing index 1 for track {}", index.track()),
             };
 
             msf - index1.msf()
@@ -96,48 +567,595 @@ impl Image for Cue {
             disc_msf: msf,
         };
 
-        let ctrl = index.control();
-        let format = index.format();
+        let q = Q::from_qdata_mode1(qdata, index.control());
+
+        Some((pos, q, index.format()))
+    }
 
-        let q = Q::from_qdata_mode1(qdata, ctrl);
+    /// Returns true if the index at `pos` in `self.indices` is stored in a BIN file
+    fn is_stored(&self, pos: usize) -> bool {
+        match self.indices.get(pos) {
+            Some(index) => matches!(index.private(), Storage::Bin(..)),
+            None => false,
+        }
+    }
 
-        // First let's read the sector data
-        let sector = match index.private() {
-            Storage::Bin(bin, offset, ty) => {
-                let bin = &mut self.bin_files[*bin as usize];
+    /// Returns the raw Mode 2 (MCN) or Mode 3 (ISRC) Q frame that replaces the Mode 1 position
+    /// data of the sector at `msf`, if any. The media catalog number is inserted in the first
+    /// sector of every `IDENTIFICATION_Q_PERIOD` and the track's ISRC half way through, outside of
+    /// the pregaps. `pos` is the position in `self.indices` of the index containing `msf`.
+    fn identification_q(&self, pos: usize, msf: Msf, ctrl: AdrControl) -> Option<[u8; 12]> {
+        let n = msf.sector_index();
+        let aframe = msf.frames();
 
-                // For now we only support "simple sector" format
-                if ty.sector_size() != 2352 {
-                    panic!("Unimplemented CUE track type: {:?}", ty);
-                }
+        if n % IDENTIFICATION_Q_PERIOD == 0 {
+            let mcn = self.catalog.as_ref()?;
 
-                let index_offset =
-                    ty.sector_size() as u64 * (msf.sector_index() - index.sector_index()) as u64;
+            subchannel::mcn_q_raw(mcn, ctrl, aframe).ok()
+        } else if n % IDENTIFICATION_Q_PERIOD == IDENTIFICATION_Q_PERIOD / 2 {
+            let index = self.indices.get(pos)?;
 
-                let offset = offset + index_offset;
+            if index.is_pregap() {
+                return None;
+            }
 
-                let mut sector = Sector::uninitialized(q, format)?;
+            let isrc = self.isrc(index.track())?;
+
+            subchannel::isrc_q_raw(isrc, ctrl, aframe).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Attach the Q frame returned by `identification_q` to `sector` as raw subchannel data, unless
+    /// the sector already has some
+    fn add_identification_q(&self, pos: usize, msf: Msf, sector: &mut Sector) {
+        if sector.raw_subchannel().is_some() {
+            return;
+        }
+
+        if let Some(subq) = self.identification_q(pos, msf, sector.q().adr_control()) {
+            sector.set_raw_subchannel(RawSubchannel::from_q(subq));
+        }
+    }
+
+    /// Returns true if the index at `pos` in `self.indices` is stored in a BIN file along with raw
+    /// subchannel data
+    fn has_raw_subchannel(&self, pos: usize) -> bool {
+        match self.indices.get(pos).map(|i| i.private()) {
+            Some(Storage::Bin(_, _, ty, _)) => {
+                ty.layout(self.raw_subchannel) == SectorLayout::RawWithSubchannel
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the location of the sector at `msf` in the BIN files: the index of the BIN file,
+    /// the byte offset of the sector, the track type and the file type. `pos` is the position in
+    /// `self.indices` of the index containing `msf`, it must be stored in a BIN file.
+    fn bin_location(&self, pos: usize, msf: Msf) -> (u32, u64, CueTrackType, CueFileType) {
+        let index = match self.indices.get(pos) {
+            Some(i) => i,
+            None => panic!("Invalid index position {}", pos),
+        };
+
+        let (bin, offset, ty, file_type) = match index.private() {
+            Storage::Bin(bin, offset, ty, file_type) => (*bin, *offset, *ty, *file_type),
+            _ => panic!("Attempted to read generated sector from BIN file"),
+        };
+
+        let index_offset = ty.sector_size(self.raw_subchannel) as u64
+            * (msf.sector_index() - index.sector_index()) as u64;
+
+        (bin, offset + index_offset, ty, file_type)
+    }
+
+    /// Read the data of the sector at `msf` from the BIN file into `sector`. `pos` is the position
+    /// in `self.indices` of the index containing `msf`, it must be stored in a BIN file.
+    fn read_bin(&mut self, pos: usize, msf: Msf, sector: &mut Sector) -> CdResult<()> {
+        let (bin, offset, ty, file_type) = self.bin_location(pos, msf);
+
+        let sector_size = ty.sector_size(self.raw_subchannel) as usize;
+
+        let sequential = self.read_ahead.next == Some((bin, offset));
+
+        self.read_ahead.next = Some((bin, offset + sector_size as u64));
+
+        if !sequential {
+            self.read_ahead.buf.clear();
+        } else if self.read_ahead.sectors > 0
+            && self.read_ahead.get(bin, offset, sector_size).is_none()
+        {
+            // Sequential access past the end of the buffer, refill it
+            let max = self.read_ahead.sectors.saturating_add(1);
+            let count = self.stored_run(msf, max).map(|(_, n)| n).unwrap_or(1);
+
+            let read_ahead = &mut self.read_ahead;
+
+            read_ahead.bin = bin;
+            read_ahead.start = offset;
+            read_ahead.buf.resize(sector_size * count as usize, 0);
+
+            if let Err(e) = self.bin_source.read_exact_from(
+                &mut self.bin_files[bin as usize],
+                offset,
+                &mut read_ahead.buf,
+            ) {
+                read_ahead.buf.clear();
+                return Err(e);
+            }
+        }
+
+        if let Some(data) = self.read_ahead.get(bin, offset, sector_size) {
+            self.decode_bin_sector(ty, file_type, data, sector);
+
+            return Ok(());
+        }
+
+        let mut data = [0; 2448];
+        let data = &mut data[..sector_size];
+
+        self.bin_source
+            .read_exact_from(&mut self.bin_files[bin as usize], offset, data)?;
+
+        self.decode_bin_sector(ty, file_type, data, sector);
+
+        Ok(())
+    }
+
+    /// Same as `read_bin` but applies `self.read_error_policy` if the read fails. `sector` must
+    /// have been reset with the Q data and format of the sector at `msf`.
+    fn read_stored(&mut self, pos: usize, msf: Msf, sector: &mut Sector) -> CdResult<()> {
+        let err = match self.read_bin(pos, msf, sector) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        let q = sector.q().clone();
+        let format = sector.format();
+
+        *sector = match self.read_error_policy {
+            ReadErrorPolicy::Fail => return Err(err),
+            ReadErrorPolicy::Zero => Sector::empty(q, format)?,
+            ReadErrorPolicy::Skip => Sector::uninitialized(q, format)?,
+        };
+
+        sector.set_synthesized();
+
+        Ok(())
+    }
+
+    /// Read `count` consecutive sectors starting at `msf` with a single read of the BIN file and
+    /// append them to `sectors`. `pos` is the position in `self.indices` of the index containing
+    /// `msf`, it must be stored in a BIN file and contain all the sectors. `buf` is used as scratch
+    /// space.
+    ///
+    /// On error some of the sectors of the run may already have been appended to `sectors`.
+    fn read_bin_run(
+        &mut self,
+        pos: usize,
+        msf: Msf,
+        count: u32,
+        buf: &mut Vec<u8>,
+        sectors: &mut Vec<Sector>,
+    ) -> CdResult<()> {
+        let (bin, offset, ty, file_type) = self.bin_location(pos, msf);
+
+        let sector_size = ty.sector_size(self.raw_subchannel) as usize;
+
+        buf.resize(sector_size * count as usize, 0);
+
+        self.bin_source
+            .read_exact_from(&mut self.bin_files[bin as usize], offset, buf)?;
+
+        for (n, data) in buf.chunks_exact(sector_size).enumerate() {
+            let msf = msf.checked_add_sectors(n as u32).unwrap();
+
+            let (_, q, format) = self.locate(msf).unwrap();
+
+            let mut sector = Sector::uninitialized(q, format)?;
+
+            self.decode_bin_sector(ty, file_type, data, &mut sector);
+            self.add_identification_q(pos, msf, &mut sector);
+
+            sectors.push(sector);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of sectors, up to `max`, that can be read from the BIN file in a single
+    /// run starting at `msf`, along with the position of the index containing them in
+    /// `self.indices`. Returns `None` if the sector at `msf` isn't stored in a BIN file.
+    fn stored_run(&self, msf: Msf, max: u32) -> Option<(usize, u32)> {
+        let (pos, _) = self.indices.find_index_for_msf(msf)?;
+
+        if !self.is_stored(pos) {
+            return None;
+        }
+
+        let end = match self.indices.get(pos + 1) {
+            Some(next) => next.msf(),
+            None => self.indices.lead_out(),
+        };
+
+        let len = (end - msf).sector_index();
+
+        Some((pos, cmp::min(cmp::min(len, max), MAX_BULK_SECTORS)))
+    }
+
+    /// Decode the sector `data` read from a BIN file into `sector`. `data` must contain a full
+    /// sector of type `ty`.
+    fn decode_bin_sector(
+        &self,
+        ty: CueTrackType,
+        file_type: CueFileType,
+        data: &[u8],
+        sector: &mut Sector,
+    ) {
+        // Region of the full sector stored in the BIN file. For CD+G we just ignore the subchannel
+        // data following the audio payload.
+        let stored = match ty.layout(self.raw_subchannel) {
+            SectorLayout::Raw | SectorLayout::RawWithSubchannel => 0..2352,
+            SectorLayout::Mode2Headerless => 16..2352,
+            SectorLayout::Payload => 16..2064,
+            SectorLayout::Form2Payload => panic!("Unimplemented CUE track type: {:?}", ty),
+        };
+
+        let partial = stored.len() != 2352;
+
+        if partial {
+            // Make sure that we don't leave the previous contents of the sector in the regions
+            // that are not stored
+            for b in sector.data_2352_mut().iter_mut() {
+                *b = 0;
+            }
+        }
+
+        let stored_len = stored.len();
+
+        sector.data_2352_mut()[stored].copy_from_slice(&data[..stored_len]);
+
+        if ty.layout(self.raw_subchannel) == SectorLayout::RawWithSubchannel {
+            let mut raw = [0; 96];
+
+            raw.copy_from_slice(&data[2352..2448]);
 
-                self.bin_source.read_exact_from(
-                    bin,
-                    SeekFrom::Start(offset),
-                    sector.data_2352_mut(),
-                )?;
+            // Use the stored Q subchannel if it's valid for this track. Otherwise (bad CRC for
+            // instance) we keep the one we generated, the raw data remains available through
+            // `Sector::raw_subchannel`.
+            if let Ok(q) = Q::from_raw_interleaved(raw) {
+                let format = sector.format();
 
-                sector
+                let _ = sector.reset(q, format);
             }
-            Storage::PreGap => {
-                // We don't have data for this track, leave it empty
-                Sector::empty(q, format)?
+
+            sector.set_raw_subchannel(RawSubchannel::new(raw));
+        }
+
+        if partial && self.regenerate {
+            sector.write_headers();
+
+            if ty.layout(self.raw_subchannel) == SectorLayout::Payload {
+                sector.write_edc_ecc();
+            }
+        }
+
+        if file_type == CueFileType::Motorola && sector.format().is_audio() {
+            // Big-endian samples, swap them to get the little-endian samples expected
+            // everywhere else
+            for sample in sector.data_2352_mut().chunks_exact_mut(2) {
+                sample.swap(0, 1);
             }
+        }
+    }
+}
+
+impl Image for Cue {
+    fn image_format(&self) -> String {
+        "CUE".to_string()
+    }
+
+    fn read_sector(&mut self, position: DiscPosition) -> CdResult<Sector> {
+        let msf = match position {
+            DiscPosition::LeadIn(index) => return self.toc.build_toc_sector(index),
+            DiscPosition::Program(msf) => msf,
+        };
+
+        let (pos, q, format) = match self.locate(msf) {
+            Some(l) => l,
+            None => return self.toc.build_lead_out_sector(msf),
         };
 
+        let mut sector = if self.is_stored(pos) {
+            let mut sector = Sector::uninitialized(q, format)?;
+            self.read_stored(pos, msf, &mut sector)?;
+            sector
+        } else {
+            self.generate_sector(pos, msf, q, format)?
+        };
+
+        self.add_identification_q(pos, msf, &mut sector);
+
         Ok(sector)
     }
 
+    fn read_sector_into(&mut self, position: DiscPosition, sector: &mut Sector) -> CdResult<()> {
+        let msf = match position {
+            DiscPosition::Program(msf) => msf,
+            DiscPosition::LeadIn(_) => {
+                *sector = self.read_sector(position)?;
+                return Ok(());
+            }
+        };
+
+        let (pos, q, format) = match self.locate(msf) {
+            Some(l) => l,
+            None => {
+                *sector = self.toc.build_lead_out_sector(msf)?;
+                return Ok(());
+            }
+        };
+
+        if self.is_stored(pos) {
+            sector.reset(q, format)?;
+            self.read_stored(pos, msf, sector)?;
+        } else {
+            *sector = self.generate_sector(pos, msf, q, format)?;
+        }
+
+        self.add_identification_q(pos, msf, sector);
+
+        Ok(())
+    }
+
+    fn read_sectors(&mut self, position: DiscPosition, count: u32) -> CdResult<Vec<Sector>> {
+        // `count` can be arbitrarily large, let the vector grow past the first bulk read
+        let mut sectors = Vec::with_capacity(cmp::min(count, MAX_BULK_SECTORS) as usize);
+        let mut buf = Vec::new();
+
+        while (sectors.len() as u32) < count {
+            let position = position
+                .checked_add_sectors(sectors.len() as u32)
+                .ok_or(CdError::OutOfDiscPosition)?;
+
+            let remaining = count - sectors.len() as u32;
+
+            // Consecutive sectors stored in the same index are read in one go
+            let run = match position {
+                DiscPosition::Program(msf) => self.stored_run(msf, remaining).map(|r| (msf, r)),
+                DiscPosition::LeadIn(_) => None,
+            };
+
+            match run {
+                Some((msf, (pos, n))) => {
+                    let first = sectors.len();
+
+                    let res = self.read_bin_run(pos, msf, n, &mut buf, &mut sectors);
+
+                    if let Err(e) = res {
+                        if self.read_error_policy == ReadErrorPolicy::Fail {
+                            return Err(e);
+                        }
+
+                        // Drop what was decoded before the error and read the run one sector at a
+                        // time to find the bad ones
+                        sectors.truncate(first);
+
+                        for n in 0..n {
+                            let msf = msf.checked_add_sectors(n).unwrap();
+
+                            sectors.push(self.read_sector(DiscPosition::Program(msf))?);
+                        }
+                    }
+                }
+                None => sectors.push(self.read_sector(position)?),
+            }
+        }
+
+        Ok(sectors)
+    }
+
     fn toc(&self) -> &Toc {
         &self.toc
     }
+
+    fn read_subq(&mut self, position: DiscPosition) -> CdResult<Q> {
+        let msf = match position {
+            DiscPosition::LeadIn(msf) => return Ok(self.toc.build_toc_q(msf)),
+            DiscPosition::Program(msf) => msf,
+        };
+
+        match self.locate(msf) {
+            // The Q subchannel might be stored in the BIN file, we have to read the sector
+            Some((pos, _, _)) if self.has_raw_subchannel(pos) => {
+                self.read_sector(position).map(|s| s.q().clone())
+            }
+            Some((_, q, _)) => Ok(q),
+            None => self.toc.build_lead_out_q(msf),
+        }
+    }
+
+    fn track_pregap(&mut self, track: Bcd) -> CdResult<Msf> {
+        let (pos01, index01) = self.indices.find_index01_for_track(track)?;
+
+        let first = self.indices.indices()[..pos01]
+            .iter()
+            .rev()
+            .take_while(|i| i.track() == track)
+            .last()
+            .unwrap_or(index01);
+
+        Ok(index01.msf() - first.msf())
+    }
+
+    fn current_index(&mut self, msf: Msf) -> CdResult<(Bcd, Bcd)> {
+        match self.indices.find_index_for_msf(msf) {
+            Some((_, index)) => Ok((index.track(), index.index())),
+            None => Err(CdError::BadTrack),
+        }
+    }
+
+    /// Returns the value of the `CATALOG` command if there's one, otherwise looks for it in the
+    /// raw subchannel data
+    fn media_catalog_number(&mut self) -> CdResult<Option<String>> {
+        match self.catalog {
+            Some(ref mcn) => Ok(Some(mcn.clone())),
+            None => metadata::media_catalog_number(self),
+        }
+    }
+
+    /// Returns the value of the track's `ISRC` command if there's one, otherwise looks for it in
+    /// the raw subchannel data
+    fn track_isrc(&mut self, track: Bcd) -> CdResult<Option<String>> {
+        match self.isrc(track) {
+            Some(isrc) => Ok(Some(isrc.to_string())),
+            None => metadata::track_isrc(self, track),
+        }
+    }
+}
+
+/// Custom storage for the BIN files referenced by a cue sheet, see `Cue::from_parts`. This can be
+/// used to load images from memory, network storage or custom archive formats.
+pub trait BinProvider: Send {
+    /// Open the BIN file `name`, as found in the `FILE` command of the cue sheet, and return its
+    /// size in bytes. BIN files are opened in the order of the `FILE` commands.
+    fn open(&mut self, name: &str) -> io::Result<u64>;
+
+    /// Fill `buf` with the contents of the BIN file `bin` starting at byte `offset`. `bin` is the
+    /// index of the BIN file in the order of the `open` calls, starting from 0.
+    fn read_exact_at(&mut self, bin: u32, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+}
+
+/// Raw BIN data read ahead of the current position, see `Cue::set_read_ahead`
+#[derive(Default)]
+struct ReadAhead {
+    /// Number of sectors to read ahead, 0 disables read-ahead
+    sectors: u32,
+    /// BIN file containing the buffered data
+    bin: u32,
+    /// Offset of the first byte of `buf` in the BIN file
+    start: u64,
+    /// Buffered BIN data, empty if nothing is buffered
+    buf: Vec<u8>,
+    /// BIN file and offset following the last sector read, used to detect sequential reads
+    next: Option<(u32, u64)>,
+}
+
+impl ReadAhead {
+    /// Returns the `len` bytes at `offset` in BIN file `bin` if they're buffered
+    fn get(&self, bin: u32, offset: u64, len: usize) -> Option<&[u8]> {
+        if bin != self.bin || offset < self.start {
+            return None;
+        }
+
+        let start = (offset - self.start) as usize;
+
+        self.buf.get(start..start + len)
+    }
+}
+
+/// Location of a track's data in the BIN files, returned by `Cue::track_layout`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrackLayout {
+    /// Index of the BIN file in the order of the `FILE` commands of the cue sheet
+    pub bin_index: u32,
+    /// Offset in bytes of the first sector of the track's INDEX 01 in the BIN file
+    pub byte_offset: u64,
+    /// Size of a sector in the BIN file (2048, 2336, 2352 or 2448 bytes)
+    pub sector_size: u16,
+    /// False if the track is not stored in a BIN file, in which case the other fields are 0
+    pub stored: bool,
+}
+
+/// Location of a single sector in the BIN files, returned by `Cue::sector_file_location`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SectorLocation {
+    /// Index of the BIN file in the order of the `FILE` commands of the cue sheet
+    pub bin_index: u32,
+    /// Offset in bytes of the sector in the BIN file
+    pub offset: u64,
+    /// Number of bytes used by the sector in the BIN file (2048, 2336, 2352 or 2448)
+    pub size: u16,
+}
+
+/// Options controlling how cue sheets are parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CueOptions {
+    /// If true, commands (`FILE`, `TRACK`...) and keywords (`BINARY`, `AUDIO`, `MODE1/2352`...)
+    /// are matched ignoring ASCII case. File names are never affected. Defaults to true.
+    pub case_insensitive: bool,
+    /// If true, a partial sector at the end of a BIN file is ignored and reported in
+    /// `Cue::warnings` instead of causing a parsing error. Defaults to false.
+    pub truncate_partial_sectors: bool,
+    /// If true, `MODE2/2336` and `MODE2/2352` tracks are CD-ROM XA tracks (as specified by the
+    /// CDRWIN documentation), otherwise they're plain CD-ROM Mode 2 tracks. Defaults to true.
+    pub mode2_xa: bool,
+    /// If set, the BIN files are not kept open for the lifetime of the `Cue`, at most this many
+    /// are open at the same time and the least recently used one is closed when another one is
+    /// needed. Useful for images with many BIN files. Ignored for images loaded from ZIP files.
+    /// Defaults to `None`.
+    pub max_open_files: Option<usize>,
+    /// If true, every 2352-byte sector in the BIN files is followed by 96 bytes of raw
+    /// interleaved subchannel data (for instance the output of cdrdao's `--read-subchan rw_raw`).
+    /// The subchannel data is attached to the sectors returned by `read_sector` and the Q
+    /// subchannel is taken from it if it's valid. Defaults to false.
+    pub raw_subchannel: bool,
+    /// Maximum number of indices in the image, including the generated pregaps and postgaps.
+    /// Cue sheets describing more are rejected with `CdError::BadImage`. Defaults to
+    /// `CueOptions::DEFAULT_MAX_INDICES`.
+    pub max_indices: usize,
+    /// Maximum size in bytes of a BIN file. Cue sheets referencing bigger files are rejected with
+    /// `CdError::BadImage`. Defaults to `CueOptions::DEFAULT_MAX_BIN_SIZE`.
+    pub max_bin_size: u64,
+    /// If true, BIN files are mapped in memory instead of being read through regular file I/O,
+    /// which makes random sector reads cheaper. Files that can't be mapped silently fall back to
+    /// regular reads. Ignored when `max_open_files` is set and for images loaded from ZIP files.
+    /// Truncating a mapped BIN file while the `Cue` is alive crashes the process with `SIGBUS`
+    /// instead of returning an error. Only available with the `mmap` feature. Defaults to false.
+    #[cfg(feature = "mmap")]
+    pub mmap: bool,
+    /// If true, `INDEX`, `PREGAP` and `POSTGAP` timestamps with out of range seconds or frames
+    /// (for instance `00:00:80`, as found in some hand-edited cue sheets) are accepted and the
+    /// overflow is carried into the next unit (`00:01:05`). A warning is added to
+    /// `Cue::warnings`. Defaults to false.
+    pub lenient_msf: bool,
+}
+
+impl CueOptions {
+    /// Default value of `CueOptions::max_indices`, comfortably above the 99 tracks of 100 indices
+    /// a disc can contain
+    pub const DEFAULT_MAX_INDICES: usize = 16 * 1024;
+
+    /// Default value of `CueOptions::max_bin_size`: 4GiB, about four times the size of a full
+    /// 99 minute disc stored with raw subchannel data
+    pub const DEFAULT_MAX_BIN_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+    /// True if BIN files should be mapped in memory
+    pub(crate) fn use_mmap(&self) -> bool {
+        #[cfg(feature = "mmap")]
+        return self.mmap;
+
+        #[cfg(not(feature = "mmap"))]
+        return false;
+    }
+}
+
+impl Default for CueOptions {
+    fn default() -> CueOptions {
+        CueOptions {
+            case_insensitive: true,
+            truncate_partial_sectors: false,
+            mode2_xa: true,
+            max_open_files: None,
+            raw_subchannel: false,
+            max_indices: CueOptions::DEFAULT_MAX_INDICES,
+            max_bin_size: CueOptions::DEFAULT_MAX_BIN_SIZE,
+            #[cfg(feature = "mmap")]
+            mmap: false,
+            lenient_msf: false,
+        }
+    }
 }
 
 /// Possible types for a CUE track.
@@ -145,14 +1163,20 @@ impl Image for Cue {
 enum CueTrackType {
     /// CD-DA audio track (red book audio)
     Audio,
+    /// Karaoke CD+G: CD-DA audio followed by the 96 bytes of raw subchannel data for each sector
+    Cdg,
     /// CD-ROM Mode1/2048 (only data, no header or ECC/EDC)
     Mode1Data,
     /// CD-ROM Mode1/2352
     Mode1Raw,
+    /// CD-ROM Mode1/2448: full sector followed by the 96 bytes of raw subchannel data
+    Mode1Sub,
     /// CD-ROM XA Mode2/2336 (without the 16byte header)
     Mode2Headerless,
     /// CD-ROM XA Mode2/2352
     Mode2Raw,
+    /// CD-ROM XA Mode2/2448: full sector followed by the 96 bytes of raw subchannel data
+    Mode2Sub,
     /// CD-I Mode2/2336 (without the 16byte header)
     CdIHeaderless,
     /// CD-I Mode2/2352
@@ -160,27 +1184,141 @@ enum CueTrackType {
 }
 
 impl CueTrackType {
-    fn sector_size(self) -> u16 {
+    /// Returns the keyword used in the `TRACK` command for this type
+    fn keyword(self) -> &'static str {
         match self {
-            CueTrackType::Audio => 2352,
-            CueTrackType::Mode1Data => 2048,
-            CueTrackType::Mode1Raw => 2336,
-            CueTrackType::Mode2Headerless => 2336,
-            CueTrackType::Mode2Raw => 2352,
-            CueTrackType::CdIHeaderless => 2336,
-            CueTrackType::CdIRaw => 2352,
+            CueTrackType::Audio => "AUDIO",
+            CueTrackType::Cdg => "CDG",
+            CueTrackType::Mode1Data => "MODE1/2048",
+            CueTrackType::Mode1Raw => "MODE1/2352",
+            CueTrackType::Mode1Sub => "MODE1/2448",
+            CueTrackType::Mode2Headerless => "MODE2/2336",
+            CueTrackType::Mode2Raw => "MODE2/2352",
+            CueTrackType::Mode2Sub => "MODE2/2448",
+            CueTrackType::CdIHeaderless => "CDI/2336",
+            CueTrackType::CdIRaw => "CDI/2352",
         }
     }
+
+    /// Returns the way sectors of this type are stored in the BIN file. `raw_subchannel` is the
+    /// value of `CueOptions::raw_subchannel`.
+    fn layout(self, raw_subchannel: bool) -> SectorLayout {
+        let layout = match self {
+            CueTrackType::Audio => SectorLayout::Raw,
+            CueTrackType::Cdg => SectorLayout::RawWithSubchannel,
+            CueTrackType::Mode1Data => SectorLayout::Payload,
+            CueTrackType::Mode1Raw => SectorLayout::Raw,
+            CueTrackType::Mode1Sub => SectorLayout::RawWithSubchannel,
+            CueTrackType::Mode2Headerless => SectorLayout::Mode2Headerless,
+            CueTrackType::Mode2Raw => SectorLayout::Raw,
+            CueTrackType::Mode2Sub => SectorLayout::RawWithSubchannel,
+            CueTrackType::CdIHeaderless => SectorLayout::Mode2Headerless,
+            CueTrackType::CdIRaw => SectorLayout::Raw,
+        };
+
+        if raw_subchannel && layout == SectorLayout::Raw {
+            SectorLayout::RawWithSubchannel
+        } else {
+            layout
+        }
+    }
+
+    fn sector_size(self, raw_subchannel: bool) -> u16 {
+        self.layout(raw_subchannel).sector_size()
+    }
+}
+
+/// Possible contents for the pregap sectors that are not stored in the BIN files (for instance
+/// the ones introduced by a `PREGAP` command).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PregapFill {
+    /// Empty sectors: zeroes for audio tracks, zeroed payload with valid headers and EDC/ECC
+    /// for CD-ROM tracks. This is the default.
+    Zero,
+    /// All 2352 bytes of the sector are set to zero (digital silence for audio tracks), no
+    /// CD-ROM header or EDC/ECC is generated for data tracks.
+    Silence,
+    /// Mirror the sectors preceding the pregap, so that the pregap contains a copy of the tail of
+    /// the previous track
+    PrevTrackTail,
+    /// Mirror the sectors following the pregap, so that the pregap contains a copy of the head of
+    /// the next track
+    NextTrackHead,
+}
+
+/// What to do when a sector can't be read from its BIN file, see `Cue::set_read_error_policy`
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ReadErrorPolicy {
+    /// Return the error. This is the default.
+    Fail,
+    /// Return an empty sector with valid Q subchannel data for this position: zeroes for audio
+    /// tracks, zeroed payload with valid headers and EDC/ECC for CD-ROM tracks.
+    Zero,
+    /// Return a sector with valid Q subchannel data whose 2352 bytes are set to zero, without
+    /// CD-ROM header or EDC/ECC, so that it can't be mistaken for valid data.
+    Skip,
+}
+
+/// Possible types for a CUE file.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum CueFileType {
+    /// Raw binary data, audio samples are stored little-endian
+    Binary,
+    /// Raw binary data, audio samples are stored big-endian
+    Motorola,
 }
 
 /// Storage for a slice
 enum Storage {
     /// The slice is stored in a portion of a BIN file. Contains the
-    /// index of the BIN file and the offset in the file.
-    Bin(u32, u64, CueTrackType),
+    /// index of the BIN file, the offset in the file, the format of the track and the type of the
+    /// file.
+    Bin(u32, u64, CueTrackType, CueFileType),
     /// The slice is a pre-gap, it's not stored in the BIN file and
-    /// must be regererated.
+    /// must be regererated. The contents depend on `Cue::pregap_fill`.
     PreGap,
+    /// The slice is not stored in the BIN file and is generated with the given contents (for
+    /// instance a POSTGAP)
+    Generated { fill: GeneratedFill },
+}
+
+/// Contents of generated sectors
+enum GeneratedFill {
+    /// Empty sectors: zeroes for audio tracks, zeroed payload with valid headers and EDC/ECC
+    /// for CD-ROM tracks
+    Zero,
+    /// All 2352 bytes of the sector are set to zero, no CD-ROM header or EDC/ECC is generated
+    Silence,
+}
+
+impl GeneratedFill {
+    /// Generate a sector with the given Q subchannel and format
+    fn sector(&self, q: Q, format: TrackFormat) -> CdResult<Sector> {
+        match self {
+            GeneratedFill::Zero => Sector::empty(q, format),
+            GeneratedFill::Silence => Sector::uninitialized(q, format),
+        }
+    }
+}
+
+/// `REM key value` metadata
+struct Rem {
+    /// Track the metadata belongs to, `None` for the metadata placed before the first track
+    track: Option<Bcd>,
+    key: String,
+    value: String,
+}
+
+/// Parse a gain in dB, formatted like `+1.23 dB`
+fn parse_gain(s: &str) -> Option<f32> {
+    let s = s.trim();
+
+    let s = match s.len().checked_sub(2) {
+        Some(l) if s.is_char_boundary(l) && s[l..].eq_ignore_ascii_case("dB") => &s[..l],
+        _ => s,
+    };
+
+    s.trim().parse().ok()
 }
 
 /// Max size for a cue sheet, used to detect bogus input early without attempting to load a huge
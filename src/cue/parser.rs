@@ -1,4 +1,7 @@
-use super::{Cue, CueTrackType, Storage, CUE_SHEET_MAX_LENGTH};
+use super::{
+    BinProvider, Cue, CueFileType, CueOptions, CueTrackType, GeneratedFill, PregapFill, ReadAhead,
+    ReadErrorPolicy, Rem, Storage, CUE_SHEET_MAX_LENGTH,
+};
 use bcd::Bcd;
 use internal::{Index, IndexCache};
 use msf::Msf;
@@ -7,11 +10,12 @@ use std::io;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use subchannel::AdrControl;
-use zip::ZipArchive;
+use subchannel::{self, AdrControl};
+use zip::{CompressionMethod, ZipArchive};
 use CdError;
 use CdResult;
 use TrackFormat;
+use {Progress, ProgressPhase};
 
 pub struct CueParser {
     /// Path to the cue sheet
@@ -26,12 +30,16 @@ pub struct CueParser {
     pos: usize,
     /// Current line in the buffer
     line: u32,
-    /// Current absolute MSF
+    /// Absolute MSF of the position `index_msf` in the current BIN file
     msf: Msf,
     /// List of BIN files
     bin_files: Vec<BinaryBlob>,
+    /// Names of the BIN files, as found in the cue sheet
+    bin_names: Vec<String>,
     /// Length of the current BIN file in bytes
     bin_len: u64,
+    /// Type of the current BIN file
+    file_type: CueFileType,
     /// Bytes consumed from the current BIN file
     consumed_bytes: u64,
     /// MSF of the last generated index into the file (00:00:00 is the
@@ -41,15 +49,79 @@ pub struct CueParser {
     index_type: Option<CueTrackType>,
     /// Current Track
     track: Option<(Bcd, CueTrackType, TrackFormat, AdrControl)>,
+    /// Length of the PREGAP of the current track, inserted before its first INDEX
+    pregap: Option<Msf>,
+    /// Length of the POSTGAP of the previous track along with the track number, index number,
+    /// format and control of its last index. Inserted after the end of the track's data.
+    postgap: Option<(Msf, Bcd, Bcd, TrackFormat, AdrControl)>,
+    /// Current session, set by `REM SESSION`. 0 if the cue sheet doesn't specify sessions.
+    session: u8,
     /// Indices
     indices: Vec<Index<Storage>>,
+    /// `REM` metadata
+    rem: Vec<Rem>,
+    /// Media catalog number, set by `CATALOG`
+    catalog: Option<String>,
+    /// ISRC of the tracks, set by `ISRC`
+    isrc: Vec<(Bcd, String)>,
+    /// Parsing options
+    options: CueOptions,
+    /// Non-fatal problems found while parsing
+    warnings: Vec<CdError>,
+    /// If set, the parser doesn't stop at the first error and collects them here instead, see
+    /// `CueParser::validate`
+    errors: Option<Vec<CdError>>,
 }
 
 impl CueParser {
     /// Parse a CUE sheet, open the BIN files and generate the CD
     /// structure
-    pub fn build_cue<P: AsRef<Path>>(cue_path: P) -> CdResult<Cue> {
-        let cue_path = cue_path.as_ref();
+    pub fn build_cue<P: AsRef<Path>>(
+        cue_path: P,
+        options: CueOptions,
+        progress: Option<&mut dyn FnMut(Progress)>,
+    ) -> CdResult<Cue> {
+        let (cue_path, bin_source, cue_sheet) = CueParser::open_fs(cue_path.as_ref(), &options)?;
+
+        CueParser::do_parse(cue_path, bin_source, &cue_sheet, options, progress)
+    }
+
+    /// Parse the cue sheet at `cue_path` without stopping at the first error and return all the
+    /// errors found. The layout of the indices is only validated if no other error was found.
+    pub fn validate<P: AsRef<Path>>(cue_path: P, options: CueOptions) -> Vec<CdError> {
+        let (cue_path, bin_source, cue_sheet) =
+            match CueParser::open_fs(cue_path.as_ref(), &options) {
+                Ok(r) => r,
+                Err(e) => return vec![e],
+            };
+
+        let mut parser = CueParser::new(cue_path, bin_source, options);
+
+        parser.errors = Some(Vec::new());
+
+        let res = parser.parse(&cue_sheet, None);
+
+        let mut errors = parser.errors.take().unwrap_or_default();
+
+        if let Err(e) = res {
+            errors.push(e);
+        }
+
+        if errors.is_empty() {
+            let res = IndexCache::new(parser.cue_path, parser.indices, parser.msf)
+                .and_then(|indices| indices.toc());
+
+            if let Err(e) = res {
+                errors.push(e);
+            }
+        }
+
+        errors
+    }
+
+    /// Read the cue sheet at `cue_path` and return its path, the source for the BIN files
+    /// (located in the same directory) and its contents
+    fn open_fs(cue_path: &Path, options: &CueOptions) -> CdResult<(PathBuf, BinSource, Vec<u8>)> {
         let cue_sheet = match read_file(cue_path, CUE_SHEET_MAX_LENGTH) {
             Ok(c) => c,
             Err(e) => return Err(CdError::IoError(e)),
@@ -62,30 +134,55 @@ impl CueParser {
             None => cue_path.clone(),
         };
 
-        let bin_source = BinSource::Fs(dir);
+        let bin_source = BinSource::Fs {
+            dir,
+            pool: options.max_open_files.map(FilePool::new),
+        };
 
-        CueParser::do_parse(cue_path, bin_source, &cue_sheet)
+        Ok((cue_path, bin_source, cue_sheet))
     }
 
-    fn do_parse(cue_path: PathBuf, bin_source: BinSource, cue_sheet: &[u8]) -> CdResult<Cue> {
-        let mut parser = CueParser {
+    fn new(cue_path: PathBuf, bin_source: BinSource, options: CueOptions) -> CueParser {
+        CueParser {
             cue_path,
             bin_source,
             pos: 0,
             line: 0,
-            // CUE always skips track 01's pregap (and assumes it's 2
-            // seconds long) so we start at index 01.
+            // CUE usually skips track 01's pregap (and assumes it's 2
+            // seconds long) so we start at index 01. If the pregap is
+            // explicitly stored this is reset in `command_index`.
             msf: Msf::from_sector_index(150).unwrap(),
             bin_files: Vec::new(),
+            bin_names: Vec::new(),
             bin_len: 0,
+            file_type: CueFileType::Binary,
             consumed_bytes: 0,
             index_type: None,
             index_msf: Msf::ZERO,
             track: None,
+            pregap: None,
+            postgap: None,
+            session: 0,
             indices: Vec::new(),
-        };
+            rem: Vec::new(),
+            catalog: None,
+            isrc: Vec::new(),
+            options,
+            warnings: Vec::new(),
+            errors: None,
+        }
+    }
+
+    fn do_parse(
+        cue_path: PathBuf,
+        bin_source: BinSource,
+        cue_sheet: &[u8],
+        options: CueOptions,
+        progress: Option<&mut dyn FnMut(Progress)>,
+    ) -> CdResult<Cue> {
+        let mut parser = CueParser::new(cue_path, bin_source, options);
 
-        parser.parse(cue_sheet)?;
+        parser.parse(cue_sheet, progress)?;
 
         let indices = IndexCache::new(parser.cue_path, parser.indices, parser.msf)?;
         let toc = indices.toc()?;
@@ -94,11 +191,38 @@ impl CueParser {
             indices,
             bin_source: parser.bin_source,
             bin_files: parser.bin_files,
+            bin_names: parser.bin_names,
             toc,
+            pregap_fill: PregapFill::Zero,
+            regenerate: true,
+            raw_subchannel: parser.options.raw_subchannel,
+            rem: parser.rem,
+            catalog: parser.catalog,
+            isrc: parser.isrc,
+            read_ahead: ReadAhead::default(),
+            read_error_policy: ReadErrorPolicy::Fail,
+            warnings: parser.warnings,
         })
     }
 
-    pub fn build_cue_from_zip<P: AsRef<Path>>(zip_path: P) -> CdResult<Cue> {
+    pub fn build_cue_from_parts(
+        cue_sheet: &[u8],
+        bin_provider: Box<dyn BinProvider>,
+        options: CueOptions,
+    ) -> CdResult<Cue> {
+        if cue_sheet.len() as u64 > CUE_SHEET_MAX_LENGTH {
+            return Err(CdError::BadImage {
+                path: PathBuf::new(),
+                desc: "CUE sheet is too big".to_string(),
+            });
+        }
+
+        let bin_source = BinSource::Custom(bin_provider);
+
+        CueParser::do_parse(PathBuf::new(), bin_source, cue_sheet, options, None)
+    }
+
+    pub fn build_cue_from_zip<P: AsRef<Path>>(zip_path: P, options: CueOptions) -> CdResult<Cue> {
         let zip_path = zip_path.as_ref();
         let archive = File::open(zip_path)?;
         let mut zip = ZipArchive::new(archive)?;
@@ -139,12 +263,11 @@ impl CueParser {
 
             drop(f);
 
-            let bin_source = BinSource::Zip {
-                zip,
-                path: zip_path.to_path_buf(),
-            };
+            let file = File::open(zip_path)?;
 
-            return CueParser::do_parse(cue_path, bin_source, &cue_sheet);
+            let bin_source = BinSource::Zip { zip, file };
+
+            return CueParser::do_parse(cue_path, bin_source, &cue_sheet, options, None);
         }
 
         Err(CdError::BadImage {
@@ -165,71 +288,296 @@ impl CueParser {
         self.error(msg.to_string())
     }
 
-    fn parse(&mut self, cue_sheet: &[u8]) -> CdResult<()> {
+    fn parse(
+        &mut self,
+        cue_sheet: &[u8],
+        mut progress: Option<&mut dyn FnMut(Progress)>,
+    ) -> CdResult<()> {
+        // Some editors add a UTF-8 byte order mark at the start of the file
+        let cue_sheet = cue_sheet.strip_prefix(b"\xef\xbb\xbf").unwrap_or(cue_sheet);
+
+        let total_lines = if progress.is_some() {
+            let mut lines = 0;
+            let mut pos = 0;
+
+            while let Some((new_pos, _)) = next_line(cue_sheet, pos) {
+                pos = new_pos;
+                lines += 1;
+            }
+
+            lines
+        } else {
+            0
+        };
+
         while let Some((new_pos, buf)) = next_line(cue_sheet, self.pos) {
             self.pos = new_pos;
             self.line += 1;
 
-            let params = self.split(buf)?;
+            if let Some(ref mut progress) = progress {
+                progress(Progress {
+                    phase: ProgressPhase::Parsing,
+                    current: self.line,
+                    total: total_lines,
+                });
+            }
 
-            if params.is_empty() {
-                // Empty line
-                continue;
+            let res = self.parse_line(buf);
+            self.check(res)?;
+        }
+
+        let res = self.finalize_bin();
+        self.check(res)?;
+        let res = self.flush_postgap();
+        self.check(res)?;
+
+        Ok(())
+    }
+
+    /// If errors are being collected (see `CueParser::validate`) store `res`'s error and return
+    /// `Ok(())`, otherwise return `res` unchanged
+    fn check(&mut self, res: CdResult<()>) -> CdResult<()> {
+        match (res, self.errors.as_mut()) {
+            (Err(e), Some(errors)) => {
+                errors.push(e);
+                Ok(())
             }
+            (res, _) => res,
+        }
+    }
 
-            let command = params[0];
-
-            type Callback = fn(&mut CueParser, &[&[u8]]) -> CdResult<()>;
-
-            let handlers: [(&'static [u8], Callback, Option<u32>); 6] = [
-                (b"REM", CueParser::command_rem, None),
-                (b"FILE", CueParser::command_file, Some(2)),
-                (b"TRACK", CueParser::command_track, Some(2)),
-                (b"PREGAP", CueParser::command_pregap, Some(1)),
-                (b"INDEX", CueParser::command_index, Some(2)),
-                (b"FLAGS", CueParser::command_flags, None),
-            ];
-
-            let callback = handlers.iter().find(|&&(name, _, _)| name == command);
-
-            match callback {
-                Some(&(_, c, nparams)) => {
-                    if let Some(nparams) = nparams {
-                        if params.len() - 1 != nparams as usize {
-                            let command = String::from_utf8_lossy(command);
-
-                            let error = format!(
-                                "Wrong number of parameters \
-                                 for command {}: expected \
-                                 {} got {}",
-                                command,
-                                nparams,
-                                params.len() - 1
-                            );
-
-                            return Err(self.error(error));
-                        }
-                    }
+    /// Parse a single line of the cue sheet
+    fn parse_line(&mut self, buf: &[u8]) -> CdResult<()> {
+        let params = self.split(buf)?;
 
-                    c(self, &params)?;
-                }
-                None => {
-                    let command = String::from_utf8_lossy(command);
+        if params.is_empty() {
+            // Empty line
+            return Ok(());
+        }
+
+        let command = params[0];
+
+        let params = if self.is_keyword(command, b"FILE") {
+            self.join_unquoted_file_name(buf, params)
+        } else {
+            params
+        };
 
-                    let error = format!("Unexpected command \"{}\"", command);
-                    return Err(self.error(error));
+        type Callback = fn(&mut CueParser, &[&[u8]]) -> CdResult<()>;
+
+        let handlers: [(&'static [u8], Callback, Option<u32>); 9] = [
+            (b"REM", CueParser::command_rem, None),
+            (b"CATALOG", CueParser::command_catalog, Some(1)),
+            (b"ISRC", CueParser::command_isrc, Some(1)),
+            (b"FILE", CueParser::command_file, Some(2)),
+            (b"TRACK", CueParser::command_track, Some(2)),
+            (b"PREGAP", CueParser::command_pregap, Some(1)),
+            (b"INDEX", CueParser::command_index, Some(2)),
+            (b"POSTGAP", CueParser::command_postgap, Some(1)),
+            (b"FLAGS", CueParser::command_flags, None),
+        ];
+
+        let callback = handlers
+            .iter()
+            .find(|&&(name, _, _)| self.is_keyword(command, name));
+
+        match callback {
+            Some(&(_, c, nparams)) => {
+                if let Some(nparams) = nparams {
+                    if params.len() - 1 != nparams as usize {
+                        let command = String::from_utf8_lossy(command);
+
+                        let error = format!(
+                            "Wrong number of parameters \
+                             for command {}: expected \
+                             {} got {}",
+                            command,
+                            nparams,
+                            params.len() - 1
+                        );
+
+                        return Err(self.error(error));
+                    }
                 }
+
+                c(self, &params)
+            }
+            None => {
+                let command = String::from_utf8_lossy(command);
+
+                let error = format!("Unexpected command \"{}\"", command);
+                Err(self.error(error))
             }
         }
+    }
 
-        self.finalize_bin()?;
+    /// Some cue sheets don't quote `FILE` names containing spaces, which we'd see as too many
+    /// parameters. If the name isn't quoted and the last parameter is a file type, merge all the
+    /// words in between (along with the whitespace separating them) into a single name.
+    fn join_unquoted_file_name<'a>(
+        &mut self,
+        line: &'a [u8],
+        params: Vec<&'a [u8]>,
+    ) -> Vec<&'a [u8]> {
+        let n = params.len();
 
-        Ok(())
+        if n <= 3 || params[1][0] == b'"' {
+            return params;
+        }
+
+        let known = [&b"BINARY"[..], b"MOTOROLA", b"AIFF", b"WAVE", b"MP3"];
+
+        if !known.iter().any(|&k| self.is_keyword(params[n - 1], k)) {
+            return params;
+        }
+
+        // All the words are slices of `line`
+        let offset = |word: &[u8]| word.as_ptr() as usize - line.as_ptr() as usize;
+
+        let start = offset(params[1]);
+        let end = offset(params[n - 2]) + params[n - 2].len();
+
+        let warning = self.error_str("Unquoted FILE name containing spaces");
+        self.warnings.push(warning);
+
+        vec![params[0], &line[start..end], params[n - 1]]
+    }
+
+    /// Parse an `mm:ss:ff` MSF. If `CueOptions::lenient_msf` is set, out of range frames and
+    /// seconds are carried into the next unit (`00:00:80` becomes `00:01:05`) with a warning.
+    fn parse_msf(&mut self, b: &[u8]) -> Result<Msf, ()> {
+        if let Ok(msf) = from_buf(b) {
+            return Ok(msf);
+        }
+
+        if !self.options.lenient_msf {
+            return Err(());
+        }
+
+        let mut fields = [0u32; 3];
+        let mut count = 0;
+
+        for (i, f) in b.split(|&c| c == b':').enumerate() {
+            if i >= 3 || f.is_empty() || f.len() > 3 || !f.iter().all(u8::is_ascii_digit) {
+                return Err(());
+            }
+
+            fields[i] = from_buf(f)?;
+            count += 1;
+        }
+
+        if count != 3 {
+            return Err(());
+        }
+
+        let [m, s, f] = fields;
+
+        let msf = Msf::from_sector_index((m * 60 + s) * 75 + f).ok_or(())?;
+
+        let warning = self.error(format!(
+            "Out of range MSF {} normalized to {}",
+            String::from_utf8_lossy(b),
+            msf
+        ));
+        self.warnings.push(warning);
+
+        Ok(msf)
     }
 
     /// REM comment
-    fn command_rem(&mut self, _: &[&[u8]]) -> CdResult<()> {
-        // REM is used for comments, we can ignore this line
+    fn command_rem(&mut self, params: &[&[u8]]) -> CdResult<()> {
+        // REM is used for comments, however some tools use `REM SESSION nn` to describe
+        // multi-session discs
+        if params.len() == 3 && self.is_keyword(params[1], b"SESSION") {
+            return self.command_session(params[2]);
+        }
+
+        // Other tools use `REM KEY value` to store metadata (`REM GENRE`, `REM DATE`,
+        // `REM REPLAYGAIN_TRACK_GAIN`...). Anything else is a plain comment that we can ignore.
+        if params.len() < 3 {
+            return Ok(());
+        }
+
+        let mut key = String::from_utf8_lossy(params[1]).into_owned();
+
+        if self.options.case_insensitive {
+            key.make_ascii_uppercase();
+        }
+
+        let value = params[2..]
+            .iter()
+            .map(|p| String::from_utf8_lossy(p.strip_prefix(b"\"").unwrap_or(p)))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        self.rem.push(Rem {
+            track: self.track.map(|(n, _, _, _)| n),
+            key,
+            value,
+        });
+
+        Ok(())
+    }
+
+    /// REM SESSION nn
+    ///
+    /// The following tracks belong to session `nn`. We don't generate the lead-out and lead-in
+    /// areas between the sessions, the tracks of all the sessions are contiguous.
+    fn command_session(&mut self, session: &[u8]) -> CdResult<()> {
+        let session: u8 = match from_buf(session) {
+            Ok(s) => s,
+            Err(_) => return Err(self.error_str("Invalid session number")),
+        };
+
+        if session == 0 || session <= self.session {
+            return Err(self.error_str("Sessions must be numbered in increasing order from 1"));
+        }
+
+        if self.track.is_some() && self.session == 0 {
+            return Err(self.error_str("Tracks placed before the first session"));
+        }
+
+        self.session = session;
+
+        Ok(())
+    }
+
+    /// CATALOG nnnnnnnnnnnnn
+    fn command_catalog(&mut self, params: &[&[u8]]) -> CdResult<()> {
+        if self.catalog.is_some() {
+            return Err(self.error_str("Multiple CATALOG commands"));
+        }
+
+        let mcn = String::from_utf8_lossy(params[1]).into_owned();
+
+        if !subchannel::is_valid_mcn(&mcn) {
+            return Err(self.error_str("Invalid media catalog number"));
+        }
+
+        self.catalog = Some(mcn);
+
+        Ok(())
+    }
+
+    /// ISRC CCOOOYYSSSSS
+    fn command_isrc(&mut self, params: &[&[u8]]) -> CdResult<()> {
+        let track = match self.track {
+            Some((n, _, _, _)) => n,
+            None => return Err(self.error_str("Track-less ISRC")),
+        };
+
+        if self.isrc.iter().any(|&(t, _)| t == track) {
+            return Err(self.error_str("Multiple ISRC for the same track"));
+        }
+
+        let isrc = String::from_utf8_lossy(params[1]).into_owned();
+
+        if !subchannel::is_valid_isrc(&isrc) {
+            return Err(self.error_str("Invalid ISRC"));
+        }
+
+        self.isrc.push((track, isrc));
+
         Ok(())
     }
 
@@ -246,25 +594,86 @@ impl CueParser {
             bin_name = &bin_name[1..];
         }
 
-        if bin_type != b"BINARY" {
-            let ty = String::from_utf8_lossy(bin_type);
+        let file_types: [(&[u8], CueFileType); 2] = [
+            (b"BINARY", CueFileType::Binary),
+            (b"MOTOROLA", CueFileType::Motorola),
+        ];
 
-            let error = format!("Unsupported file type \"{}\"", ty);
+        let file_type = match self.match_keyword(bin_type, &file_types) {
+            Some(t) => t,
+            None => {
+                let ty = String::from_utf8_lossy(bin_type);
 
-            return Err(self.error(error));
-        }
+                let error = format!("Unsupported file type \"{}\"", ty);
+
+                return Err(self.error(error));
+            }
+        };
 
         // A new binary blob is introduced
-        let (blob, size) = match self.bin_source {
-            BinSource::Fs(ref root) => {
+        let r = match self.bin_source {
+            BinSource::Fs {
+                ref dir,
+                pool: None,
+            } => {
                 // Open the new BIN blob
-                BinaryBlob::from_file(root.clone(), bin_name)
+                BinaryBlob::from_file(dir.clone(), bin_name, self.options.use_mmap())
             }
+            BinSource::Fs {
+                ref dir,
+                pool: Some(_),
+            } => BinaryBlob::from_path(dir.clone(), bin_name, self.bin_files.len() as u32),
             BinSource::Zip { ref mut zip, .. } => BinaryBlob::from_zip_file(zip, bin_name),
-        }?;
+            BinSource::Custom(ref mut provider) => {
+                let bin = self.bin_files.len() as u32;
+
+                provider
+                    .open(&String::from_utf8_lossy(bin_name))
+                    .map(|size| (BinaryBlob::Custom(bin), size))
+            }
+        };
+
+        // Add some context to I/O errors, otherwise it's hard to tell which BIN file is at fault
+        let (blob, size) = match r {
+            Ok(r) => r,
+            Err(e) => {
+                let error = format!(
+                    "Can't open BIN file \"{}\": {}",
+                    String::from_utf8_lossy(bin_name),
+                    e
+                );
+
+                let error = self.error(error);
+
+                match self.errors {
+                    // Keep validating the rest of the file with a placeholder of unknown size,
+                    // otherwise the following TRACK and INDEX commands would all fail
+                    Some(ref mut errors) => {
+                        errors.push(error);
+                        (BinaryBlob::Missing, u64::MAX)
+                    }
+                    None => return Err(error),
+                }
+            }
+        };
+
+        if size > self.options.max_bin_size && !matches!(blob, BinaryBlob::Missing) {
+            return Err(CdError::BadImage {
+                path: self.cue_path.clone(),
+                desc: format!(
+                    "BIN file \"{}\" is too big ({} bytes, the maximum is {})",
+                    String::from_utf8_lossy(bin_name),
+                    size,
+                    self.options.max_bin_size
+                ),
+            });
+        }
 
         self.bin_files.push(blob);
+        self.bin_names
+            .push(String::from_utf8_lossy(bin_name).into_owned());
         self.bin_len = size;
+        self.file_type = file_type;
         self.consumed_bytes = 0;
         self.index_msf = Msf::ZERO;
         self.index_type = None;
@@ -283,26 +692,41 @@ impl CueParser {
             Err(_) => return Err(self.error_str("Invalid track number")),
         };
 
-        let t = match params[2] {
-            b"AUDIO" => CueTrackType::Audio,
-            b"CDG" => return Err(self.error_str("Unsupported CDG track format")),
-            b"MODE1/2048" => CueTrackType::Mode1Data,
-            b"MODE1/2352" => CueTrackType::Mode1Raw,
-            b"MODE2/2336" => CueTrackType::Mode2Headerless,
-            b"MODE2/2352" => CueTrackType::Mode2Raw,
-            b"CDI/2336" => CueTrackType::CdIHeaderless,
-            b"CDI/2352" => CueTrackType::CdIRaw,
-            _ => return Err(self.error_str("Unsupported track type")),
+        let track_types: [(&[u8], CueTrackType); 10] = [
+            (b"AUDIO", CueTrackType::Audio),
+            (b"CDG", CueTrackType::Cdg),
+            (b"MODE1/2048", CueTrackType::Mode1Data),
+            (b"MODE1/2352", CueTrackType::Mode1Raw),
+            (b"MODE1/2448", CueTrackType::Mode1Sub),
+            (b"MODE2/2336", CueTrackType::Mode2Headerless),
+            (b"MODE2/2352", CueTrackType::Mode2Raw),
+            (b"MODE2/2448", CueTrackType::Mode2Sub),
+            (b"CDI/2336", CueTrackType::CdIHeaderless),
+            (b"CDI/2352", CueTrackType::CdIRaw),
+        ];
+
+        let t = match self.match_keyword(params[2], &track_types) {
+            Some(t) => t,
+            None => return Err(self.error_str("Unsupported track type")),
         };
 
         // According to the cdrwin docs the Mode2 formats are specifically for CD-ROM XA and never
-        // CD-ROM Mode 2
+        // CD-ROM Mode 2, but some tools use them for plain Mode 2 tracks
+        let mode2 = if self.options.mode2_xa {
+            TrackFormat::Mode2Xa
+        } else {
+            TrackFormat::Mode2
+        };
+
         let f = match t {
             CueTrackType::Audio => TrackFormat::Audio,
+            CueTrackType::Cdg => TrackFormat::Audio,
             CueTrackType::Mode1Data => TrackFormat::Mode1,
             CueTrackType::Mode1Raw => TrackFormat::Mode1,
-            CueTrackType::Mode2Headerless => TrackFormat::Mode2Xa,
-            CueTrackType::Mode2Raw => TrackFormat::Mode2Xa,
+            CueTrackType::Mode1Sub => TrackFormat::Mode1,
+            CueTrackType::Mode2Headerless => mode2,
+            CueTrackType::Mode2Raw => mode2,
+            CueTrackType::Mode2Sub => mode2,
             CueTrackType::CdIHeaderless => TrackFormat::Mode2CdI,
             CueTrackType::CdIRaw => TrackFormat::Mode2CdI,
         };
@@ -322,33 +746,18 @@ impl CueParser {
     ///
     /// There can be only one PREGAP per track and it must appear before any INDEX
     fn command_pregap(&mut self, params: &[&[u8]]) -> CdResult<()> {
-        let (track_number, _track_type, track_format, ctrl) = match self.track {
-            Some(t) => t,
-            None => return Err(self.error_str("Track-less pregap")),
-        };
+        if self.track.is_none() {
+            return Err(self.error_str("Track-less pregap"));
+        }
 
-        let msf = match from_buf(params[1]) {
+        let msf = match self.parse_msf(params[1]) {
             Ok(b) => b,
             Err(_) => return Err(self.error_str("Invalid index MSF")),
         };
 
-        self.consume_bin_sectors(self.msf)?;
-
-        let pregap = Index::new(
-            Bcd::ZERO,
-            self.msf,
-            track_number,
-            track_format,
-            0,
-            ctrl,
-            Storage::PreGap,
-        );
-        self.indices.push(pregap);
-
-        self.msf += msf;
-        // Since the pregap is not stored in the file we need to advance the index_msf to avoid
-        // skipping some of the data
-        self.index_msf += msf;
+        // The pregap is not stored in the file, it's inserted right before the first index of the
+        // track (which we don't know the position of yet)
+        self.pregap = Some(msf);
 
         Ok(())
     }
@@ -365,29 +774,99 @@ impl CueParser {
             Err(_) => return Err(self.error_str("Invalid index")),
         };
 
-        let msf = match from_buf(params[2]) {
+        let msf = match self.parse_msf(params[2]) {
             Ok(b) => b,
             Err(_) => return Err(self.error_str("Invalid index MSF")),
         };
 
-        if track_number.binary() == 1 {
-            // CUE always ignores track 1's pregap, let's add it in here
+        if self.indices.is_empty() {
+            if n == Bcd::ZERO {
+                // Track 1's pregap is explicitly stored in the BIN file, so the disc starts
+                // directly with it. We know that this index is at the very beginning of the file
+                // since `consume_bin_sectors` rejects anything else for the first index.
+                self.msf = Msf::ZERO;
+            } else if msf == Msf::from_sector_index(150).unwrap() && self.pregap.is_none() {
+                // The BIN file contains exactly 2 seconds of data before track 1's first index,
+                // that's track 1's pregap (sometimes used to hide data or audio). Treat it as if
+                // there was an explicit `INDEX 00 00:00:00` so that it can be read back.
+                let warning = self.error_str("Track 1 pregap is stored in the BIN file");
+                self.warnings.push(warning);
+
+                let bin_index = (self.bin_files.len() - 1) as u32;
+
+                let pregap = Index::new(
+                    Bcd::ZERO,
+                    Msf::ZERO,
+                    track_number,
+                    track_format,
+                    self.session,
+                    ctrl,
+                    Storage::Bin(bin_index, 0, track_type, self.file_type),
+                );
+
+                self.indices.push(pregap);
+                self.index_type = Some(track_type);
+                self.msf = Msf::ZERO;
+            } else if msf != Msf::ZERO {
+                // Any other amount of data can't be a standard pregap and we have no way to tell
+                // where it's supposed to go
+                return Err(self.error_str("File doesn't start at 00:00:00"));
+            } else {
+                // CUE usually ignores track 1's pregap, let's add the standard 2 second pregap
+                // here
+                let pregap = Index::new(
+                    Bcd::ZERO,
+                    Msf::ZERO,
+                    track_number,
+                    track_format,
+                    self.session,
+                    ctrl,
+                    Storage::PreGap,
+                );
+
+                self.indices.push(pregap);
+            }
+        }
+
+        if msf < self.index_msf {
+            return Err(self.error_str("Index placed before the previous one in the file"));
+        }
+
+        let delta = msf - self.index_msf;
+
+        self.consume_bin_sectors(track_number, n, msf)?;
+
+        self.msf = match self.msf.checked_add(delta) {
+            Some(m) => m,
+            None => return Err(self.error_str("Index is too far, MSF overflow")),
+        };
+
+        self.flush_postgap()?;
+
+        if let Some(prev) = self.indices.last() {
+            if prev.track() != track_number && prev.msf() == self.msf {
+                let warning = self.error(format!("Track {} is empty", prev.track()));
+                self.warnings.push(warning);
+            }
+        }
+
+        if let Some(len) = self.pregap.take() {
             let pregap = Index::new(
                 Bcd::ZERO,
-                Msf::ZERO,
+                self.msf,
                 track_number,
                 track_format,
-                0,
+                self.session,
                 ctrl,
                 Storage::PreGap,
             );
-
             self.indices.push(pregap);
-        }
-
-        self.consume_bin_sectors(msf)?;
 
-        self.msf += msf;
+            self.msf = match self.msf.checked_add(len) {
+                Some(m) => m,
+                None => return Err(self.error_str("Pregap is too long, MSF overflow")),
+            };
+        }
 
         // Should be validated in `command_track`
         assert!(!self.bin_files.is_empty());
@@ -399,21 +878,96 @@ impl CueParser {
             self.msf,
             track_number,
             track_format,
-            0,
+            self.session,
             ctrl,
-            Storage::Bin(bin_index, self.consumed_bytes, track_type),
+            Storage::Bin(bin_index, self.consumed_bytes, track_type, self.file_type),
         );
 
         self.indices.push(index);
         self.index_type = Some(track_type);
 
+        if self.indices.len() > self.options.max_indices {
+            return Err(CdError::BadImage {
+                path: self.cue_path.clone(),
+                desc: format!(
+                    "Too many indices at line {}, the maximum is {}",
+                    self.line, self.options.max_indices
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// POSTGAP mm:ss:ff
+    ///
+    /// There can be only one POSTGAP per track and it must appear after all the track's INDEX
+    fn command_postgap(&mut self, params: &[&[u8]]) -> CdResult<()> {
+        let (track_number, _, _, _) = match self.track {
+            Some(t) => t,
+            None => return Err(self.error_str("Track-less postgap")),
+        };
+
+        let msf = match self.parse_msf(params[1]) {
+            Ok(b) => b,
+            Err(_) => return Err(self.error_str("Invalid postgap MSF")),
+        };
+
+        if self.postgap.is_some() {
+            return Err(self.error_str("Multiple postgaps for the same track"));
+        }
+
+        let postgap = match self.indices.last() {
+            Some(i) if i.track() == track_number => {
+                (msf, i.track(), i.index(), i.format(), i.control())
+            }
+            _ => return Err(self.error_str("Postgap placed before the track's indices")),
+        };
+
+        if msf != Msf::ZERO {
+            // The postgap is not stored in the file, it's inserted after the end of the track's
+            // data (which we don't know the position of yet)
+            self.postgap = Some(postgap);
+        }
+
+        Ok(())
+    }
+
+    /// Insert the pending postgap (if any) at the current position
+    fn flush_postgap(&mut self) -> CdResult<()> {
+        let (len, track, index, format, ctrl) = match self.postgap.take() {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        let session = self.indices.last().map(|i| i.session()).unwrap_or(0);
+
+        // The postgap continues the last index of the track
+        let postgap = Index::new(
+            index,
+            self.msf,
+            track,
+            format,
+            session,
+            ctrl,
+            Storage::Generated {
+                fill: GeneratedFill::Zero,
+            },
+        );
+        self.indices.push(postgap);
+
+        self.msf = match self.msf.checked_add(len) {
+            Some(m) => m,
+            None => return Err(self.error_str("Postgap is too long, MSF overflow")),
+        };
+
         Ok(())
     }
 
     /// FLAGS flag [flag [...]]
     fn command_flags(&mut self, params: &[&[u8]]) -> CdResult<()> {
-        let (_track_number, _track_type, _track_format, ref mut ctrl) = match self.track {
-            Some(t) => t,
+        let mut ctrl = match self.track {
+            Some((_, _, _, ctrl)) => ctrl,
             None => return Err(self.error_str("Track-less flag")),
         };
 
@@ -422,17 +976,42 @@ impl CueParser {
         }
 
         for &flag in params.iter().skip(1) {
-            match flag {
-                b"DCP" => ctrl.set_digital_copy_permited(true),
-                b"4CH" => ctrl.set_four_channel_audio(true),
-                b"PRE" => ctrl.set_pre_emphasis(true),
-                _ => return Err(self.error_str("Unknown flag")),
+            if self.is_keyword(flag, b"DCP") {
+                ctrl.set_digital_copy_permited(true);
+            } else if self.is_keyword(flag, b"4CH") {
+                ctrl.set_four_channel_audio(true);
+            } else if self.is_keyword(flag, b"PRE") {
+                ctrl.set_pre_emphasis(true);
+            } else {
+                return Err(self.error_str("Unknown flag"));
             }
         }
 
+        if let Some((_, _, _, ref mut c)) = self.track {
+            *c = ctrl;
+        }
+
         Ok(())
     }
 
+    /// Returns true if `token` is the command or keyword `keyword`, ignoring ASCII case if the
+    /// parser is case-insensitive
+    fn is_keyword(&self, token: &[u8], keyword: &[u8]) -> bool {
+        if self.options.case_insensitive {
+            token.eq_ignore_ascii_case(keyword)
+        } else {
+            token == keyword
+        }
+    }
+
+    /// Look for `token` in a table of keywords, see `CueParser::is_keyword`
+    fn match_keyword<T: Copy>(&self, token: &[u8], table: &[(&[u8], T)]) -> Option<T> {
+        table
+            .iter()
+            .find(|&&(keyword, _)| self.is_keyword(token, keyword))
+            .map(|&(_, v)| v)
+    }
+
     /// Split the buffer into individual words. Handles quoted strings
     /// and treats them as a single word but returns them with the
     /// first quote included (to detect elements that shouldn't be
@@ -485,8 +1064,8 @@ impl CueParser {
     }
 
     /// Advance in the current BIN file, updating how many bytes are
-    /// left to consume.
-    fn consume_bin_sectors(&mut self, offset: Msf) -> CdResult<()> {
+    /// left to consume. `track` and `index` are only used for error reporting.
+    fn consume_bin_sectors(&mut self, track: Bcd, index: Bcd, offset: Msf) -> CdResult<()> {
         let delta = offset - self.index_msf;
 
         let delta = delta.sector_index() as u64;
@@ -500,7 +1079,7 @@ impl CueParser {
             None => return Err(self.error_str("File doesn't start at 00:00:00")),
         };
 
-        let sector_size = ty.sector_size() as u64;
+        let sector_size = ty.sector_size(self.options.raw_subchannel) as u64;
 
         let index_size = match sector_size.checked_mul(delta) {
             Some(m) => m,
@@ -508,7 +1087,14 @@ impl CueParser {
         };
 
         if index_size > (self.bin_len - self.consumed_bytes) {
-            return Err(self.error_str("Index out of range (past the end of the BIN file)"));
+            return Err(CdError::IndexPastEndOfFile {
+                path: self.cue_path.clone(),
+                line: self.line,
+                track,
+                index,
+                offset,
+                bin_sectors: self.bin_len / sector_size,
+            });
         }
 
         self.consumed_bytes += index_size;
@@ -526,14 +1112,25 @@ impl CueParser {
             None => return Ok(()),
         };
 
-        let sector_size = ty.sector_size() as u64;
+        let sector_size = ty.sector_size(self.options.raw_subchannel) as u64;
 
-        let remaining_bytes = self.bin_len - self.consumed_bytes;
+        // We don't know the size of a missing file, assume that its last index is empty
+        let remaining_bytes = match self.bin_files.last() {
+            Some(BinaryBlob::Missing) => 0,
+            _ => self.bin_len - self.consumed_bytes,
+        };
 
         let sectors = remaining_bytes / sector_size;
 
         if remaining_bytes % sector_size != 0 {
-            return Err(self.error_str("Missaligned sector data while finishing a BIN file"));
+            let error = self.error_str("Missaligned sector data while finishing a BIN file");
+
+            if !self.options.truncate_partial_sectors {
+                return Err(error);
+            }
+
+            // The trailing partial sector is ignored
+            self.warnings.push(error);
         }
 
         let msf = match Msf::from_sector_index(sectors as u32) {
@@ -550,6 +1147,121 @@ impl CueParser {
     }
 }
 
+/// Returns the path to the BIN file `bin_name` relative to the directory `bin_path` containing the
+/// cue sheet. If it doesn't exist, look for a file with the same name but a different case.
+fn resolve_bin_path(mut bin_path: PathBuf, bin_name: &[u8]) -> io::Result<PathBuf> {
+    match build_path(bin_name) {
+        // If bin_name is an absolute Path it'll replace the
+        // parent completely bin_path (see the doc for PathBuf)
+        Some(p) => bin_path.push(p),
+        None => {
+            // XXX Use `InvalidFilename` when stabilized
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid BIN path in cuesheet: `{}`",
+                    String::from_utf8_lossy(bin_name)
+                ),
+            ));
+        }
+    }
+
+    if !bin_path.exists() {
+        bin_path = find_case_insensitive(&bin_path)?;
+    }
+
+    Ok(bin_path)
+}
+
+/// Pool of open BIN files, used to limit the number of file handles used by images referencing
+/// many BIN files. When the pool is full the least recently used file is closed.
+#[derive(Debug)]
+pub struct FilePool {
+    /// Maximum number of files open at the same time
+    max_open: usize,
+    /// Open files along with their BIN index, the most recently used last
+    files: Vec<(u32, File)>,
+}
+
+impl FilePool {
+    fn new(max_open: usize) -> FilePool {
+        let max_open = max_open.max(1);
+
+        FilePool {
+            max_open,
+            files: Vec::with_capacity(max_open),
+        }
+    }
+
+    /// Returns the open file for BIN `index`, opening `path` if necessary
+    fn get(&mut self, index: u32, path: &Path) -> io::Result<&mut File> {
+        match self.files.iter().position(|&(i, _)| i == index) {
+            Some(pos) => {
+                let f = self.files.remove(pos);
+                self.files.push(f);
+            }
+            None => {
+                let file = File::open(path)?;
+
+                if self.files.len() >= self.max_open {
+                    self.files.remove(0);
+                }
+
+                self.files.push((index, file));
+            }
+        }
+
+        // We just pushed it
+        Ok(&mut self.files.last_mut().unwrap().1)
+    }
+}
+
+/// Look for a file whose name matches the file name of `path` ignoring ASCII case, in the same
+/// directory. Used to open the BIN files referenced with the wrong case on case-sensitive
+/// filesystems. Fails if there's no match or if several files match.
+fn find_case_insensitive(path: &Path) -> io::Result<PathBuf> {
+    let not_found = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Couldn't find BIN file `{}`", path.display()),
+        )
+    };
+
+    let (dir, name) = match (path.parent(), path.file_name().and_then(|n| n.to_str())) {
+        (Some(d), Some(n)) => (d, n),
+        _ => return Err(not_found()),
+    };
+
+    let mut found = None;
+
+    for entry in dir.read_dir()? {
+        let entry = entry?;
+
+        let matches = match entry.file_name().to_str() {
+            Some(n) => n.eq_ignore_ascii_case(name),
+            None => false,
+        };
+
+        if !matches {
+            continue;
+        }
+
+        if found.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Several files match BIN file `{}` ignoring case",
+                    path.display()
+                ),
+            ));
+        }
+
+        found = Some(entry.path());
+    }
+
+    found.ok_or_else(not_found)
+}
+
 fn read_file<P: AsRef<Path>>(cue: P, max_len: u64) -> Result<Vec<u8>, io::Error> {
     let cue = cue.as_ref();
     let md = metadata(cue)?;
@@ -582,9 +1294,14 @@ fn next_line(cue_sheet: &[u8], start: usize) -> Option<(usize, &[u8])> {
         end += 1;
     }
 
-    end += 1;
+    let mut line = &cue_sheet[start..end];
+
+    // Handle CRLF line endings
+    if let Some(l) = line.strip_suffix(b"\r") {
+        line = l;
+    }
 
-    Some((end, &cue_sheet[start..end]))
+    Some((end + 1, line))
 }
 
 /// Like from_str but from an `u8`. Fails if buffer is not valid utf-8
@@ -629,23 +1346,58 @@ pub fn build_path(bytes: &[u8]) -> Option<PathBuf> {
 
 /// Possible sources for BIN files
 pub enum BinSource {
-    Fs(PathBuf),
+    Fs {
+        /// Directory containing the cue sheet
+        dir: PathBuf,
+        /// If set, the BIN files are opened on demand through this pool instead of being kept
+        /// open
+        pool: Option<FilePool>,
+    },
     Zip {
         zip: ZipArchive<File>,
-        path: PathBuf,
+        /// Separate handle to the archive, used to read stored (uncompressed) entries directly
+        file: File,
     },
+    /// User-provided storage, see `Cue::from_parts`
+    Custom(Box<dyn BinProvider>),
 }
 
 impl BinSource {
+    /// Fill `buf` with the contents of `blob` starting at byte `offset`
     pub fn read_exact_from(
         &mut self,
         blob: &mut BinaryBlob,
-        seek: SeekFrom,
+        offset: u64,
         buf: &mut [u8],
     ) -> CdResult<()> {
         match (self, blob) {
-            (BinSource::Fs(_path), BinaryBlob::File(f)) => {
-                f.seek(seek)?;
+            (BinSource::Fs { .. }, BinaryBlob::File(f)) => {
+                f.seek(SeekFrom::Start(offset))?;
+
+                f.read_exact(buf)?;
+            }
+            #[cfg(feature = "mmap")]
+            (BinSource::Fs { .. }, BinaryBlob::Mmap(map)) => {
+                if offset + buf.len() as u64 > map.len() as u64 {
+                    return Err(CdError::IoError(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Attempted to read past the end of a mapped BIN file",
+                    )));
+                }
+
+                let start = offset as usize;
+
+                buf.copy_from_slice(&map[start..start + buf.len()]);
+            }
+            (
+                BinSource::Fs {
+                    pool: Some(pool), ..
+                },
+                BinaryBlob::Pooled { index, path },
+            ) => {
+                let f = pool.get(*index, path)?;
+
+                f.seek(SeekFrom::Start(offset))?;
 
                 f.read_exact(buf)?;
             }
@@ -661,9 +1413,23 @@ impl BinSource {
                     }
                 }
 
-                buffer.seek(seek)?;
+                buffer.seek(SeekFrom::Start(offset))?;
                 buffer.read_exact(buf)?;
             }
+            (BinSource::Zip { file, .. }, BinaryBlob::ZipStored { data_start, size }) => {
+                if offset + buf.len() as u64 > *size {
+                    return Err(CdError::IoError(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Attempted to read past the end of a ZIP entry",
+                    )));
+                }
+
+                file.seek(SeekFrom::Start(*data_start + offset))?;
+                file.read_exact(buf)?;
+            }
+            (BinSource::Custom(provider), BinaryBlob::Custom(bin)) => {
+                provider.read_exact_at(*bin, offset, buf)?;
+            }
             _ => unreachable!("Invalid BinarySource/BinaryBlob configuration"),
         }
 
@@ -676,6 +1442,15 @@ impl BinSource {
 pub enum BinaryBlob {
     /// The blob is contained in a File
     File(File),
+    /// The blob is contained in a File mapped in memory
+    #[cfg(feature = "mmap")]
+    Mmap(memmap2::Mmap),
+    /// The blob is contained in a file opened on demand through the `FilePool`
+    Pooled {
+        /// Index of the BIN file in the cue sheet
+        index: u32,
+        path: PathBuf,
+    },
     /// The blob is contained in a ZIP file, referenced by its index.
     ZipFile {
         /// The index in the ZIP archive
@@ -683,33 +1458,55 @@ pub enum BinaryBlob {
         /// The contents are decompressed when the blob is first accessed
         buffer: io::Cursor<Vec<u8>>,
     },
+    /// The blob is stored uncompressed in a ZIP file, it's read directly from the archive without
+    /// buffering
+    ZipStored {
+        /// Offset of the entry's data in the archive
+        data_start: u64,
+        /// Size of the entry
+        size: u64,
+    },
+    /// The blob is read through a `BinProvider`, referenced by its index
+    Custom(u32),
+    /// Placeholder for a BIN file that couldn't be opened, only used by `CueParser::validate`
+    Missing,
 }
 
 impl BinaryBlob {
-    fn from_file(mut bin_path: PathBuf, bin_name: &[u8]) -> io::Result<(BinaryBlob, u64)> {
-        match build_path(bin_name) {
-            // If bin_name is an absolute Path it'll replace the
-            // parent completely bin_path (see the doc for PathBuf)
-            Some(p) => bin_path.push(p),
-            None => {
-                // XXX Use `InvalidFilename` when stabilized
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!(
-                        "Invalid BIN path in cuesheet: `{}`",
-                        String::from_utf8_lossy(bin_name)
-                    ),
-                ));
-            }
-        }
+    #[cfg_attr(not(feature = "mmap"), allow(unused_variables))]
+    fn from_file(dir: PathBuf, bin_name: &[u8], mmap: bool) -> io::Result<(BinaryBlob, u64)> {
+        let bin_path = resolve_bin_path(dir, bin_name)?;
 
         let file = File::open(&bin_path)?;
 
         let size = metadata(&bin_path)?.len();
 
+        #[cfg(feature = "mmap")]
+        if mmap {
+            // Safety: the mapping is only ever read from. Like with the `File` path, modifying
+            // the BIN file while the image is open results in garbage data (or a SIGBUS if the
+            // file is truncated).
+            //
+            // If the file can't be mapped (empty file, unsupported filesystem...) we silently
+            // fall back to regular reads.
+            if let Ok(map) = unsafe { memmap2::Mmap::map(&file) } {
+                let size = map.len() as u64;
+
+                return Ok((BinaryBlob::Mmap(map), size));
+            }
+        }
+
         Ok((BinaryBlob::File(file), size))
     }
 
+    fn from_path(dir: PathBuf, bin_name: &[u8], index: u32) -> io::Result<(BinaryBlob, u64)> {
+        let path = resolve_bin_path(dir, bin_name)?;
+
+        let size = metadata(&path)?.len();
+
+        Ok((BinaryBlob::Pooled { index, path }, size))
+    }
+
     fn from_zip_file(zip: &mut ZipArchive<File>, name: &[u8]) -> io::Result<(BinaryBlob, u64)> {
         for i in 0..zip.len() {
             let f = match zip.by_index(i) {
@@ -719,9 +1516,16 @@ impl BinaryBlob {
 
             if f.name_raw() == name {
                 let size = f.size();
-                let blob = BinaryBlob::ZipFile {
-                    zip_index: i,
-                    buffer: io::Cursor::new(Vec::new()),
+                let blob = if f.compression() == CompressionMethod::Stored {
+                    BinaryBlob::ZipStored {
+                        data_start: f.data_start(),
+                        size,
+                    }
+                } else {
+                    BinaryBlob::ZipFile {
+                        zip_index: i,
+                        buffer: io::Cursor::new(Vec::new()),
+                    }
                 };
 
                 return Ok((blob, size));
@@ -0,0 +1,3312 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use super::parser::BinaryBlob;
+use super::{
+    BinProvider, Cue, CueOptions, PregapFill, ReadErrorPolicy, SectorLocation, Storage, TrackLayout,
+};
+use adapters::TocOverride;
+use subchannel::{
+    isrc_from_q_raw, isrc_q_raw, mcn_from_q_raw, mcn_q_raw, AdrControl, QData, RawSubchannel, Q,
+};
+use {
+    Bcd, CdError, CdResult, DiscPosition, Image, Msf, PositionKind, Progress, ProgressPhase,
+    Sector, SectorIter, SessionFormat, Toc, Track, TrackFormat,
+};
+
+/// Temporary directory holding the cue sheet and BIN files of a test image. The directory is
+/// removed when the `TestImage` is dropped.
+struct TestImage {
+    dir: PathBuf,
+}
+
+impl TestImage {
+    /// Create a new empty test image. `name` must be unique among all the tests since they can run
+    /// in parallel.
+    fn new(name: &str) -> TestImage {
+        let mut dir = ::std::env::temp_dir();
+        dir.push(format!("cdimage-test-{}-{}", name, process::id()));
+
+        // Remove any leftover from a previous run
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        TestImage { dir }
+    }
+
+    /// Write `contents` to `file_name` in the test image directory
+    fn write(&self, file_name: &str, contents: &[u8]) -> PathBuf {
+        let mut path = self.dir.clone();
+        path.push(file_name);
+
+        fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    /// Write the cue sheet and attempt to load it
+    fn cue(&self, cue_sheet: &str) -> CdResult<Cue> {
+        self.cue_with_options(cue_sheet, CueOptions::default())
+    }
+
+    /// Write the cue sheet and attempt to load it with custom options
+    fn cue_with_options(&self, cue_sheet: &str, options: CueOptions) -> CdResult<Cue> {
+        let path = self.write("disc.cue", cue_sheet.as_bytes());
+
+        Cue::new_with_options(path, options)
+    }
+}
+
+impl Drop for TestImage {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Generate `nsectors` of recognizable audio data: every sector is filled with a pattern depending
+/// on its position in the BIN
+fn audio_bin(nsectors: u32) -> Vec<u8> {
+    let mut bin = Vec::with_capacity(nsectors as usize * 2352);
+
+    for s in 0..nsectors {
+        for i in 0..2352u32 {
+            bin.push((s.wrapping_mul(7) ^ i.wrapping_mul(13)) as u8);
+        }
+    }
+
+    bin
+}
+
+fn msf(s: &str) -> Msf {
+    s.parse().unwrap()
+}
+
+fn pos(s: &str) -> DiscPosition {
+    s.parse().unwrap()
+}
+
+#[test]
+fn motorola_audio() {
+    let img = TestImage::new("motorola_audio");
+
+    let bin = audio_bin(10);
+    let mut swapped = bin.clone();
+    for sample in swapped.chunks_exact_mut(2) {
+        sample.swap(0, 1);
+    }
+
+    img.write("le.bin", &bin);
+    img.write("be.bin", &swapped);
+
+    let mut le = img
+        .cue(
+            "FILE \"le.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    let mut be = img
+        .cue(
+            "FILE \"be.bin\" MOTOROLA\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    assert_eq!(le.toc(), be.toc());
+    assert_eq!(be.toc().tracks()[0].length, msf("00:00:10"));
+
+    for p in &["+00:02:00", "+00:02:05", "+00:02:09"] {
+        let p = pos(p);
+
+        let le = le.read_sector(p).unwrap();
+        let be = be.read_sector(p).unwrap();
+
+        assert_eq!(le.data_2352()[..], be.data_2352()[..]);
+        assert_eq!(le.q(), be.q());
+    }
+
+    // Make sure that we actually read the little-endian reference
+    let s = be.read_sector(pos("+00:02:00")).unwrap();
+    assert_eq!(s.data_2352()[..], bin[..2352]);
+}
+
+#[test]
+fn pregap_fill() {
+    let img = TestImage::new("pregap_fill");
+
+    let bin = audio_bin(10);
+    img.write("audio.bin", &bin);
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   PREGAP 00:00:02\n\
+             \x20   INDEX 01 00:00:05\n",
+        )
+        .unwrap();
+
+    assert_eq!(cue.pregap_fill(), PregapFill::Zero);
+    assert_eq!(cue.toc().tracks()[1].start, msf("00:02:07"));
+
+    let bin_sector = |n: usize| &bin[n * 2352..(n + 1) * 2352];
+
+    for &(fill, first, second) in &[
+        (PregapFill::Zero, None, None),
+        (PregapFill::Silence, None, None),
+        (PregapFill::PrevTrackTail, Some(3), Some(4)),
+        (PregapFill::NextTrackHead, Some(5), Some(6)),
+    ] {
+        cue.set_pregap_fill(fill);
+
+        for &(p, expected) in &[("+00:02:05", first), ("+00:02:06", second)] {
+            let s = cue.read_sector(pos(p)).unwrap();
+
+            assert!(s.q().is_pregap());
+            assert_eq!(s.q().amsf(), msf(&p[1..]));
+
+            match expected {
+                Some(n) => assert_eq!(s.data_2352()[..], bin_sector(n)[..]),
+                None => assert!(s.data_2352().iter().all(|&b| b == 0)),
+            }
+        }
+    }
+
+    // Stored sectors are unaffected
+    let s = cue.read_sector(pos("+00:02:07")).unwrap();
+    assert_eq!(s.data_2352()[..], bin_sector(5)[..]);
+}
+
+#[test]
+fn single_bin_track_positions() {
+    let img = TestImage::new("single_bin_track_positions");
+
+    img.write("audio.bin", &audio_bin(20));
+
+    let cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:00:05\n\
+             \x20 TRACK 03 AUDIO\n\
+             \x20   INDEX 01 00:00:08\n",
+        )
+        .unwrap();
+
+    let toc = cue.toc();
+
+    let expected = [
+        ("00:02:00", "00:00:05"),
+        ("00:02:05", "00:00:03"),
+        ("00:02:08", "00:00:12"),
+    ];
+
+    assert_eq!(toc.tracks().len(), expected.len());
+
+    for (t, &(start, length)) in toc.tracks().iter().zip(expected.iter()) {
+        assert_eq!(t.start, msf(start));
+        assert_eq!(t.length, msf(length));
+    }
+
+    assert_eq!(toc.lead_out_start(), msf("00:02:20"));
+}
+
+#[test]
+fn pregap_offset_accounting() {
+    let img = TestImage::new("pregap_offset_accounting");
+
+    let bin = audio_bin(20);
+    img.write("audio.bin", &bin);
+
+    // Index offsets are relative to the start of the file and the PREGAP of track 2 isn't stored
+    // in it, so it mustn't shift the data of the following tracks
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   PREGAP 00:00:02\n\
+             \x20   INDEX 01 00:00:05\n\
+             \x20 TRACK 03 AUDIO\n\
+             \x20   INDEX 01 00:00:12\n",
+        )
+        .unwrap();
+
+    let starts: Vec<_> = cue.toc().tracks().iter().map(|t| t.start).collect();
+
+    assert_eq!(starts, [msf("00:02:00"), msf("00:02:07"), msf("00:02:14")]);
+    assert_eq!(cue.toc().lead_out_start(), msf("00:02:22"));
+
+    for &(p, n) in &[
+        ("+00:02:04", 4),
+        ("+00:02:07", 5),
+        ("+00:02:14", 12),
+        ("+00:02:21", 19),
+    ] {
+        let s = cue.read_sector(pos(p)).unwrap();
+
+        assert_eq!(s.data_2352()[..], bin[n * 2352..(n + 1) * 2352]);
+    }
+}
+
+#[test]
+fn seek_track() {
+    let img = TestImage::new("seek_track");
+
+    img.write("audio.bin", &audio_bin(20));
+
+    let cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   PREGAP 00:00:02\n\
+             \x20   INDEX 01 00:00:05\n\
+             \x20 TRACK 03 AUDIO\n\
+             \x20   INDEX 01 00:00:08\n",
+        )
+        .unwrap();
+
+    // Track 01 starts at 00:02:00, track 02 at 00:02:07, track 03 at 00:02:10 and the lead-out at
+    // 00:02:22
+    let to_test = &[
+        ("+00:02:03", 0, "+00:02:00"),
+        ("+00:02:03", 1, "+00:02:07"),
+        ("+00:02:03", 2, "+00:02:10"),
+        ("+00:02:03", -1, "+00:02:00"),
+        ("+00:02:03", -50, "+00:02:00"),
+        ("+00:02:03", 3, "+00:02:22"),
+        ("+00:02:03", i32::MAX, "+00:02:22"),
+        // Track 02's pregap is still part of track 01 as far as the ToC is concerned
+        ("+00:02:05", 1, "+00:02:07"),
+        ("+00:02:07", 0, "+00:02:07"),
+        ("+00:02:07", -1, "+00:02:00"),
+        ("+00:02:10", 1, "+00:02:22"),
+        ("+00:02:21", -2, "+00:02:00"),
+        ("+00:00:00", 0, "+00:02:00"),
+        ("+00:00:00", 1, "+00:02:00"),
+        ("+00:00:00", 2, "+00:02:07"),
+        ("<99:59:74", 1, "+00:02:00"),
+        ("+00:02:22", -1, "+00:02:10"),
+        ("+00:02:22", 1, "+00:02:22"),
+        ("+10:00:00", -3, "+00:02:00"),
+        ("+10:00:00", i32::MIN, "+00:02:00"),
+    ];
+
+    for &(p, delta, expected) in to_test {
+        assert_eq!(cue.seek_track(pos(p), delta).unwrap(), pos(expected));
+    }
+}
+
+#[test]
+fn read_sector_into() {
+    let img = TestImage::new("read_sector_into");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   PREGAP 00:00:02\n\
+             \x20   INDEX 01 00:00:05\n",
+        )
+        .unwrap();
+
+    let mut sector = cue.read_sector(pos("+00:02:00")).unwrap();
+
+    // Stored sectors, generated pregap, lead-out and lead-in
+    for p in &[
+        "+00:02:03",
+        "+00:02:05",
+        "+00:02:08",
+        "+00:02:11",
+        "+00:02:12",
+        "<00:00:10",
+        "+00:02:01",
+    ] {
+        let p = pos(p);
+
+        cue.read_sector_into(p, &mut sector).unwrap();
+        let expected = cue.read_sector(p).unwrap();
+
+        assert_eq!(sector.data_2352()[..], expected.data_2352()[..]);
+        assert_eq!(sector.q(), expected.q());
+        assert_eq!(sector.format(), expected.format());
+    }
+}
+
+#[test]
+fn track1_implicit_pregap_once() {
+    let img = TestImage::new("track1_implicit_pregap_once");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20   INDEX 02 00:00:04\n\
+             \x20   INDEX 03 00:00:06\n",
+        )
+        .unwrap();
+
+    // The standard 2 second pregap is only inserted before the first index of track 1
+    let pregaps: Vec<_> = cue
+        .indices
+        .indices()
+        .iter()
+        .filter(|i| matches!(i.private(), Storage::PreGap))
+        .map(|i| i.msf())
+        .collect();
+
+    assert_eq!(pregaps, [Msf::ZERO]);
+
+    assert_eq!(cue.toc().tracks()[0].length, msf("00:00:10"));
+    assert_eq!(cue.toc().lead_out_start(), msf("00:02:10"));
+
+    assert_eq!(
+        cue.current_index(msf("00:02:06")).unwrap(),
+        (Bcd::ONE, Bcd::from_binary(3).unwrap())
+    );
+}
+
+#[test]
+fn higher_indices() {
+    let img = TestImage::new("higher_indices");
+
+    img.write("audio.bin", &audio_bin(20));
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20   INDEX 02 00:00:04\n\
+             \x20   INDEX 03 00:00:06\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:00:10\n\
+             \x20   INDEX 01 00:00:12\n\
+             \x20   INDEX 02 00:00:15\n",
+        )
+        .unwrap();
+
+    let to_test = [
+        ("00:02:00", 1, 1, "00:00:00"),
+        ("00:02:03", 1, 1, "00:00:03"),
+        ("00:02:04", 1, 2, "00:00:04"),
+        ("00:02:05", 1, 2, "00:00:05"),
+        ("00:02:06", 1, 3, "00:00:06"),
+        ("00:02:09", 1, 3, "00:00:09"),
+        ("00:02:10", 2, 0, "00:00:02"),
+        ("00:02:12", 2, 1, "00:00:00"),
+        ("00:02:15", 2, 2, "00:00:03"),
+        ("00:02:19", 2, 2, "00:00:07"),
+    ];
+
+    for &(m, track, index, track_msf) in &to_test {
+        let m = msf(m);
+        let track = Bcd::from_binary(track).unwrap();
+        let index = Bcd::from_binary(index).unwrap();
+
+        assert_eq!(cue.current_index(m).unwrap(), (track, index));
+
+        let s = cue.read_sector(DiscPosition::Program(m)).unwrap();
+
+        assert_eq!(
+            *s.q().data(),
+            QData::Mode1 {
+                track,
+                index,
+                track_msf: msf(track_msf),
+                disc_msf: m,
+            }
+        );
+    }
+
+    assert!(cue.current_index(msf("00:02:20")).is_err());
+}
+
+#[test]
+fn cdg_track() {
+    let img = TestImage::new("cdg_track");
+
+    let audio = audio_bin(10);
+
+    // Interleave 96 bytes of subchannel data after every audio sector
+    let mut bin = Vec::new();
+    for sector in audio.chunks(2352) {
+        bin.extend_from_slice(sector);
+        bin.extend_from_slice(&[0xc9; 96]);
+    }
+
+    img.write("karaoke.bin", &bin);
+
+    let mut cue = img
+        .cue(
+            "FILE \"karaoke.bin\" BINARY\n\
+             \x20 TRACK 01 CDG\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    let track = &cue.toc().tracks()[0];
+    assert_eq!(track.format, TrackFormat::Audio);
+    assert_eq!(track.length, msf("00:00:10"));
+
+    for n in &[0, 3, 9] {
+        let s = cue
+            .read_sector(DiscPosition::Program(
+                msf("00:02:00") + Msf::from_sector_index(*n).unwrap(),
+            ))
+            .unwrap();
+
+        assert_eq!(
+            s.data_2352()[..],
+            audio[*n as usize * 2352..(*n as usize + 1) * 2352]
+        );
+    }
+}
+
+#[test]
+fn hashes() {
+    let img = TestImage::new("hashes");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:00:05\n",
+        )
+        .unwrap();
+
+    let hex = |h: [u8; 20]| h.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+
+    let t1 = Bcd::ONE;
+    let t2 = Bcd::from_binary(2).unwrap();
+
+    assert_eq!(cue.track_crc32(t1).unwrap(), 0xbebc_d943);
+    assert_eq!(
+        hex(cue.track_sha1(t1).unwrap()),
+        "87a2d39b6d3375d15f22a225f2d9bad255053ed9"
+    );
+    assert_eq!(cue.track_crc32(t2).unwrap(), 0x2bfb_3d81);
+    assert_eq!(
+        hex(cue.track_sha1(t2).unwrap()),
+        "6e76a9f7bd1ccba5842dc653e9d68cff93976f28"
+    );
+    assert_eq!(cue.disc_crc32().unwrap(), 0x3376_d300);
+    assert_eq!(
+        hex(cue.disc_sha1().unwrap()),
+        "658e41a75b00408e3dc8d615f584382af27d556a"
+    );
+
+    assert!(cue.track_crc32(Bcd::from_binary(3).unwrap()).is_err());
+
+    // Track 2 starts with a pregap stored in the BIN file, it's hashed with track 2
+    let bin = audio_bin(10);
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:00:03\n\
+             \x20   INDEX 01 00:00:05\n",
+        )
+        .unwrap();
+
+    assert_eq!(
+        cue.track_crc32(t1).unwrap(),
+        ::crc32fast::hash(&bin[..3 * 2352])
+    );
+    assert_eq!(
+        cue.track_crc32(t2).unwrap(),
+        ::crc32fast::hash(&bin[3 * 2352..])
+    );
+    assert_eq!(cue.disc_crc32().unwrap(), ::crc32fast::hash(&bin));
+}
+
+#[test]
+fn explicit_first_pregap() {
+    let img = TestImage::new("explicit_first_pregap");
+
+    // 5 seconds and 10 frames
+    let bin = audio_bin(5 * 75 + 10);
+    img.write("audio.bin", &bin);
+
+    let implicit = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:05:00\n",
+        )
+        .unwrap();
+
+    let tracks = implicit.toc().tracks();
+    assert_eq!(tracks[0].start, msf("00:02:00"));
+    assert_eq!(tracks[1].start, msf("00:07:00"));
+
+    let mut explicit = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 00 00:00:00\n\
+             \x20   INDEX 01 00:03:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:05:00\n",
+        )
+        .unwrap();
+
+    let tracks = explicit.toc().tracks();
+    assert_eq!(tracks[0].start, msf("00:03:00"));
+    assert_eq!(tracks[0].length, msf("00:02:00"));
+    assert_eq!(tracks[1].start, msf("00:05:00"));
+    assert_eq!(explicit.toc().lead_out_start(), msf("00:05:10"));
+
+    // The pregap is read from the BIN file
+    let s = explicit.read_sector(pos("+00:00:00")).unwrap();
+    assert_eq!(s.data_2352()[..], bin[..2352]);
+    assert_eq!(
+        *s.q().data(),
+        QData::Mode1 {
+            track: Bcd::ONE,
+            index: Bcd::ZERO,
+            track_msf: msf("00:03:00"),
+            disc_msf: msf("00:00:00"),
+        }
+    );
+
+    let s = explicit.read_sector(pos("+00:03:00")).unwrap();
+    assert_eq!(s.data_2352()[..], bin[225 * 2352..226 * 2352]);
+}
+
+#[test]
+fn postgap() {
+    let img = TestImage::new("postgap");
+
+    let bin = audio_bin(10);
+    img.write("audio.bin", &bin);
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20   POSTGAP 00:00:03\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:00:05\n\
+             \x20   POSTGAP 00:00:02\n",
+        )
+        .unwrap();
+
+    let tracks = cue.toc().tracks();
+
+    // The postgaps are part of their tracks
+    assert_eq!(tracks[0].start, msf("00:02:00"));
+    assert_eq!(tracks[0].length, msf("00:00:08"));
+    assert_eq!(tracks[1].start, msf("00:02:08"));
+    assert_eq!(tracks[1].length, msf("00:00:07"));
+    assert_eq!(cue.toc().lead_out_start(), msf("00:02:15"));
+
+    for &(p, track, track_msf, stored) in &[
+        ("00:02:04", 1, "00:00:04", Some(4)),
+        ("00:02:05", 1, "00:00:05", None),
+        ("00:02:07", 1, "00:00:07", None),
+        ("00:02:08", 2, "00:00:00", Some(5)),
+        ("00:02:12", 2, "00:00:04", Some(9)),
+        ("00:02:13", 2, "00:00:05", None),
+        ("00:02:14", 2, "00:00:06", None),
+    ] {
+        let s = cue.read_sector(DiscPosition::Program(msf(p))).unwrap();
+
+        assert_eq!(
+            *s.q().data(),
+            QData::Mode1 {
+                track: Bcd::from_binary(track).unwrap(),
+                index: Bcd::ONE,
+                track_msf: msf(track_msf),
+                disc_msf: msf(p),
+            }
+        );
+
+        match stored {
+            Some(n) => assert_eq!(s.data_2352()[..], bin[n * 2352..(n + 1) * 2352]),
+            None => assert!(s.data_2352().iter().all(|&b| b == 0)),
+        }
+    }
+
+    assert!(img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   POSTGAP 00:00:03\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .is_err());
+}
+
+#[test]
+fn sectors() {
+    let img = TestImage::new("sectors");
+
+    let bin = audio_bin(10);
+    img.write("audio.bin", &bin);
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   PREGAP 00:00:02\n\
+             \x20   INDEX 01 00:00:05\n",
+        )
+        .unwrap();
+
+    let first = cue.toc().tracks()[0].start;
+    let lead_out = cue.toc().lead_out_start();
+    let expected = (lead_out - first).sector_index() as usize;
+
+    assert_eq!(expected, 12);
+    assert_eq!(cue.sectors().len(), expected);
+
+    let sectors = cue.sectors().collect::<CdResult<Vec<_>>>().unwrap();
+    assert_eq!(sectors.len(), expected);
+
+    assert_eq!(sectors[0].q().amsf(), first);
+    assert_eq!(sectors[0].data_2352()[..], bin[..2352]);
+    assert_eq!(sectors[11].q().amsf(), msf("00:02:11"));
+    assert_eq!(sectors[11].data_2352()[..], bin[9 * 2352..]);
+    assert!(sectors.iter().all(|s| !s.q().is_lead_out()));
+
+    // Also usable through a trait object
+    let image: &mut dyn Image = &mut cue;
+    assert_eq!(SectorIter::new(image).count(), expected);
+}
+
+#[test]
+fn lead_out_length() {
+    let img = TestImage::new("lead_out_length");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    assert!(cue.read_sector(pos("+00:02:10")).unwrap().q().is_lead_out());
+    assert!(cue.read_sector(pos("+01:32:09")).is_ok());
+    assert!(cue.read_sector(pos("+01:32:10")).is_err());
+
+    cue.set_lead_out_length(msf("00:00:05"));
+    assert_eq!(cue.toc().lead_out_end(), msf("00:02:15"));
+
+    assert!(cue.read_sector(pos("+00:02:14")).is_ok());
+    assert!(matches!(
+        cue.read_sector(pos("+00:02:15")),
+        Err(CdError::OutOfDiscPosition)
+    ));
+}
+
+#[test]
+fn case_insensitive() {
+    let img = TestImage::new("case_insensitive");
+
+    img.write("Audio.bin", &audio_bin(10));
+
+    let sheet = "file \"Audio.bin\" binary\n\
+                 \x20 track 01 audio\n\
+                 \x20   flags dcp\n\
+                 \x20   index 01 00:00:00\n\
+                 \x20 Track 02 Audio\n\
+                 \x20   Pregap 00:00:02\n\
+                 \x20   Index 01 00:00:05\n";
+
+    let cue = img.cue(sheet).unwrap();
+
+    let tracks = cue.toc().tracks();
+    assert_eq!(tracks.len(), 2);
+    assert_eq!(tracks[0].start, msf("00:02:00"));
+    assert!(tracks[0].control.digital_copy_permitted());
+    assert_eq!(tracks[1].start, msf("00:02:07"));
+    assert_eq!(cue.toc().lead_out_start(), msf("00:02:12"));
+
+    let strict = CueOptions {
+        case_insensitive: false,
+        ..CueOptions::default()
+    };
+
+    assert!(img.cue_with_options(sheet, strict).is_err());
+}
+
+#[test]
+fn flags() {
+    let img = TestImage::new("flags");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   FLAGS DCP PRE 4CH\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:00:05\n",
+        )
+        .unwrap();
+
+    let control = cue.toc().tracks()[0].control;
+    assert!(control.digital_copy_permitted());
+    assert!(control.pre_emphasis());
+    assert!(control.four_channel_audio());
+
+    // The flags are attached to their track only
+    let control = cue.toc().tracks()[1].control;
+    assert!(!control.digital_copy_permitted());
+    assert!(!control.pre_emphasis());
+    assert!(!control.four_channel_audio());
+
+    let s = cue.read_sector(pos("+00:02:01")).unwrap();
+    assert!(s.q().adr_control().pre_emphasis());
+
+    for sheet in &[
+        "FILE \"audio.bin\" BINARY\n\
+         \x20 TRACK 01 AUDIO\n\
+         \x20   FLAGS SCMS\n\
+         \x20   INDEX 01 00:00:00\n",
+        "FILE \"audio.bin\" BINARY\n\
+         FLAGS DCP\n\
+         \x20 TRACK 01 AUDIO\n\
+         \x20   INDEX 01 00:00:00\n",
+    ] {
+        assert!(matches!(img.cue(sheet), Err(CdError::ParseError { .. })));
+    }
+}
+
+#[test]
+fn track_layout() {
+    let img = TestImage::new("track_layout");
+
+    img.write("audio.bin", &audio_bin(20));
+    img.write("data.bin", &vec![0; 2048 * 10]);
+
+    let cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:00:05\n\
+             \x20   INDEX 01 00:00:07\n\
+             FILE \"data.bin\" BINARY\n\
+             \x20 TRACK 03 MODE1/2048\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    // Track 1 and the pregap of track 2 come first in the BIN
+    let track1_len = 7;
+
+    assert_eq!(
+        cue.track_layout(Bcd::from_binary(2).unwrap()).unwrap(),
+        TrackLayout {
+            bin_index: 0,
+            byte_offset: track1_len * 2352,
+            sector_size: 2352,
+            stored: true,
+        }
+    );
+
+    assert_eq!(
+        cue.track_layout(Bcd::from_binary(3).unwrap()).unwrap(),
+        TrackLayout {
+            bin_index: 1,
+            byte_offset: 0,
+            sector_size: 2048,
+            stored: true,
+        }
+    );
+
+    assert!(cue.track_layout(Bcd::from_binary(4).unwrap()).is_err());
+}
+
+#[test]
+fn sector_file_location() {
+    let img = TestImage::new("sector_file_location");
+
+    img.write("audio.bin", &audio_bin(20));
+    img.write("data.bin", &vec![0; 2048 * 10]);
+
+    let cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:00:05\n\
+             \x20   INDEX 01 00:00:07\n\
+             FILE \"data.bin\" BINARY\n\
+             \x20 TRACK 03 MODE1/2048\n\
+             \x20   PREGAP 00:00:02\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    let location = |p: &str| cue.sector_file_location(pos(p)).unwrap();
+
+    // First sector of track 2
+    assert_eq!(
+        location("+00:02:07"),
+        Some(SectorLocation {
+            bin_index: 0,
+            offset: 7 * 2352,
+            size: 2352,
+        })
+    );
+
+    // Pregap of track 2, stored in the BIN
+    assert_eq!(
+        location("+00:02:05"),
+        Some(SectorLocation {
+            bin_index: 0,
+            offset: 5 * 2352,
+            size: 2352,
+        })
+    );
+
+    // Generated pregap of track 3
+    assert_eq!(location("+00:02:20"), None);
+    assert_eq!(location("+00:02:21"), None);
+
+    assert_eq!(
+        location("+00:02:25"),
+        Some(SectorLocation {
+            bin_index: 1,
+            offset: 3 * 2048,
+            size: 2048,
+        })
+    );
+
+    // Lead-in and lead-out
+    assert_eq!(location("<99:59:74"), None);
+    assert_eq!(location("+00:02:32"), None);
+
+    assert!(matches!(
+        cue.sector_file_location(DiscPosition::Program(cue.toc().lead_out_end())),
+        Err(CdError::OutOfDiscPosition)
+    ));
+}
+
+#[test]
+fn mode1_raw_sector_size() {
+    let img = TestImage::new("mode1_raw_sector_size");
+
+    let mut bin = Vec::new();
+
+    for n in 0..5u32 {
+        let q = Q::from_qdata_mode1(
+            QData::Mode1 {
+                track: Bcd::ONE,
+                index: Bcd::ONE,
+                track_msf: Msf::from_sector_index(n).unwrap(),
+                disc_msf: Msf::from_sector_index(150 + n).unwrap(),
+            },
+            AdrControl::DATA,
+        );
+
+        let mut sector = Sector::empty(q, TrackFormat::Mode1).unwrap();
+        sector.data_2352_mut()[100] = n as u8;
+        sector.write_edc_ecc();
+
+        bin.extend_from_slice(sector.data_2352());
+    }
+
+    let audio = audio_bin(5);
+    bin.extend_from_slice(&audio);
+
+    img.write("disc.bin", &bin);
+
+    let mut cue = img
+        .cue(
+            "FILE \"disc.bin\" BINARY\n\
+             \x20 TRACK 01 MODE1/2352\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:00:05\n",
+        )
+        .unwrap();
+
+    // MODE1/2352 sectors are stored as full 2352-byte sectors
+    assert_eq!(cue.track_layout(Bcd::ONE).unwrap().sector_size, 2352);
+    assert_eq!(
+        cue.track_layout(Bcd::from_binary(2).unwrap())
+            .unwrap()
+            .byte_offset,
+        5 * 2352
+    );
+
+    for n in 0..5 {
+        let s = cue
+            .read_sector(DiscPosition::Program(
+                Msf::from_sector_index(150 + n).unwrap(),
+            ))
+            .unwrap();
+
+        assert_eq!(s.data_2352()[100], n as u8);
+        assert!(s.edc_valid());
+        assert!(s.validate_position().is_ok());
+    }
+
+    let s = cue.read_sector(pos("+00:02:05")).unwrap();
+    assert_eq!(s.data_2352()[..], audio[..2352]);
+    assert_eq!(cue.toc().lead_out_start(), msf("00:02:10"));
+}
+
+#[test]
+fn truncate_partial_sectors() {
+    let img = TestImage::new("truncate_partial_sectors");
+
+    let mut bin = audio_bin(10);
+    bin.extend_from_slice(&[0xff; 5]);
+
+    img.write("audio.bin", &bin);
+
+    let sheet = "FILE \"audio.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n";
+
+    assert!(matches!(img.cue(sheet), Err(CdError::ParseError { .. })));
+
+    let lenient = CueOptions {
+        truncate_partial_sectors: true,
+        ..CueOptions::default()
+    };
+
+    let mut cue = img.cue_with_options(sheet, lenient).unwrap();
+
+    assert_eq!(cue.warnings().len(), 1);
+    assert_eq!(cue.toc().lead_out_start(), msf("00:02:10"));
+
+    let sector = cue.read_sector(pos("+00:02:09")).unwrap();
+    assert_eq!(sector.data_2352(), &bin[9 * 2352..10 * 2352]);
+}
+
+#[test]
+fn bin_name_case() {
+    let img = TestImage::new("bin_name_case");
+
+    img.write("game.bin", &audio_bin(10));
+
+    let sheet = "FILE \"Game.BIN\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n";
+
+    let cue = img.cue(sheet).unwrap();
+
+    assert_eq!(cue.toc().lead_out_start(), msf("00:02:10"));
+
+    // Ambiguous match, only possible on case-sensitive filesystems
+    if cfg!(target_os = "linux") {
+        img.write("GAME.bin", &audio_bin(10));
+
+        assert!(matches!(img.cue(sheet), Err(CdError::ParseError { .. })));
+    }
+}
+
+#[test]
+fn plain_mode2() {
+    let img = TestImage::new("plain_mode2");
+
+    img.write("data.bin", &vec![0; 2352 * 10]);
+
+    let sheet = "FILE \"data.bin\" BINARY\n\
+                 \x20 TRACK 01 MODE2/2352\n\
+                 \x20   INDEX 01 00:00:00\n";
+
+    let cue = img.cue(sheet).unwrap();
+    assert_eq!(cue.toc().tracks()[0].format, TrackFormat::Mode2Xa);
+
+    let options = CueOptions {
+        mode2_xa: false,
+        ..CueOptions::default()
+    };
+
+    let mut cue = img.cue_with_options(sheet, options).unwrap();
+    assert_eq!(cue.toc().tracks()[0].format, TrackFormat::Mode2);
+
+    let sector = cue.read_sector(pos("+00:02:00")).unwrap();
+    assert_eq!(sector.format(), TrackFormat::Mode2);
+}
+
+#[test]
+fn progress() {
+    let img = TestImage::new("progress");
+
+    img.write("audio.bin", &audio_bin(20));
+
+    let path = img.write(
+        "disc.cue",
+        b"FILE \"audio.bin\" BINARY\n\
+          \x20 TRACK 01 AUDIO\n\
+          \x20   INDEX 01 00:00:00\n\
+          \n\
+          \x20 TRACK 02 AUDIO\n\
+          \x20   INDEX 01 00:00:05\n",
+    );
+
+    let mut calls = Vec::new();
+
+    let mut cue =
+        Cue::new_with_progress(path, CueOptions::default(), &mut |p| calls.push(p)).unwrap();
+
+    let expected: Vec<Progress> = (1..=6)
+        .map(|current| Progress {
+            phase: ProgressPhase::Parsing,
+            current,
+            total: 6,
+        })
+        .collect();
+
+    assert_eq!(calls, expected);
+
+    let mut last = 0;
+
+    cue.integrity_report_with_progress(&mut |p| {
+        assert_eq!(p.phase, ProgressPhase::Verifying);
+        assert_eq!(p.total, 20);
+        assert!(p.current > last);
+        last = p.current;
+    })
+    .unwrap();
+
+    assert_eq!(last, 20);
+}
+
+#[test]
+fn zip_stored() {
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    let img = TestImage::new("zip_stored");
+
+    let bin = audio_bin(20);
+    let sheet = "FILE \"audio.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n\
+                 FILE \"packed.bin\" BINARY\n\
+                 \x20 TRACK 02 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n";
+
+    let path = img.write("disc.zip", &[]);
+
+    {
+        let mut zip = ZipWriter::new(fs::File::create(&path).unwrap());
+
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        zip.start_file("disc.cue", stored).unwrap();
+        zip.write_all(sheet.as_bytes()).unwrap();
+        zip.start_file("audio.bin", stored).unwrap();
+        zip.write_all(&bin).unwrap();
+        zip.start_file("packed.bin", deflated).unwrap();
+        zip.write_all(&bin).unwrap();
+        zip.finish().unwrap();
+    }
+
+    let mut cue = Cue::new_from_zip(&path).unwrap();
+
+    let sector = cue.read_sector(pos("+00:02:13")).unwrap();
+    assert_eq!(sector.data_2352(), &bin[13 * 2352..14 * 2352]);
+
+    let sector = cue.read_sector(pos("+00:02:33")).unwrap();
+    assert_eq!(sector.data_2352(), &bin[13 * 2352..14 * 2352]);
+
+    // The stored entry is read directly from the archive, only the deflated one is buffered
+    assert!(matches!(cue.bin_files[0], BinaryBlob::ZipStored { .. }));
+    match cue.bin_files[1] {
+        BinaryBlob::ZipFile { ref buffer, .. } => assert_eq!(buffer.get_ref().len(), bin.len()),
+        ref b => panic!("Unexpected blob {:?}", b),
+    }
+}
+
+#[test]
+fn read_subq() {
+    let img = TestImage::new("read_subq");
+
+    img.write("audio.bin", &audio_bin(20));
+    img.write("data.bin", &vec![0; 2352 * 10]);
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:00:05\n\
+             \x20   INDEX 01 00:00:07\n\
+             \x20   POSTGAP 00:00:02\n\
+             FILE \"data.bin\" BINARY\n\
+             \x20 TRACK 03 MODE1/2352\n\
+             \x20   PREGAP 00:00:03\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    for &p in &[
+        "<99:59:74",
+        "<99:59:00",
+        "+00:00:00",
+        "+00:02:00",
+        "+00:02:05",
+        "+00:02:07",
+        "+00:02:20",
+        "+00:02:23",
+        "+00:02:25",
+        "+00:02:35",
+        "+00:03:00",
+    ] {
+        let p = pos(p);
+
+        assert_eq!(
+            cue.read_subq(p).unwrap(),
+            *cue.read_sector(p).unwrap().q(),
+            "{}",
+            p
+        );
+    }
+
+    assert!(matches!(
+        cue.read_subq(pos("+10:00:00")),
+        Err(CdError::OutOfDiscPosition)
+    ));
+}
+
+#[test]
+fn max_open_files() {
+    let img = TestImage::new("max_open_files");
+
+    let mut sheet = String::new();
+    let mut bins = Vec::new();
+
+    for t in 0..5u8 {
+        let bin: Vec<u8> = audio_bin(10).iter().map(|b| b ^ t).collect();
+        let name = format!("track{}.bin", t + 1);
+
+        img.write(&name, &bin);
+
+        sheet.push_str(&format!(
+            "FILE \"{}\" BINARY\n  TRACK {:02} AUDIO\n    INDEX 01 00:00:00\n",
+            name,
+            t + 1
+        ));
+
+        bins.push(bin);
+    }
+
+    let options = CueOptions {
+        max_open_files: Some(2),
+        ..CueOptions::default()
+    };
+
+    let mut cue = img.cue_with_options(&sheet, options).unwrap();
+
+    assert_eq!(cue.toc().tracks().len(), 5);
+
+    // Go back and forth between the BINs to force them to be reopened
+    for &t in &[0, 1, 2, 3, 4, 0, 4, 2, 2, 1, 3] {
+        let start = cue.toc().tracks()[t].start;
+        let p = DiscPosition::Program(start + msf("00:00:03"));
+
+        let sector = cue.read_sector(p).unwrap();
+
+        assert_eq!(sector.data_2352(), &bins[t][3 * 2352..4 * 2352]);
+    }
+}
+
+#[test]
+fn write_cue_sheet() {
+    let img = TestImage::new("write_cue_sheet");
+
+    img.write("a.bin", &audio_bin(20));
+    img.write("b.bin", &audio_bin(10));
+
+    let cue = img
+        .cue(
+            "FILE \"a.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   FLAGS DCP PRE\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20   POSTGAP 00:00:02\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:00:08\n\
+             \x20   INDEX 01 00:00:10\n\
+             FILE \"b.bin\" BINARY\n\
+             \x20 TRACK 03 AUDIO\n\
+             \x20   PREGAP 00:00:03\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    let sheet = cue.write_cue_sheet();
+
+    assert_eq!(
+        sheet,
+        "FILE \"a.bin\" BINARY\n\
+         \x20 TRACK 01 AUDIO\n\
+         \x20   FLAGS DCP PRE\n\
+         \x20   INDEX 01 00:00:00\n\
+         \x20   POSTGAP 00:00:02\n\
+         \x20 TRACK 02 AUDIO\n\
+         \x20   INDEX 00 00:00:08\n\
+         \x20   INDEX 01 00:00:10\n\
+         FILE \"b.bin\" BINARY\n\
+         \x20 TRACK 03 AUDIO\n\
+         \x20   PREGAP 00:00:03\n\
+         \x20   INDEX 01 00:00:00\n"
+    );
+
+    /// Position, track, index number, BIN index and offset if stored, true if generated
+    type Layout = (Msf, Bcd, Bcd, Option<(u32, u64)>, bool);
+
+    /// Returns the position and storage of every index of `cue`
+    fn layout(cue: &Cue) -> Vec<Layout> {
+        cue.indices
+            .indices()
+            .iter()
+            .map(|i| {
+                let (stored, generated) = match *i.private() {
+                    Storage::Bin(bin, offset, _, _) => (Some((bin, offset)), false),
+                    Storage::PreGap => (None, false),
+                    Storage::Generated { .. } => (None, true),
+                };
+
+                (i.msf(), i.track(), i.index(), stored, generated)
+            })
+            .collect()
+    }
+
+    let reparsed = img.cue(&sheet).unwrap();
+
+    assert_eq!(layout(&reparsed), layout(&cue));
+    for (a, b) in reparsed.toc().tracks().iter().zip(cue.toc().tracks()) {
+        assert_eq!((a.track, a.start, a.length), (b.track, b.start, b.length));
+    }
+    assert_eq!(reparsed.write_cue_sheet(), sheet);
+}
+
+#[test]
+fn missing_bin() {
+    let img = TestImage::new("missing_bin");
+
+    img.write("track1.bin", &audio_bin(10));
+
+    let err = img
+        .cue(
+            "FILE \"track1.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             FILE \"track2.bin\" BINARY\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .err()
+        .unwrap();
+
+    match err {
+        CdError::ParseError { line, ref desc, .. } => {
+            assert_eq!(line, 4);
+            assert!(desc.contains("track2.bin"));
+        }
+        ref e => panic!("Unexpected error {:?}", e),
+    }
+
+    let msg = err.to_string();
+
+    assert!(msg.contains("|4:"));
+    assert!(msg.contains("track2.bin"));
+}
+
+#[test]
+fn hidden_track1_pregap() {
+    let img = TestImage::new("hidden_track1_pregap");
+
+    // 150 sectors of hidden pregap followed by 10 sectors of track 1
+    let bin = audio_bin(160);
+    img.write("audio.bin", &bin);
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:02:00\n",
+        )
+        .unwrap();
+
+    assert_eq!(cue.warnings().len(), 1);
+
+    let tracks = cue.toc().tracks();
+    assert_eq!(tracks[0].start, msf("00:02:00"));
+    assert_eq!(tracks[0].length, msf("00:00:10"));
+
+    for &n in &[0, 1, 75, 149] {
+        let p = DiscPosition::Program(Msf::from_sector_index(n).unwrap());
+
+        let s = cue.read_sector(p).unwrap();
+
+        assert_eq!(
+            *s.q().data(),
+            QData::Mode1 {
+                track: Bcd::ONE,
+                index: Bcd::ZERO,
+                track_msf: Msf::from_sector_index(150 - n).unwrap(),
+                disc_msf: Msf::from_sector_index(n).unwrap(),
+            }
+        );
+
+        let n = n as usize;
+        assert_eq!(s.data_2352()[..], bin[n * 2352..(n + 1) * 2352]);
+    }
+
+    let s = cue.read_sector(pos("+00:02:00")).unwrap();
+    assert_eq!(s.data_2352()[..], bin[150 * 2352..151 * 2352]);
+
+    // Without hidden data the pregap is still generated
+    let cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    assert!(cue.warnings().is_empty());
+    assert_eq!(cue.toc().tracks()[0].length, msf("00:02:10"));
+
+    // Only a standard 2 second pregap can be stored before track 1
+    for offset in &["00:00:10", "00:01:74", "00:02:01"] {
+        let sheet = format!(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 {}\n",
+            offset
+        );
+
+        assert!(matches!(img.cue(&sheet), Err(CdError::ParseError { .. })));
+    }
+}
+
+#[test]
+fn bom_and_crlf() {
+    let img = TestImage::new("bom_and_crlf");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let cue = img
+        .cue(
+            "\u{feff}REM Windows-authored cue sheet\r\n\
+             FILE \"audio.bin\" BINARY\r\n\
+             \x20 TRACK 01 AUDIO\r\n\
+             \x20   INDEX 01 00:00:00",
+        )
+        .unwrap();
+
+    assert_eq!(cue.toc().tracks().len(), 1);
+    assert_eq!(cue.toc().lead_out_start(), msf("00:02:10"));
+    assert_eq!(
+        cue.write_cue_sheet().lines().next(),
+        Some("FILE \"audio.bin\" BINARY")
+    );
+}
+
+#[test]
+fn regenerate() {
+    let img = TestImage::new("regenerate");
+
+    let mode1: Vec<u8> = (0..2048 * 10).map(|i| (i * 3) as u8).collect();
+    let mode2: Vec<u8> = (0..2336 * 10).map(|i| (i * 5) as u8).collect();
+
+    img.write("mode1.bin", &mode1);
+    img.write("mode2.bin", &mode2);
+
+    let mut cue = img
+        .cue(
+            "FILE \"mode1.bin\" BINARY\n\
+             \x20 TRACK 01 MODE1/2048\n\
+             \x20   INDEX 01 00:00:00\n\
+             FILE \"mode2.bin\" BINARY\n\
+             \x20 TRACK 02 MODE2/2336\n\
+             \x20   INDEX 00 00:00:00\n\
+             \x20   INDEX 01 00:00:02\n",
+        )
+        .unwrap();
+
+    assert!(cue.regenerate());
+
+    let s = cue.read_sector(pos("+00:02:03")).unwrap();
+
+    assert_eq!(s.data_2352()[16..2064], mode1[3 * 2048..4 * 2048]);
+    assert!(s.edc_valid());
+    assert!(s.validate_position().is_ok());
+
+    let s = cue.read_sector(pos("+00:02:13")).unwrap();
+
+    assert_eq!(s.data_2352()[16..], mode2[3 * 2336..4 * 2336]);
+    assert!(s.validate_position().is_ok());
+
+    cue.set_regenerate(false);
+
+    for &(p, start, end) in &[("+00:02:03", 16, 2064), ("+00:02:13", 16, 2352)] {
+        let s = cue.read_sector(pos(p)).unwrap();
+
+        assert!(s.data_2352()[..start].iter().all(|&b| b == 0));
+        assert!(s.data_2352()[end..].iter().all(|&b| b == 0));
+    }
+
+    let s = cue.read_sector(pos("+00:02:03")).unwrap();
+    assert_eq!(s.data_2352()[16..2064], mode1[3 * 2048..4 * 2048]);
+    // The ECC region stays empty
+    assert!(!s.edc_valid());
+
+    // Make sure that reusing a sector doesn't leak the previous contents
+    let mut sector = cue.read_sector(pos("+00:02:13")).unwrap();
+    cue.read_sector_into(pos("+00:02:03"), &mut sector).unwrap();
+    assert!(sector == s);
+}
+
+#[test]
+fn expected_bin_size() {
+    let img = TestImage::new("expected_bin_size");
+
+    let audio = audio_bin(20);
+    let data = vec![0; 2048 * 7];
+
+    img.write("audio.bin", &audio);
+    img.write("data.bin", &data);
+
+    let cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   PREGAP 00:00:02\n\
+             \x20   INDEX 01 00:00:05\n\
+             \x20 TRACK 03 AUDIO\n\
+             \x20   INDEX 00 00:00:10\n\
+             \x20   INDEX 01 00:00:12\n\
+             \x20   POSTGAP 00:00:03\n\
+             FILE \"data.bin\" BINARY\n\
+             \x20 TRACK 04 MODE1/2048\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    assert_eq!(cue.expected_bin_size(0), audio.len() as u64);
+    assert_eq!(cue.expected_bin_size(1), data.len() as u64);
+    assert_eq!(cue.expected_bin_size(2), 0);
+}
+
+#[test]
+fn raw_subchannel() {
+    let img = TestImage::new("raw_subchannel");
+
+    let audio = audio_bin(10);
+
+    let subchannels: Vec<[u8; 96]> = (0..10u32)
+        .map(|n| {
+            let qdata = QData::Mode1 {
+                track: Bcd::ONE,
+                index: Bcd::ONE,
+                track_msf: Msf::from_sector_index(n).unwrap(),
+                disc_msf: Msf::from_sector_index(150 + n).unwrap(),
+            };
+
+            let mut raw = Q::from_qdata_mode1(qdata, AdrControl::AUDIO).to_raw_interleaved();
+
+            for (i, r) in raw.iter_mut().enumerate() {
+                // P is set for the first sector only, R-W contains a recognizable pattern
+                if n == 0 {
+                    *r |= 0x80;
+                }
+
+                *r |= (n as usize * 7 + i) as u8 & 0x3f;
+            }
+
+            // Corrupt the Q CRC of sector 5
+            if n == 5 {
+                raw[95] ^= 0x40;
+            }
+
+            raw
+        })
+        .collect();
+
+    let mut bin = Vec::new();
+    for (sector, sub) in audio.chunks(2352).zip(subchannels.iter()) {
+        bin.extend_from_slice(sector);
+        bin.extend_from_slice(sub);
+    }
+
+    img.write("audio.bin", &bin);
+
+    let sheet = "FILE \"audio.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n";
+
+    // Without the option the BIN is misinterpreted
+    assert!(img.cue(sheet).is_err());
+
+    let options = CueOptions {
+        raw_subchannel: true,
+        ..CueOptions::default()
+    };
+
+    let mut cue = img.cue_with_options(sheet, options).unwrap();
+
+    assert_eq!(cue.toc().tracks()[0].length, msf("00:00:10"));
+    assert_eq!(cue.expected_bin_size(0), bin.len() as u64);
+
+    for n in 0..10 {
+        let p = DiscPosition::Program(Msf::from_sector_index(150 + n as u32).unwrap());
+
+        let s = cue.read_sector(p).unwrap();
+
+        assert_eq!(s.data_2352()[..], audio[n * 2352..(n + 1) * 2352]);
+        assert_eq!(
+            s.raw_subchannel(),
+            Some(&RawSubchannel::new(subchannels[n]))
+        );
+
+        let raw = s.data_2448();
+
+        if n == 5 {
+            // The Q subchannel was regenerated
+            assert_ne!(raw[2352..], subchannels[n][..]);
+        } else {
+            assert_eq!(raw[2352..], subchannels[n][..]);
+            assert_eq!(*s.q(), Q::from_raw_interleaved(subchannels[n]).unwrap());
+        }
+
+        assert_eq!(cue.read_subq(p).unwrap(), *s.q());
+
+        let sub = s.raw_subchannel().unwrap();
+
+        assert_eq!(sub.p(), if n == 0 { [0xff; 12] } else { [0; 12] });
+        assert_eq!(sub.rw()[0], (n * 7) as u8 & 0x3f);
+    }
+}
+
+#[test]
+fn subchannel_track_types() {
+    let img = TestImage::new("subchannel_track_types");
+
+    let mut bin = Vec::new();
+    let mut subchannels = Vec::new();
+
+    for n in 0..20u32 {
+        let (track, format, index) = if n < 10 {
+            (Bcd::ONE, TrackFormat::Mode1, n)
+        } else {
+            (Bcd::from_binary(2).unwrap(), TrackFormat::Mode2Xa, n - 10)
+        };
+
+        let disc_msf = Msf::from_sector_index(150 + n).unwrap();
+
+        let q = Q::from_qdata_mode1(
+            QData::Mode1 {
+                track,
+                index: Bcd::ONE,
+                track_msf: Msf::from_sector_index(index).unwrap(),
+                disc_msf,
+            },
+            AdrControl::DATA,
+        );
+
+        let mut sector = Sector::empty(q.clone(), format).unwrap();
+        sector.data_2352_mut()[100] = n as u8;
+        sector.write_edc_ecc();
+
+        // Give the stored subchannel a recognizable R-W pattern
+        let mut sub = q.to_raw_interleaved();
+        for (i, b) in sub.iter_mut().enumerate() {
+            *b |= (n as usize + i) as u8 & 0x3f;
+        }
+
+        bin.extend_from_slice(sector.data_2352());
+        bin.extend_from_slice(&sub);
+        subchannels.push(sub);
+    }
+
+    img.write("disc.bin", &bin);
+
+    let mut cue = img
+        .cue(
+            "FILE \"disc.bin\" BINARY\n\
+             \x20 TRACK 01 MODE1/2448\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 MODE2/2448\n\
+             \x20   INDEX 01 00:00:10\n",
+        )
+        .unwrap();
+
+    assert_eq!(cue.expected_bin_size(0), 20 * 2448);
+    assert_eq!(cue.toc().tracks()[0].format, TrackFormat::Mode1);
+    assert_eq!(cue.toc().tracks()[1].format, TrackFormat::Mode2Xa);
+    assert_eq!(cue.toc().tracks()[1].start, msf("00:02:10"));
+
+    for n in 0..20 {
+        let p = DiscPosition::Program(Msf::from_sector_index(150 + n as u32).unwrap());
+
+        let s = cue.read_sector(p).unwrap();
+
+        assert_eq!(s.data_2352()[..], bin[n * 2448..n * 2448 + 2352]);
+        assert!(s.edc_valid());
+        assert_eq!(s.data_2448()[2352..], subchannels[n][..]);
+        assert_eq!(*s.q(), Q::from_raw_interleaved(subchannels[n]).unwrap());
+        assert_eq!(cue.read_subq(p).unwrap(), *s.q());
+    }
+
+    assert!(cue.write_cue_sheet().contains("TRACK 02 MODE2/2448"));
+}
+
+#[test]
+fn read_sectors() {
+    let img = TestImage::new("read_sectors");
+
+    let mode1: Vec<u8> = (0..2048 * 600).map(|i| (i * 7 + i / 2048) as u8).collect();
+
+    img.write("mode1.bin", &mode1);
+    img.write("audio.bin", &audio_bin(500));
+
+    let mut cue = img
+        .cue(
+            "FILE \"mode1.bin\" BINARY\n\
+             \x20 TRACK 01 MODE1/2048\n\
+             \x20   INDEX 01 00:00:00\n\
+             FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   PREGAP 00:02:00\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 03 AUDIO\n\
+             \x20   INDEX 00 00:03:00\n\
+             \x20   INDEX 01 00:03:10\n",
+        )
+        .unwrap();
+
+    // Run covering the end of the lead-in, every track, both kinds of pregap and the start of
+    // the lead-out
+    let start = pos("<99:59:70");
+    let count = 5 + 150 + 600 + 150 + 500 + 10;
+
+    let bulk = cue.read_sectors(start, count).unwrap();
+
+    assert_eq!(bulk.len(), count as usize);
+
+    for (n, s) in bulk.iter().enumerate() {
+        let p = start.checked_add_sectors(n as u32).unwrap();
+
+        let expected = cue.read_sector(p).unwrap();
+
+        assert!(*s == expected, "Mismatch at {}", p);
+    }
+
+    assert_eq!(bulk[155].data_2352()[16..2064], mode1[..2048]);
+    assert!(bulk[155].edc_valid());
+
+    // Huge counts fail once we run past the end of the disc instead of preallocating everything
+    let last = DiscPosition::Program(cue.toc().lead_out_end() - Msf::from_sector_index(1).unwrap());
+
+    assert!(cue.read_sectors(last, u32::MAX).is_err());
+
+    // Same thing with the default implementation of `Image::read_sectors`
+    struct Wrapper<'a>(&'a mut Cue);
+
+    impl<'a> Image for Wrapper<'a> {
+        fn image_format(&self) -> String {
+            self.0.image_format()
+        }
+
+        fn read_sector(&mut self, position: DiscPosition) -> CdResult<Sector> {
+            self.0.read_sector(position)
+        }
+
+        fn toc(&self) -> &Toc {
+            self.0.toc()
+        }
+    }
+
+    assert!(Wrapper(&mut cue).read_sectors(last, u32::MAX).is_err());
+}
+
+#[test]
+fn limits() {
+    let img = TestImage::new("limits");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let mut sheet = "FILE \"audio.bin\" BINARY\n\
+                     \x20 TRACK 01 AUDIO\n"
+        .to_string();
+
+    for _ in 0..20_000 {
+        sheet.push_str("    INDEX 01 00:00:00\n");
+    }
+
+    match img.cue(&sheet) {
+        Err(CdError::BadImage { desc, .. }) => assert!(desc.starts_with("Too many indices")),
+        _ => panic!("Absurd number of indices accepted"),
+    }
+
+    let sheet = "FILE \"audio.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n\
+                 \x20 TRACK 02 AUDIO\n\
+                 \x20   INDEX 01 00:00:05\n";
+
+    assert!(img.cue(sheet).is_ok());
+
+    // Track 1's implicit pregap, plus one index per track
+    let options = CueOptions {
+        max_indices: 2,
+        ..CueOptions::default()
+    };
+
+    assert!(matches!(
+        img.cue_with_options(sheet, options),
+        Err(CdError::BadImage { .. })
+    ));
+
+    let options = CueOptions {
+        max_bin_size: 10 * 2352 - 1,
+        ..CueOptions::default()
+    };
+
+    assert!(matches!(
+        img.cue_with_options(sheet, options),
+        Err(CdError::BadImage { .. })
+    ));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_read_sector() {
+    use async_image::{AsyncImage, BlockingImage};
+    use tokio::runtime;
+
+    let img = TestImage::new("async_read_sector");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let sheet = "FILE \"audio.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n";
+
+    let mut cue = img.cue(sheet).unwrap();
+    let adapter = BlockingImage::new(img.cue(sheet).unwrap());
+
+    assert!(adapter.toc() == cue.toc());
+    assert_eq!(adapter.image_format(), "Async(CUE)");
+
+    let rt = runtime::Builder::new_current_thread().build().unwrap();
+
+    for p in &[
+        "+00:01:00",
+        "+00:02:00",
+        "+00:02:09",
+        "+00:02:10",
+        "<99:59:74",
+    ] {
+        let p = pos(p);
+
+        let sector = rt.block_on(adapter.read_sector(p)).unwrap();
+
+        assert!(sector == cue.read_sector(p).unwrap());
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_read_sector() {
+    let img = TestImage::new("mmap_read_sector");
+
+    img.write("a.bin", &audio_bin(20));
+    img.write("b.bin", &audio_bin(15));
+
+    let sheet = "FILE \"a.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n\
+                 FILE \"b.bin\" BINARY\n\
+                 \x20 TRACK 02 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n";
+
+    let options = CueOptions {
+        mmap: true,
+        ..CueOptions::default()
+    };
+
+    let mut mapped = img.cue_with_options(sheet, options).unwrap();
+    let mut file = img.cue(sheet).unwrap();
+
+    // Out of order reads across both BIN files
+    for p in &[
+        "+00:02:19",
+        "+00:02:00",
+        "+00:02:27",
+        "+00:02:07",
+        "+00:02:34",
+        "+00:02:20",
+    ] {
+        let p = pos(p);
+
+        assert!(mapped.read_sector(p).unwrap() == file.read_sector(p).unwrap());
+    }
+
+    let a = mapped.read_sectors(pos("+00:02:15"), 10).unwrap();
+    let b = file.read_sectors(pos("+00:02:15"), 10).unwrap();
+
+    assert!(a == b);
+}
+
+#[test]
+fn rem_metadata() {
+    let img = TestImage::new("rem_metadata");
+
+    img.write("audio.bin", &audio_bin(30));
+
+    let cue = img
+        .cue(
+            "REM GENRE \"Video Game\"\n\
+             REM DATE 1995\n\
+             REM REPLAYGAIN_ALBUM_GAIN -6.50 dB\n\
+             REM COMMENT\n\
+             FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   REM REPLAYGAIN_TRACK_GAIN +1.25 dB\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   rem replaygain_track_gain -7.89\n\
+             \x20   INDEX 01 00:00:10\n\
+             \x20 TRACK 03 AUDIO\n\
+             \x20   REM REPLAYGAIN_TRACK_GAIN loud\n\
+             \x20   INDEX 01 00:00:20\n",
+        )
+        .unwrap();
+
+    let t = |n| Bcd::from_binary(n).unwrap();
+
+    assert_eq!(cue.rem("GENRE"), Some("Video Game"));
+    assert_eq!(cue.rem("date"), Some("1995"));
+    assert_eq!(cue.rem("REPLAYGAIN_ALBUM_GAIN"), Some("-6.50 dB"));
+    assert_eq!(cue.rem("COMMENT"), None);
+    assert_eq!(cue.rem("REPLAYGAIN_TRACK_GAIN"), None);
+
+    assert_eq!(
+        cue.track_rem(t(1), "REPLAYGAIN_TRACK_GAIN"),
+        Some("+1.25 dB")
+    );
+    assert_eq!(cue.track_rem(t(1), "GENRE"), None);
+
+    assert_eq!(cue.track_replay_gain(t(1)), Some(1.25));
+    assert_eq!(cue.track_replay_gain(t(2)), Some(-7.89));
+    assert_eq!(cue.track_replay_gain(t(3)), None);
+    assert_eq!(cue.track_replay_gain(t(4)), None);
+}
+
+#[test]
+fn subq_frames() {
+    let img = TestImage::new("subq_frames");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    cue.set_lead_out_length(msf("00:00:20"));
+
+    let (mut lead_in, mut program, mut lead_out) = (0, 0, 0);
+    let mut prev: Option<Q> = None;
+
+    for q in cue.subq_frames() {
+        let q = q.unwrap();
+
+        if q.is_lead_in() {
+            assert_eq!((program, lead_out), (0, 0));
+            lead_in += 1;
+        } else if q.is_lead_out() {
+            lead_out += 1;
+        } else {
+            assert_eq!(lead_out, 0);
+            program += 1;
+        }
+
+        // The absolute MSF increases by one every frame, except when we move from the lead-in to
+        // the program area
+        if let Some(prev) = prev {
+            if prev.is_lead_in() == q.is_lead_in() {
+                assert_eq!(prev.amsf().next(), Some(q.amsf()));
+            }
+        }
+
+        prev = Some(q);
+    }
+
+    // From 97:30:00 to 99:59:74
+    assert_eq!(lead_in, 2 * 60 * 75 + 30 * 75);
+    // 2 second pregap followed by the track
+    assert_eq!(program, 150 + 10);
+    assert_eq!(lead_out, 20);
+}
+
+#[test]
+fn classify() {
+    let img = TestImage::new("classify");
+
+    img.write("audio.bin", &audio_bin(30));
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:00:10\n\
+             \x20   INDEX 01 00:00:12\n\
+             \x20   INDEX 02 00:00:20\n",
+        )
+        .unwrap();
+
+    let t1 = Bcd::ONE;
+    let t2 = Bcd::from_binary(2).unwrap();
+    let i1 = Bcd::ONE;
+    let i2 = Bcd::from_binary(2).unwrap();
+
+    for &(p, kind) in &[
+        ("<99:59:74", PositionKind::LeadIn),
+        ("<97:30:00", PositionKind::LeadIn),
+        ("+00:00:00", PositionKind::Pregap { track: t1 }),
+        ("+00:01:74", PositionKind::Pregap { track: t1 }),
+        (
+            "+00:02:00",
+            PositionKind::Track {
+                track: t1,
+                index: i1,
+            },
+        ),
+        (
+            "+00:02:09",
+            PositionKind::Track {
+                track: t1,
+                index: i1,
+            },
+        ),
+        ("+00:02:10", PositionKind::Pregap { track: t2 }),
+        ("+00:02:11", PositionKind::Pregap { track: t2 }),
+        (
+            "+00:02:12",
+            PositionKind::Track {
+                track: t2,
+                index: i1,
+            },
+        ),
+        (
+            "+00:02:19",
+            PositionKind::Track {
+                track: t2,
+                index: i1,
+            },
+        ),
+        (
+            "+00:02:20",
+            PositionKind::Track {
+                track: t2,
+                index: i2,
+            },
+        ),
+        (
+            "+00:02:29",
+            PositionKind::Track {
+                track: t2,
+                index: i2,
+            },
+        ),
+        ("+00:02:30", PositionKind::LeadOut),
+        ("+00:10:00", PositionKind::LeadOut),
+    ] {
+        assert_eq!(cue.classify(pos(p)).unwrap(), kind, "{}", p);
+    }
+}
+
+#[test]
+fn sessions() {
+    let img = TestImage::new("sessions");
+
+    img.write("audio.bin", &audio_bin(20));
+    img.write("data.bin", &vec![0; 2352 * 10]);
+
+    let cue = img
+        .cue(
+            "REM SESSION 01\n\
+             FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:00:10\n\
+             \x20   POSTGAP 00:00:02\n\
+             REM SESSION 02\n\
+             FILE \"data.bin\" BINARY\n\
+             \x20 TRACK 03 MODE1/2352\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    for &(track, session) in &[(1, 1), (2, 1), (3, 2)] {
+        let track = Bcd::from_binary(track).unwrap();
+
+        assert_eq!(cue.track_session(track).unwrap(), session);
+    }
+
+    for i in cue.indices.indices() {
+        let expected = if i.track() == Bcd::from_binary(3).unwrap() {
+            2
+        } else {
+            1
+        };
+
+        assert_eq!(i.session(), expected);
+    }
+
+    // Without sessions the indices are in session 0 but the tracks are reported in session 1
+    let cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    assert_eq!(cue.track_session(Bcd::ONE).unwrap(), 1);
+    assert!(cue.indices.indices().iter().all(|i| i.session() == 0));
+
+    for sheet in &[
+        // Decreasing session number
+        "REM SESSION 02\n\
+         FILE \"audio.bin\" BINARY\n\
+         \x20 TRACK 01 AUDIO\n\
+         \x20   INDEX 01 00:00:00\n\
+         REM SESSION 01\n",
+        // Track before the first session
+        "FILE \"audio.bin\" BINARY\n\
+         \x20 TRACK 01 AUDIO\n\
+         \x20   INDEX 01 00:00:00\n\
+         REM SESSION 02\n",
+        "REM SESSION foo\n",
+    ] {
+        assert!(img.cue(sheet).is_err());
+    }
+
+    // Regular comments are still ignored
+    assert!(img
+        .cue(
+            "REM SESSIONS ARE NOT SUPPORTED\n\
+             REM GENRE Rock\n\
+             FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .is_ok());
+}
+
+#[test]
+fn export_track_wav() {
+    let img = TestImage::new("export_track_wav");
+
+    let audio = audio_bin(20);
+    img.write("audio.bin", &audio);
+    img.write("data.bin", &vec![0; 2352 * 10]);
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:00:15\n\
+             FILE \"data.bin\" BINARY\n\
+             \x20 TRACK 03 MODE1/2352\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    let mut wav = Vec::new();
+
+    cue.export_track_wav(Bcd::from_binary(2).unwrap(), &mut wav)
+        .unwrap();
+
+    let u16_at = |o: usize| u16::from_le_bytes([wav[o], wav[o + 1]]);
+    let u32_at = |o: usize| u32::from_le_bytes([wav[o], wav[o + 1], wav[o + 2], wav[o + 3]]);
+
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(u32_at(4) as usize, wav.len() - 8);
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32_at(16), 16);
+    assert_eq!(u16_at(20), 1);
+    assert_eq!(u16_at(22), 2);
+    assert_eq!(u32_at(24), 44_100);
+    assert_eq!(u32_at(28), 176_400);
+    assert_eq!(u16_at(32), 4);
+    assert_eq!(u16_at(34), 16);
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(u32_at(40), 5 * 2352);
+
+    assert_eq!(wav.len(), 44 + 5 * 2352);
+    assert_eq!(wav[44..], audio[15 * 2352..]);
+
+    assert!(matches!(
+        cue.export_track_wav(Bcd::from_binary(3).unwrap(), &mut Vec::new()),
+        Err(CdError::BadFormat)
+    ));
+}
+
+#[test]
+fn gap_placement() {
+    use wav::{export_track, WavOptions};
+    use GapPlacement;
+
+    let img = TestImage::new("gap_placement");
+
+    img.write("audio.bin", &audio_bin(40));
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:00:10\n\
+             \x20   INDEX 01 00:00:13\n\
+             \x20 TRACK 03 AUDIO\n\
+             \x20   PREGAP 00:00:02\n\
+             \x20   INDEX 01 00:00:30\n",
+        )
+        .unwrap();
+
+    let t1 = Bcd::ONE;
+    let t2 = Bcd::from_binary(2).unwrap();
+    let t3 = Bcd::from_binary(3).unwrap();
+
+    assert_eq!(cue.track_pregap(t1).unwrap(), msf("00:02:00"));
+    assert_eq!(cue.track_pregap(t2).unwrap(), msf("00:00:03"));
+    assert_eq!(cue.track_pregap(t3).unwrap(), msf("00:00:02"));
+
+    for &(track, placement, start, end) in &[
+        (t1, GapPlacement::Omit, "00:02:00", "00:02:10"),
+        (t1, GapPlacement::Append, "00:02:00", "00:02:13"),
+        (t1, GapPlacement::Prepend, "00:02:00", "00:02:10"),
+        (t2, GapPlacement::Omit, "00:02:13", "00:02:30"),
+        (t2, GapPlacement::Append, "00:02:13", "00:02:32"),
+        (t2, GapPlacement::Prepend, "00:02:10", "00:02:30"),
+        (t3, GapPlacement::Omit, "00:02:32", "00:02:42"),
+        (t3, GapPlacement::Append, "00:02:32", "00:02:42"),
+        (t3, GapPlacement::Prepend, "00:02:30", "00:02:42"),
+    ] {
+        assert_eq!(
+            cue.track_range(track, placement).unwrap(),
+            (msf(start), msf(end))
+        );
+    }
+
+    let mut export = |placement| {
+        let mut wav = Vec::new();
+
+        let options = WavOptions {
+            gap_placement: placement,
+            ..WavOptions::default()
+        };
+
+        export_track(&mut cue, t2, &mut wav, &options).unwrap();
+
+        (wav.len() - 44) / 2352
+    };
+
+    let append = export(GapPlacement::Append);
+    let prepend = export(GapPlacement::Prepend);
+
+    // Track 2 gets track 3's gap in one case and its own in the other
+    assert_eq!(append, 19);
+    assert_eq!(prepend, 20);
+    assert_eq!(export(GapPlacement::Omit), 17);
+}
+
+#[test]
+fn catalog_isrc() {
+    let img = TestImage::new("catalog_isrc");
+
+    img.write("audio.bin", &audio_bin(20));
+
+    let sheet = "CATALOG 0724383452320\n\
+                 FILE \"audio.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   FLAGS DCP\n\
+                 \x20   ISRC USEM39100045\n\
+                 \x20   INDEX 01 00:00:00\n\
+                 \x20 TRACK 02 AUDIO\n\
+                 \x20   INDEX 01 00:00:10\n";
+
+    let cue = img.cue(sheet).unwrap();
+
+    let t = |n| Bcd::from_binary(n).unwrap();
+
+    assert_eq!(cue.catalog(), Some("0724383452320"));
+    assert_eq!(cue.isrc(t(1)), Some("USEM39100045"));
+    assert_eq!(cue.isrc(t(2)), None);
+
+    let written = cue.write_cue_sheet();
+    assert_eq!(written, sheet);
+
+    let reparsed = img.cue(&written).unwrap();
+
+    assert_eq!(reparsed.catalog(), cue.catalog());
+    assert_eq!(reparsed.isrc(t(1)), cue.isrc(t(1)));
+    assert_eq!(reparsed.isrc(t(2)), None);
+
+    for bad in &[
+        "CATALOG 123\n",
+        "CATALOG 0724383452320\nCATALOG 0724383452320\n",
+        "ISRC USEM39100045\n",
+        "FILE \"audio.bin\" BINARY\n  TRACK 01 AUDIO\n    ISRC usem3910004\n",
+    ] {
+        assert!(
+            matches!(img.cue(bad), Err(CdError::ParseError { .. })),
+            "{}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn index_past_end_of_file() {
+    let img = TestImage::new("index_past_end_of_file");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let err = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:00:05\n\
+             \x20   INDEX 01 00:00:12\n",
+        )
+        .err()
+        .unwrap();
+
+    match err {
+        CdError::IndexPastEndOfFile {
+            line,
+            track,
+            index,
+            offset,
+            bin_sectors,
+            ..
+        } => {
+            assert_eq!(line, 6);
+            assert_eq!(track, Bcd::from_binary(2).unwrap());
+            assert_eq!(index, Bcd::ONE);
+            assert_eq!(offset, msf("00:00:12"));
+            assert_eq!(bin_sectors, 10);
+        }
+        ref e => panic!("Unexpected error {:?}", e),
+    }
+
+    assert!(err.to_string().starts_with(
+        "Index 01 of track 02 at 00:00:12 is past the end of the BIN file (10 sectors)"
+    ));
+}
+
+#[test]
+fn sector_at_radius() {
+    use disc_position::Radius;
+
+    let img = TestImage::new("sector_at_radius");
+
+    img.write("audio.bin", &audio_bin(1000));
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    cue.set_lead_out_length(msf("00:00:20"));
+
+    let mid = msf("00:08:00");
+    let r = DiscPosition::Program(mid).disc_radius().unwrap();
+
+    let expected = DiscPosition::from_radius(r).unwrap();
+    let sector = cue.sector_at_radius(r).unwrap();
+
+    assert!(sector == cue.read_sector(expected).unwrap());
+
+    // The radius has micrometer precision, that's less than a turn of the spiral
+    let delta = sector.q().amsf().sector_index() as i32 - mid.sector_index() as i32;
+    assert!(delta.abs() < 10, "{}", delta);
+
+    let lead_in = cue.sector_at_radius(Radius::from_micros(0)).unwrap();
+    assert!(lead_in.q().is_lead_in());
+    assert!(lead_in == cue.read_sector(DiscPosition::INNERMOST).unwrap());
+
+    let last = cue.toc().lead_out_end() - msf("00:00:01");
+
+    for &micros in &[40_000, 59_000, 60_000] {
+        let sector = cue.sector_at_radius(Radius::from_micros(micros)).unwrap();
+
+        assert!(sector.q().is_lead_out());
+        assert_eq!(sector.q().amsf(), last);
+    }
+}
+
+#[test]
+fn media_catalog_number() {
+    let img = TestImage::new("media_catalog_number");
+
+    img.write("audio.bin", &audio_bin(10));
+
+    let t1 = Bcd::ONE;
+
+    let mut cue = img
+        .cue(
+            "CATALOG 0724383452320\n\
+             FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   ISRC USEM39100045\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    assert_eq!(
+        cue.media_catalog_number().unwrap().as_deref(),
+        Some("0724383452320")
+    );
+    assert_eq!(cue.track_isrc(t1).unwrap().as_deref(), Some("USEM39100045"));
+    assert!(matches!(
+        cue.track_isrc(Bcd::from_binary(2).unwrap()),
+        Err(CdError::BadTrack)
+    ));
+
+    // Without raw subchannel data there's nothing else to look at
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    assert_eq!(cue.media_catalog_number().unwrap(), None);
+    assert_eq!(cue.track_isrc(t1).unwrap(), None);
+
+    // Store the MCN in sector 3 and the ISRC in sector 7 of the raw subchannel data
+    let interleave = |subq: [u8; 12]| {
+        let mut raw = [0u8; 96];
+
+        for (bit, r) in raw.iter_mut().enumerate() {
+            if subq[bit / 8] & (1 << (7 - (bit & 7))) != 0 {
+                *r = 0x40;
+            }
+        }
+
+        raw
+    };
+
+    let audio = audio_bin(10);
+    let mut bin = Vec::new();
+
+    for (n, sector) in audio.chunks(2352).enumerate() {
+        let qdata = QData::Mode1 {
+            track: t1,
+            index: Bcd::ONE,
+            track_msf: Msf::from_sector_index(n as u32).unwrap(),
+            disc_msf: Msf::from_sector_index(150 + n as u32).unwrap(),
+        };
+
+        let aframe = (150 + n) as u8 % 75;
+
+        let sub = match n {
+            3 => interleave(mcn_q_raw("0724383452320", AdrControl::AUDIO, aframe).unwrap()),
+            7 => interleave(isrc_q_raw("USEM39100045", AdrControl::AUDIO, aframe).unwrap()),
+            _ => Q::from_qdata_mode1(qdata, AdrControl::AUDIO).to_raw_interleaved(),
+        };
+
+        bin.extend_from_slice(sector);
+        bin.extend_from_slice(&sub);
+    }
+
+    img.write("audio.bin", &bin);
+
+    let options = CueOptions {
+        raw_subchannel: true,
+        ..CueOptions::default()
+    };
+
+    let mut cue = img
+        .cue_with_options(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+            options,
+        )
+        .unwrap();
+
+    assert_eq!(
+        cue.media_catalog_number().unwrap().as_deref(),
+        Some("0724383452320")
+    );
+    assert_eq!(cue.track_isrc(t1).unwrap().as_deref(), Some("USEM39100045"));
+}
+
+#[test]
+fn identification_q() {
+    let img = TestImage::new("identification_q");
+
+    img.write("audio.bin", &audio_bin(320));
+
+    let sheet = "CATALOG 0724383452320\n\
+                 FILE \"audio.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   ISRC USEM39100045\n\
+                 \x20   INDEX 01 00:00:00\n\
+                 \x20 TRACK 02 AUDIO\n\
+                 \x20   ISRC GBAYE0000001\n\
+                 \x20   INDEX 00 00:02:40\n\
+                 \x20   INDEX 01 00:02:60\n";
+
+    let mut cue = img.cue(sheet).unwrap();
+
+    let p = |n: u32| DiscPosition::Program(Msf::from_sector_index(n).unwrap());
+
+    // Raw Q frame of the sector at absolute position `n`, as found in `Sector::data_2448`
+    let subq = |cue: &mut Cue, n: u32| {
+        let raw = cue.read_sector(p(n)).unwrap().data_2448();
+
+        RawSubchannel::new(*array_ref![raw, 2352, 96]).q()
+    };
+
+    let mcn = Some("0724383452320".to_string());
+    let isrc1 = Some("USEM39100045".to_string());
+    let isrc2 = Some("GBAYE0000001".to_string());
+
+    for &n in &[200, 300, 400] {
+        assert_eq!(mcn_from_q_raw(&subq(&mut cue, n)), mcn);
+    }
+
+    assert_eq!(isrc_from_q_raw(&subq(&mut cue, 250)), isrc1);
+    assert_eq!(isrc_from_q_raw(&subq(&mut cue, 450)), isrc2);
+
+    // The Q data still contains the position
+    let s = cue.read_sector(p(200)).unwrap();
+    assert_eq!(s.q().amsf(), Msf::from_sector_index(200).unwrap());
+    assert_eq!(
+        mcn_from_q_raw(&s.raw_subchannel().unwrap().q()),
+        mcn.clone()
+    );
+
+    // Nothing in track 2's pregap, other sectors contain the position
+    assert!(cue.read_sector(p(350)).unwrap().raw_subchannel().is_none());
+    assert_eq!(subq(&mut cue, 350), cue.read_subq(p(350)).unwrap().to_raw());
+    assert_eq!(subq(&mut cue, 201), cue.read_subq(p(201)).unwrap().to_raw());
+
+    // Same thing for the other ways of reading sectors
+    let mut sector = cue.read_sector(p(201)).unwrap();
+    cue.read_sector_into(p(300), &mut sector).unwrap();
+    assert_eq!(mcn_from_q_raw(&sector.raw_subchannel().unwrap().q()), mcn);
+
+    let bulk = cue.read_sectors(p(150), 320).unwrap();
+    for (n, s) in bulk.iter().enumerate() {
+        let expected = cue.read_sector(p(150 + n as u32)).unwrap();
+
+        assert_eq!(s.data_2448()[..], expected.data_2448()[..]);
+    }
+
+    let mut raw = Vec::new();
+    cue.export_raw_2448(&mut raw).unwrap();
+
+    let frame = &raw[(250 - 150) * 2448..][..2448];
+    let sub = RawSubchannel::new(*array_ref![frame, 2352, 96]);
+    assert_eq!(isrc_from_q_raw(&sub.q()), isrc1);
+
+    // The generic lookup finds them as well
+    assert_eq!(::metadata::media_catalog_number(&mut cue).unwrap(), mcn);
+    assert_eq!(
+        ::metadata::track_isrc(&mut cue, Bcd::from_binary(2).unwrap()).unwrap(),
+        isrc2
+    );
+
+    // Without CATALOG and ISRC the Q subchannel only contains the position
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    for &n in &[200, 250] {
+        assert_eq!(subq(&mut cue, n), cue.read_subq(p(n)).unwrap().to_raw());
+    }
+}
+
+#[test]
+fn read_ahead() {
+    use std::io;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// In-memory BIN file counting the reads
+    struct CountingBin {
+        data: Vec<u8>,
+        reads: Arc<AtomicU32>,
+    }
+
+    impl BinProvider for CountingBin {
+        fn open(&mut self, _name: &str) -> io::Result<u64> {
+            Ok(self.data.len() as u64)
+        }
+
+        fn read_exact_at(&mut self, _bin: u32, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+
+            let offset = offset as usize;
+            buf.copy_from_slice(&self.data[offset..offset + buf.len()]);
+
+            Ok(())
+        }
+    }
+
+    let sheet = "FILE \"audio.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n\
+                 \x20 TRACK 02 AUDIO\n\
+                 \x20   PREGAP 00:00:05\n\
+                 \x20   INDEX 01 00:00:40\n\
+                 \x20   INDEX 02 00:00:60\n";
+
+    let load = || {
+        let reads = Arc::new(AtomicU32::new(0));
+
+        let bin = CountingBin {
+            data: audio_bin(100),
+            reads: reads.clone(),
+        };
+
+        let cue = Cue::from_parts(sheet.as_bytes(), Box::new(bin)).unwrap();
+        reads.store(0, Ordering::SeqCst);
+
+        (cue, reads)
+    };
+
+    let (mut reference, reference_reads) = load();
+    let (mut cue, cue_reads) = load();
+
+    cue.set_read_ahead(16);
+
+    let bin_reads = || cue_reads.load(Ordering::SeqCst);
+
+    let p = |n: u32| DiscPosition::Program(Msf::from_sector_index(150 + n).unwrap());
+
+    // 100 stored sectors and the 5 sectors of generated pregap
+    for n in 0..105 {
+        assert!(cue.read_sector(p(n)).unwrap() == reference.read_sector(p(n)).unwrap());
+    }
+
+    assert_eq!(reference_reads.load(Ordering::SeqCst), 100);
+    // Sequential reads are buffered, but the buffer never crosses an index boundary
+    assert!(bin_reads() < 15, "{}", bin_reads());
+
+    // Random accesses only read what's needed
+    let reads = bin_reads();
+
+    for &n in &[50, 10, 90, 11, 3] {
+        assert!(cue.read_sector(p(n)).unwrap() == reference.read_sector(p(n)).unwrap());
+    }
+
+    assert_eq!(bin_reads() - reads, 5);
+
+    // Same thing with `read_sector_into`
+    let reads = bin_reads();
+    let mut sector = reference.read_sector(p(0)).unwrap();
+
+    for n in 4..40 {
+        cue.read_sector_into(p(n), &mut sector).unwrap();
+
+        assert!(sector == reference.read_sector(p(n)).unwrap());
+    }
+
+    assert!(bin_reads() - reads < 5, "{}", bin_reads() - reads);
+}
+
+#[test]
+fn short_tracks() {
+    let img = TestImage::new("short_tracks");
+
+    let bin = audio_bin(20);
+
+    img.write("audio.bin", &bin);
+
+    // Track 2 is a single sector, track 3 is empty
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:00:10\n\
+             \x20 TRACK 03 AUDIO\n\
+             \x20   INDEX 01 00:00:11\n\
+             \x20 TRACK 04 AUDIO\n\
+             \x20   INDEX 01 00:00:11\n",
+        )
+        .unwrap();
+
+    assert_eq!(cue.warnings().len(), 1);
+    assert!(cue.warnings()[0].to_string().contains("Track 03 is empty"));
+
+    let tracks = cue.toc().tracks().to_vec();
+    let lengths: Vec<_> = tracks.iter().map(|t| t.length.sector_index()).collect();
+
+    assert_eq!(lengths, vec![10, 1, 0, 9]);
+
+    let p = tracks[1].disc_position(Msf::ZERO).unwrap();
+    let sector = cue.read_sector(p).unwrap();
+
+    assert_eq!(sector.data_2352()[..], bin[10 * 2352..11 * 2352]);
+    assert!(matches!(
+        tracks[1].disc_position(msf("00:00:01")),
+        Err(CdError::EndOfTrack)
+    ));
+
+    // Nothing can be read from the empty track, its position belongs to track 4
+    assert!(matches!(
+        tracks[2].disc_position(Msf::ZERO),
+        Err(CdError::EndOfTrack)
+    ));
+    assert_eq!(tracks[2].start, tracks[3].start);
+
+    let t4 = Bcd::from_binary(4).unwrap();
+    let sector = cue
+        .read_sector(DiscPosition::Program(tracks[3].start))
+        .unwrap();
+
+    match *sector.q().data() {
+        QData::Mode1 { track, index, .. } => assert_eq!((track, index), (t4, Bcd::ONE)),
+        ref d => panic!("Unexpected Q data {:?}", d),
+    }
+    assert_eq!(sector.data_2352()[..], bin[11 * 2352..12 * 2352]);
+
+    // The empty track survives a round-trip through the cue sheet writer
+    let reparsed = img.cue(&cue.write_cue_sheet()).unwrap();
+
+    assert!(reparsed.toc().diff(cue.toc()).is_empty());
+}
+
+#[test]
+fn from_parts() {
+    use std::collections::HashMap;
+    use std::io;
+
+    /// BIN files stored in memory
+    struct MemoryBins {
+        files: HashMap<String, Vec<u8>>,
+        opened: Vec<String>,
+    }
+
+    impl BinProvider for MemoryBins {
+        fn open(&mut self, name: &str) -> io::Result<u64> {
+            match self.files.get(name) {
+                Some(f) => {
+                    self.opened.push(name.to_string());
+                    Ok(f.len() as u64)
+                }
+                None => Err(io::Error::new(io::ErrorKind::NotFound, "no such BIN")),
+            }
+        }
+
+        fn read_exact_at(&mut self, bin: u32, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+            let data = &self.files[&self.opened[bin as usize]];
+            let offset = offset as usize;
+
+            match data.get(offset..offset + buf.len()) {
+                Some(d) => {
+                    buf.copy_from_slice(d);
+                    Ok(())
+                }
+                None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "short read")),
+            }
+        }
+    }
+
+    let a = audio_bin(20);
+    let b: Vec<u8> = audio_bin(10).iter().map(|v| !v).collect();
+
+    let mut files = HashMap::new();
+    files.insert("a.bin".to_string(), a.clone());
+    files.insert("b.bin".to_string(), b.clone());
+
+    let bins = MemoryBins {
+        files,
+        opened: Vec::new(),
+    };
+
+    let sheet = b"FILE \"a.bin\" BINARY\n\
+                  \x20 TRACK 01 AUDIO\n\
+                  \x20   INDEX 01 00:00:00\n\
+                  FILE \"b.bin\" BINARY\n\
+                  \x20 TRACK 02 AUDIO\n\
+                  \x20   INDEX 01 00:00:00\n";
+
+    let mut cue = Cue::from_parts(sheet, Box::new(bins)).unwrap();
+
+    assert_eq!(cue.toc().tracks().len(), 2);
+    assert_eq!(cue.toc().lead_out_start(), msf("00:02:30"));
+
+    let sector = cue.read_sector(pos("+00:02:05")).unwrap();
+    assert_eq!(sector.data_2352()[..], a[5 * 2352..6 * 2352]);
+
+    let sector = cue.read_sector(pos("+00:02:23")).unwrap();
+    assert_eq!(sector.data_2352()[..], b[3 * 2352..4 * 2352]);
+
+    let sectors = cue.read_sectors(pos("+00:02:00"), 30).unwrap();
+    for (n, s) in sectors.iter().enumerate() {
+        let expected = if n < 20 {
+            &a[n * 2352..(n + 1) * 2352]
+        } else {
+            &b[(n - 20) * 2352..(n - 19) * 2352]
+        };
+
+        assert_eq!(s.data_2352()[..], *expected);
+    }
+
+    let missing = MemoryBins {
+        files: HashMap::new(),
+        opened: Vec::new(),
+    };
+
+    assert!(matches!(
+        Cue::from_parts(sheet, Box::new(missing)),
+        Err(CdError::ParseError { .. })
+    ));
+}
+
+#[test]
+fn override_track_format() {
+    let img = TestImage::new("override_track_format");
+
+    let mut data = vec![0; 2352 * 10];
+
+    // Mode 2 header for the first sector
+    data[1..11].copy_from_slice(&[0xff; 10]);
+    data[12..16].copy_from_slice(&[0x00, 0x02, 0x00, 0x02]);
+
+    img.write("data.bin", &data);
+    img.write("iso.bin", &vec![0; 2048 * 10]);
+
+    let sheet = "FILE \"data.bin\" BINARY\n\
+                 \x20 TRACK 01 MODE2/2352\n\
+                 \x20   INDEX 01 00:00:00\n\
+                 FILE \"iso.bin\" BINARY\n\
+                 \x20 TRACK 02 MODE1/2048\n\
+                 \x20   INDEX 01 00:00:00\n";
+
+    let mut cue = img.cue(sheet).unwrap();
+
+    let sector = cue.read_sector(pos("+00:02:00")).unwrap();
+    assert_eq!(sector.format(), TrackFormat::Mode2Xa);
+    assert!(sector.mode2_xa_subheader().is_ok());
+
+    cue.override_track_format(Bcd::ONE, TrackFormat::Mode2CdI)
+        .unwrap();
+
+    assert_eq!(cue.toc().tracks()[0].format, TrackFormat::Mode2CdI);
+    assert_eq!(cue.toc().session_format(), SessionFormat::Cdi);
+
+    let sector = cue.read_sector(pos("+00:02:00")).unwrap();
+    assert_eq!(sector.format(), TrackFormat::Mode2CdI);
+    assert!(matches!(
+        sector.mode2_xa_subheader(),
+        Err(CdError::BadFormat)
+    ));
+
+    let two = Bcd::from_binary(2).unwrap();
+
+    assert!(matches!(
+        cue.override_track_format(two, TrackFormat::Audio),
+        Err(CdError::BadFormat)
+    ));
+    assert_eq!(cue.toc().tracks()[1].format, TrackFormat::Mode1);
+
+    let three = Bcd::from_binary(3).unwrap();
+
+    assert!(matches!(
+        cue.override_track_format(three, TrackFormat::Mode1),
+        Err(CdError::BadTrack)
+    ));
+}
+
+#[test]
+fn lenient_msf() {
+    let img = TestImage::new("lenient_msf");
+
+    img.write("audio.bin", &audio_bin(200));
+
+    let sheet = "FILE \"audio.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n\
+                 \x20 TRACK 02 AUDIO\n\
+                 \x20   INDEX 01 00:00:80\n";
+
+    assert!(matches!(img.cue(sheet), Err(CdError::ParseError { .. })));
+
+    let options = CueOptions {
+        lenient_msf: true,
+        ..CueOptions::default()
+    };
+
+    let cue = img.cue_with_options(sheet, options.clone()).unwrap();
+
+    assert_eq!(cue.warnings().len(), 1);
+
+    let two = Bcd::from_binary(2).unwrap();
+
+    // 80 frames is 1 second and 5 frames
+    assert_eq!(cue.toc().track(two).unwrap().start, msf("00:03:05"));
+    assert_eq!(cue.track_layout(two).unwrap().byte_offset, 80 * 2352);
+
+    // Garbage is still rejected
+    let sheet = "FILE \"audio.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:0x:00\n";
+
+    assert!(matches!(
+        img.cue_with_options(sheet, options),
+        Err(CdError::ParseError { .. })
+    ));
+}
+
+#[test]
+fn export_raw_2448() {
+    let img = TestImage::new("export_raw_2448");
+
+    let audio = audio_bin(10);
+    img.write("audio.bin", &audio);
+    img.write("data.bin", &vec![0; 2352 * 5]);
+
+    let mut cue = img
+        .cue(
+            "FILE \"audio.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             FILE \"data.bin\" BINARY\n\
+             \x20 TRACK 02 MODE1/2352\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    let mut raw = Vec::new();
+
+    cue.export_raw_2448(&mut raw).unwrap();
+
+    assert_eq!(raw.len(), 15 * 2448);
+
+    for (n, frame) in raw.chunks(2448).enumerate() {
+        let sector = cue
+            .read_sector(DiscPosition::Program(
+                msf("00:02:00") + Msf::from_sector_index(n as u32).unwrap(),
+            ))
+            .unwrap();
+
+        assert_eq!(frame[..2352], sector.data_2352()[..]);
+
+        let q = Q::from_raw_interleaved(*array_ref![frame, 2352, 96]).unwrap();
+        assert_eq!(&q, sector.q());
+    }
+
+    assert_eq!(raw[..2352], audio[..2352]);
+
+    let q = Q::from_raw_interleaved(*array_ref![raw, 2352, 96]).unwrap();
+    assert_eq!(q.amsf(), msf("00:02:00"));
+    assert!(q.is_audio());
+}
+
+#[test]
+fn infer_track_formats() {
+    let img = TestImage::new("infer_track_formats");
+
+    let mut bin = Vec::new();
+
+    // Two tracks of Mode 1 sectors, only the second one is labeled correctly
+    for n in 0..20u32 {
+        let track = Bcd::from_binary(1 + n as u8 / 10).unwrap();
+
+        let q = Q::from_qdata_mode1(
+            QData::Mode1 {
+                track,
+                index: Bcd::ONE,
+                track_msf: Msf::from_sector_index(n % 10).unwrap(),
+                disc_msf: Msf::from_sector_index(150 + n).unwrap(),
+            },
+            AdrControl::DATA,
+        );
+
+        let mut sector = Sector::empty(q, TrackFormat::Mode1).unwrap();
+        sector.data_2352_mut()[100] = n as u8;
+        sector.write_edc_ecc();
+
+        assert_eq!(sector.detect_mode(), Some(TrackFormat::Mode1));
+
+        bin.extend_from_slice(sector.data_2352());
+    }
+
+    bin.extend_from_slice(&audio_bin(10));
+
+    img.write("disc.bin", &bin);
+
+    let sheet = "FILE \"disc.bin\" BINARY\n\
+                 \x20 TRACK 01 MODE2/2352\n\
+                 \x20   INDEX 01 00:00:00\n\
+                 \x20 TRACK 02 MODE1/2352\n\
+                 \x20   INDEX 01 00:00:10\n\
+                 \x20 TRACK 03 AUDIO\n\
+                 \x20   INDEX 01 00:00:20\n";
+
+    let mut cue = img.cue(sheet).unwrap();
+
+    assert_eq!(cue.toc().tracks()[0].format, TrackFormat::Mode2Xa);
+
+    let sector = cue.read_sector(pos("+00:02:03")).unwrap();
+    assert_eq!(sector.format(), TrackFormat::Mode2Xa);
+    assert_eq!(sector.detect_mode(), Some(TrackFormat::Mode1));
+
+    cue.infer_track_formats().unwrap();
+
+    let formats: Vec<_> = cue.toc().tracks().iter().map(|t| t.format).collect();
+
+    assert_eq!(
+        formats,
+        [TrackFormat::Mode1, TrackFormat::Mode1, TrackFormat::Audio]
+    );
+
+    let sector = cue.read_sector(pos("+00:02:03")).unwrap();
+    assert_eq!(sector.format(), TrackFormat::Mode1);
+    assert!(sector.edc_valid());
+    assert_eq!(sector.data_2352()[100], 3);
+
+    let sector = cue.read_sector(pos("+00:02:25")).unwrap();
+    assert_eq!(sector.detect_mode(), Some(TrackFormat::Audio));
+}
+
+#[test]
+fn toc_override() {
+    let img = TestImage::new("toc_override");
+
+    let bin = audio_bin(20);
+    img.write("a.bin", &bin);
+
+    let cue = img
+        .cue(
+            "CATALOG 0724383452320\n\
+             FILE \"a.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   ISRC USEM39100045\n\
+             \x20   INDEX 01 00:00:10\n",
+        )
+        .unwrap();
+
+    // Pretend that the disc contains a single track
+    let track = Track {
+        track: Bcd::ONE,
+        format: TrackFormat::Audio,
+        start: msf("00:02:00"),
+        length: msf("00:00:20"),
+        control: AdrControl::AUDIO,
+    };
+
+    let toc = Toc::from_tracks(vec![track], msf("00:02:20")).unwrap();
+
+    let mut image = TocOverride::new(cue, toc);
+
+    assert_eq!(image.image_format(), "CUE (ToC override)");
+    assert_eq!(image.toc().tracks().len(), 1);
+    assert_eq!(image.toc().tracks()[0].length, msf("00:00:20"));
+
+    // The data still comes from the real image
+    let sector = image.read_sector(pos("+00:02:15")).unwrap();
+    assert_eq!(sector.data_2352(), &bin[15 * 2352..16 * 2352]);
+
+    match *image.read_subq(pos("+00:02:15")).unwrap().data() {
+        QData::Mode1 { track, .. } => assert_eq!(track, Bcd::from_binary(2).unwrap()),
+        _ => panic!("Unexpected Q data"),
+    }
+
+    // The metadata comes from the real image as well
+    assert_eq!(
+        image.media_catalog_number().unwrap().as_deref(),
+        Some("0724383452320")
+    );
+    assert_eq!(
+        image
+            .track_isrc(Bcd::from_binary(2).unwrap())
+            .unwrap()
+            .as_deref(),
+        Some("USEM39100045")
+    );
+
+    let cue = image.into_inner();
+    assert_eq!(cue.toc().tracks().len(), 2);
+}
+
+#[test]
+fn read_error_policy() {
+    let img = TestImage::new("read_error_policy");
+
+    let payload: Vec<u8> = (0..2048 * 10).map(|i| (i / 7) as u8).collect();
+    img.write("data.bin", &payload);
+
+    let mut cue = img
+        .cue(
+            "FILE \"data.bin\" BINARY\n\
+             \x20 TRACK 01 MODE1/2048\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    assert_eq!(cue.read_error_policy(), ReadErrorPolicy::Fail);
+
+    // Truncate the BIN file after the image has been loaded
+    img.write("data.bin", &payload[..2048 * 8]);
+
+    let last = pos("+00:02:09");
+
+    assert!(matches!(cue.read_sector(last), Err(CdError::IoError(_))));
+    assert!(cue.read_sectors(pos("+00:02:00"), 10).is_err());
+
+    let sector = cue.read_sector(pos("+00:02:07")).unwrap();
+    assert!(!sector.is_synthesized());
+    assert_eq!(&sector.data_2352()[16..2064], &payload[7 * 2048..8 * 2048]);
+
+    cue.set_read_error_policy(ReadErrorPolicy::Zero);
+
+    let sector = cue.read_sector(last).unwrap();
+    assert!(sector.is_synthesized());
+    assert!(sector.edc_valid());
+    assert!(sector.data_2352()[16..2064].iter().all(|&b| b == 0));
+    assert!(sector.validate_position().is_ok());
+    assert!(sector.q() == &cue.read_subq(last).unwrap());
+
+    match *sector.q().data() {
+        QData::Mode1 { disc_msf, .. } => assert_eq!(disc_msf, msf("00:02:09")),
+        _ => panic!("Unexpected Q data"),
+    }
+
+    // Reusing the sector clears the flag
+    let mut reused = sector;
+    cue.read_sector_into(pos("+00:02:00"), &mut reused).unwrap();
+    assert!(!reused.is_synthesized());
+
+    let sectors = cue.read_sectors(pos("+00:02:00"), 10).unwrap();
+    assert_eq!(sectors.len(), 10);
+
+    for (i, s) in sectors.iter().enumerate() {
+        assert_eq!(s.is_synthesized(), i >= 8);
+    }
+
+    cue.set_read_error_policy(ReadErrorPolicy::Skip);
+
+    let sector = cue.read_sector(last).unwrap();
+    assert!(sector.is_synthesized());
+    assert!(sector.data_2352().iter().all(|&b| b == 0));
+    assert!(sector.validate_position().is_err());
+}
+
+#[test]
+fn validate() {
+    let img = TestImage::new("validate");
+
+    img.write("a.bin", &audio_bin(20));
+
+    let sheet = "FILE \"a.bin\" BINARY\n\
+                 \x20 TRACK 01 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n\
+                 \x20 TRACK 02 AUDIO\n\
+                 \x20   INDEX 01 00:99:00\n\
+                 \x20   INDEX 01 00:00:10\n\
+                 \x20   FOO BAR\n\
+                 \x20   PREGAP 00:02:00 00:01:00\n\
+                 FILE \"missing.bin\" BINARY\n\
+                 \x20 TRACK 03 AUDIO\n\
+                 \x20   INDEX 01 00:00:00\n";
+
+    let path = img.write("disc.cue", sheet.as_bytes());
+
+    // Loading the image stops at the first error
+    match Cue::new(&path) {
+        Err(CdError::ParseError { line, .. }) => assert_eq!(line, 5),
+        _ => panic!("Expected a parse error"),
+    }
+
+    let lines: Vec<u32> = Cue::validate(&path)
+        .iter()
+        .map(|e| match *e {
+            CdError::ParseError { line, .. } => line,
+            ref e => panic!("Unexpected error {}", e),
+        })
+        .collect();
+
+    assert_eq!(lines, vec![5, 7, 8, 9]);
+
+    let path = img.write(
+        "disc.cue",
+        b"FILE \"a.bin\" BINARY\n\
+          \x20 TRACK 01 AUDIO\n\
+          \x20   INDEX 01 00:00:00\n",
+    );
+
+    assert!(Cue::validate(&path).is_empty());
+
+    // Errors found in the index layout are only reported if the cue sheet could be parsed
+    let path = img.write(
+        "disc.cue",
+        b"FILE \"a.bin\" BINARY\n\
+          \x20 TRACK 01 AUDIO\n\
+          \x20   INDEX 00 00:00:00\n",
+    );
+
+    let errors = Cue::validate(&path);
+    assert_eq!(errors.len(), 1);
+    assert!(Cue::new(&path).is_err());
+
+    let errors = Cue::validate(img.dir.join("does-not-exist.cue"));
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0], CdError::IoError(_)));
+}
+
+#[test]
+fn read_track_sector() {
+    let img = TestImage::new("read_track_sector");
+
+    img.write("a.bin", &audio_bin(30));
+
+    let mut cue = img
+        .cue(
+            "FILE \"a.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:00:10\n\
+             \x20   INDEX 01 00:00:12\n",
+        )
+        .unwrap();
+
+    let two = Bcd::from_binary(2).unwrap();
+
+    let relative = cue.read_track_sector(two, msf("00:00:05")).unwrap();
+    let absolute = cue.read_sector(pos("+00:02:17")).unwrap();
+
+    assert!(relative == absolute);
+
+    let first = cue.read_track_sector(Bcd::ONE, Msf::ZERO).unwrap();
+    assert!(first == cue.read_sector(pos("+00:02:00")).unwrap());
+
+    // Track 2 is 18 sectors long
+    assert!(cue.read_track_sector(two, msf("00:00:17")).is_ok());
+    assert!(matches!(
+        cue.read_track_sector(two, msf("00:00:18")),
+        Err(CdError::EndOfTrack)
+    ));
+    assert!(matches!(
+        cue.read_track_sector(Bcd::from_binary(3).unwrap(), Msf::ZERO),
+        Err(CdError::BadTrack)
+    ));
+}
+
+#[test]
+fn unquoted_file_name() {
+    let img = TestImage::new("unquoted_file_name");
+
+    img.write("My Game.bin", &audio_bin(10));
+    img.write("Track  2.bin", &audio_bin(5));
+
+    let cue = img
+        .cue(
+            "FILE My Game.bin BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n\
+             FILE Track  2.bin binary\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    assert_eq!(cue.toc().tracks().len(), 2);
+    assert_eq!(cue.toc().lead_out_start(), msf("00:02:15"));
+    assert_eq!(cue.warnings().len(), 2);
+
+    // Quoted names are unaffected
+    let cue = img
+        .cue(
+            "FILE \"My Game.bin\" BINARY\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+    assert!(cue.warnings().is_empty());
+
+    // Extra parameters are still rejected
+    for sheet in &[
+        "FILE \"My Game.bin\" BINARY EXTRA\n",
+        "FILE \"My\" Game.bin BINARY\n",
+        "FILE My Game.bin\n",
+        "FILE My Game.bin BINARY EXTRA\n",
+    ] {
+        assert!(matches!(
+            img.cue(sheet),
+            Err(CdError::ParseError { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+//! Cue sheet generation
+
+use std::fmt::Write;
+
+use super::{Cue, CueFileType, Storage};
+use internal::Index;
+use msf::Msf;
+use Bcd;
+
+/// Implementation of `Cue::write_cue_sheet`
+pub fn write_cue_sheet(cue: &Cue) -> String {
+    let indices = cue.indices.indices();
+
+    let mut sheet = String::new();
+    let mut cur_bin = None;
+    let mut cur_track = None;
+
+    if let Some(ref mcn) = cue.catalog {
+        let _ = writeln!(sheet, "CATALOG {}", mcn);
+    }
+
+    for (pos, index) in indices.iter().enumerate() {
+        // Length of this index, used for the generated pregaps and postgaps
+        let length = match indices.get(pos + 1) {
+            Some(next) => next.msf() - index.msf(),
+            None => cue.indices.lead_out() - index.msf(),
+        };
+
+        if cur_track != Some(index.track()) {
+            cur_track = Some(index.track());
+
+            // The FILE command must come before the TRACK it contains, even if the track starts
+            // with a generated pregap
+            if let Some((bin, ty, file_type)) = track_bin(&indices[pos..], index.track()) {
+                if cur_bin != Some(bin) {
+                    cur_bin = Some(bin);
+
+                    let file_type = match file_type {
+                        CueFileType::Binary => "BINARY",
+                        CueFileType::Motorola => "MOTOROLA",
+                    };
+
+                    let _ = writeln!(
+                        sheet,
+                        "FILE \"{}\" {}",
+                        cue.bin_names[bin as usize], file_type
+                    );
+                }
+
+                let _ = writeln!(sheet, "  TRACK {} {}", index.track(), ty.keyword());
+            }
+
+            let ctrl = index.control();
+
+            let flags: Vec<&str> = [
+                (ctrl.digital_copy_permitted(), "DCP"),
+                (ctrl.four_channel_audio(), "4CH"),
+                (ctrl.pre_emphasis(), "PRE"),
+            ]
+            .iter()
+            .filter(|&&(set, _)| set)
+            .map(|&(_, f)| f)
+            .collect();
+
+            if !flags.is_empty() {
+                let _ = writeln!(sheet, "    FLAGS {}", flags.join(" "));
+            }
+
+            if let Some(isrc) = cue.isrc(index.track()) {
+                let _ = writeln!(sheet, "    ISRC {}", isrc);
+            }
+        }
+
+        match *index.private() {
+            Storage::Bin(_, offset, ty, _) => {
+                let msf = Msf::from_sector_index(
+                    (offset / u64::from(ty.sector_size(cue.raw_subchannel))) as u32,
+                )
+                .unwrap_or(Msf::ZERO);
+
+                let _ = writeln!(sheet, "    INDEX {} {}", index.index(), msf);
+            }
+            // Track 01's pregap is implied by the format unless it's stored
+            Storage::PreGap if pos == 0 => (),
+            Storage::PreGap => {
+                let _ = writeln!(sheet, "    PREGAP {}", length);
+            }
+            Storage::Generated { .. } => {
+                let _ = writeln!(sheet, "    POSTGAP {}", length);
+            }
+        }
+    }
+
+    sheet
+}
+
+/// Returns the BIN index, track type and file type of the first index of `track` stored in a BIN
+/// file at the start of `indices`
+fn track_bin(
+    indices: &[Index<Storage>],
+    track: Bcd,
+) -> Option<(u32, super::CueTrackType, CueFileType)> {
+    indices
+        .iter()
+        .take_while(|i| i.track() == track)
+        .filter_map(|i| match *i.private() {
+            Storage::Bin(bin, _, ty, file_type) => Some((bin, ty, file_type)),
+            _ => None,
+        })
+        .next()
+}
@@ -2,6 +2,7 @@
 //! (including lead-out) of the dics
 
 use std::cmp;
+use std::convert::TryFrom;
 use std::fmt;
 use std::ops;
 use std::str::FromStr;
@@ -54,6 +55,39 @@ impl DiscPosition {
         matches!(self, DiscPosition::LeadIn(_))
     }
 
+    /// Returns the logical block address of this position. LBA 0 is the sector at `+00:02:00`
+    /// (the start of the first track's INDEX 01 on most discs) and positions before that,
+    /// including the lead-in, have negative LBAs. The last sector of the lead-in is at LBA -151.
+    ///
+    /// All positions have a valid LBA so this can't fail.
+    pub fn to_lba(self) -> i32 {
+        match self {
+            DiscPosition::Program(msf) => msf.sector_index() as i32 - LBA_OFFSET,
+            DiscPosition::LeadIn(msf) => {
+                let before_program = (Msf::MAX.sector_index() - msf.sector_index()) as i32 + 1;
+
+                -LBA_OFFSET - before_program
+            }
+        }
+    }
+
+    /// Returns the position of the sector at logical block address `lba`, see
+    /// `DiscPosition::to_lba`. Returns `None` if `lba` is outside of the disc.
+    pub fn from_lba(lba: i32) -> Option<DiscPosition> {
+        if lba >= -LBA_OFFSET {
+            let index = (lba + LBA_OFFSET) as u32;
+
+            Msf::from_sector_index(index).map(DiscPosition::Program)
+        } else {
+            let before_program = (-LBA_OFFSET - lba) as u32;
+
+            (Msf::MAX.sector_index() + 1)
+                .checked_sub(before_program)
+                .and_then(Msf::from_sector_index)
+                .map(DiscPosition::LeadIn)
+        }
+    }
+
     /// Returns the position of the sector after `self` or `None` if we've reached 99:59:74.
     pub fn next(self) -> Option<DiscPosition> {
         let n = match self {
@@ -70,6 +104,15 @@ impl DiscPosition {
         Some(n)
     }
 
+    /// Computes `self + nsectors`, moving from the lead-in to the program area if necessary.
+    /// Returns `None` if overflow occurred.
+    pub fn checked_add_sectors(self, nsectors: u32) -> Option<DiscPosition> {
+        i32::try_from(nsectors)
+            .ok()
+            .and_then(|n| self.to_lba().checked_add(n))
+            .and_then(DiscPosition::from_lba)
+    }
+
     /// Computes `self - rhs`, returning `None` if overflow occurred
     pub fn checked_sub(self, rhs: Msf) -> Option<DiscPosition> {
         match self {
@@ -218,6 +261,29 @@ impl DiscPosition {
             Radius::from_millis(r0 + t * thickness)
         })
     }
+
+    /// Returns the approximate angular position of this sector along the spiral in radians, in
+    /// the range `[0, 2π)`. The angle is 0 at the start of the lead-in and increases in the
+    /// reading direction.
+    pub fn angle(self) -> CdResult<f32> {
+        use std::f32::consts::PI;
+
+        let turns = self.disc_turns()?;
+
+        Ok(turns.fract() * 2. * PI)
+    }
+
+    /// Returns the approximate cartesian coordinates of this sector in millimeters, with the
+    /// center of the disc at the origin and the start of the lead-in on the positive X axis. See
+    /// `DiscPosition::angle` and `DiscPosition::disc_radius`.
+    pub fn xy(self) -> CdResult<(f32, f32)> {
+        let turns = self.disc_turns()?;
+        let angle = self.angle()?;
+
+        let r = CD_LEAD_IN_RADIUS.to_millis() + turns * CD_PITCH_MM;
+
+        Ok((r * angle.cos(), r * angle.sin()))
+    }
 }
 
 impl fmt::Display for DiscPosition {
@@ -346,6 +412,9 @@ pub const CD_PROGRAM_RADIUS_MAX: Radius = Radius::from_micros(59_000);
 /// Length of a frame in mm. 16mm Assuming a standard scanning speed of 1.2m/s
 pub const CD_FRAME_LENGTH_MM: u32 = 16;
 
+/// Number of sectors between `+00:00:00` and LBA 0
+const LBA_OFFSET: i32 = 150;
+
 #[test]
 fn test_disc_turns() {
     use std::f32::consts::PI;
@@ -366,6 +435,39 @@ fn test_disc_turns() {
     }
 }
 
+#[test]
+fn test_angle() {
+    use std::f32::consts::PI;
+
+    // Angle difference in the range [-π, π)
+    let angle_diff = |a: f32, b: f32| (a - b + 3. * PI).rem_euclid(2. * PI) - PI;
+
+    let dp = DiscPosition::INNERMOST;
+    assert_eq!(dp.angle().unwrap(), 0.);
+    assert_eq!(dp.xy().unwrap(), (CD_LEAD_IN_RADIUS.to_millis(), 0.));
+
+    for p in &["<99:00:00", "+00:00:00", "+12:34:56", "+60:00:00"] {
+        let dp: DiscPosition = p.parse().unwrap();
+        let a = dp.angle().unwrap();
+
+        assert!((0. ..2. * PI).contains(&a));
+
+        // `offset_turns` rounds to the nearest sector, so we can be up to half a sector off
+        let r = dp.disc_radius().unwrap().to_millis();
+        let half_sector = (CD_FRAME_LENGTH_MM as f32 / 2.) / r;
+
+        for &n in &[1, 2, -1, 10] {
+            let a_n = dp.offset_turns(n).unwrap().angle().unwrap();
+
+            assert!(angle_diff(a, a_n).abs() <= half_sector);
+        }
+
+        // The coordinates are consistent with the radius
+        let (x, y) = dp.xy().unwrap();
+        assert!(((x * x + y * y).sqrt() - r).abs() < 0.001);
+    }
+}
+
 #[test]
 fn test_disc_radius() {
     // The standard states that the lead-in must start at a maximum radius of 23mm
@@ -586,3 +688,50 @@ fn disc_position_parse_bad() {
         assert!(s.parse::<DiscPosition>().is_err())
     }
 }
+
+#[test]
+fn lba() {
+    let to_test = &[
+        (0, "+00:02:00"),
+        (-150, "+00:00:00"),
+        (-149, "+00:00:01"),
+        (-151, "<99:59:74"),
+        (-226, "<99:58:74"),
+        (75 * 60, "+01:02:00"),
+        (-450_150, "<00:00:00"),
+        (449_849, "+99:59:74"),
+    ];
+
+    for &(lba, s) in to_test.iter() {
+        let dp: DiscPosition = s.parse().unwrap();
+
+        assert_eq!(dp.to_lba(), lba);
+        assert_eq!(DiscPosition::from_lba(lba), Some(dp));
+    }
+
+    assert_eq!(DiscPosition::from_lba(-450_151), None);
+    assert_eq!(DiscPosition::from_lba(449_850), None);
+}
+
+#[test]
+fn add_sectors() {
+    let to_test = &[
+        ("<99:59:70", 0, "<99:59:70"),
+        ("<99:59:70", 4, "<99:59:74"),
+        ("<99:59:70", 5, "+00:00:00"),
+        ("<99:59:70", 5 + 75 * 2, "+00:02:00"),
+        ("+00:01:74", 1, "+00:02:00"),
+    ];
+
+    for &(start, n, end) in to_test.iter() {
+        let start: DiscPosition = start.parse().unwrap();
+        let end: DiscPosition = end.parse().unwrap();
+
+        assert_eq!(start.checked_add_sectors(n), Some(end));
+    }
+
+    let last = DiscPosition::Program(Msf::MAX);
+
+    assert_eq!(last.checked_add_sectors(1), None);
+    assert_eq!(DiscPosition::ZERO.checked_add_sectors(u32::MAX), None);
+}
@@ -102,6 +102,7 @@ fn gen_luts() {
 }
 
 #[test]
+#[allow(clippy::clone_on_copy)]
 fn test_ecc_gen() {
     // Mode-1 sector
     let sector: [u8; 0x930] = [
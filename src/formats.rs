@@ -0,0 +1,171 @@
+//! Registry of the available image formats.
+//!
+//! Each format is identified by a name and a probe function. When opening an image the probe
+//! functions are called in registration order until one of them recognizes the file. The
+//! built-in formats are registered by `Registry::new`, users can add their own custom formats with
+//! `Registry::register`.
+
+use std::path::Path;
+
+use cue::Cue;
+use {CdError, CdResult, Image};
+
+/// Probe function for an image format. Should return `None` if the file at the given path is not
+/// handled by this format, otherwise it should attempt to load the image and return the result.
+pub type ProbeFn = fn(&Path) -> Option<CdResult<Box<dyn Image>>>;
+
+/// List of image formats that can be used to open an image
+pub struct Registry {
+    /// Name and probe function for every registered format
+    backends: Vec<(String, ProbeFn)>,
+}
+
+impl Registry {
+    /// Create a registry containing all the formats supported by this crate
+    pub fn new() -> Registry {
+        let mut registry = Registry::empty();
+
+        registry.register("CUE", probe_cue);
+        registry.register("ZIP", probe_zip);
+
+        registry
+    }
+
+    /// Create a registry without any format registered
+    pub fn empty() -> Registry {
+        Registry {
+            backends: Vec::new(),
+        }
+    }
+
+    /// Register a new format. If a format with the same `name` already exists it's replaced by
+    /// the new `probe`, otherwise it's added after all the existing formats.
+    pub fn register(&mut self, name: &str, probe: ProbeFn) {
+        match self.backends.iter_mut().find(|(n, _)| n == name) {
+            Some(b) => b.1 = probe,
+            None => self.backends.push((name.to_string(), probe)),
+        }
+    }
+
+    /// Returns an iterator over the names of the registered formats, in registration order
+    pub fn formats(&self) -> impl Iterator<Item = &str> {
+        self.backends.iter().map(|(n, _)| n.as_str())
+    }
+
+    /// Attempt to open the image at `path` with the first format that recognizes it. Returns
+    /// `CdError::Unsupported` if no format does.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> CdResult<Box<dyn Image>> {
+        let path = path.as_ref();
+
+        for (_, probe) in self.backends.iter() {
+            if let Some(r) = probe(path) {
+                return r;
+            }
+        }
+
+        Err(CdError::Unsupported)
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+/// Open the image at `path` using the formats supported by this crate. See `Registry::open`.
+pub fn open<P: AsRef<Path>>(path: P) -> CdResult<Box<dyn Image>> {
+    Registry::new().open(path)
+}
+
+/// Returns true if `path` has extension `ext` (ignoring ASCII case)
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case(ext))
+        .unwrap_or(false)
+}
+
+fn probe_cue(path: &Path) -> Option<CdResult<Box<dyn Image>>> {
+    if !has_extension(path, "cue") {
+        return None;
+    }
+
+    Some(Cue::new(path).map(|c| Box::new(c) as Box<dyn Image>))
+}
+
+fn probe_zip(path: &Path) -> Option<CdResult<Box<dyn Image>>> {
+    if !has_extension(path, "zip") {
+        return None;
+    }
+
+    Some(Cue::new_from_zip(path).map(|c| Box::new(c) as Box<dyn Image>))
+}
+
+#[test]
+fn custom_format() {
+    use bcd::Bcd;
+    use subchannel::AdrControl;
+    use {DiscPosition, Msf, Sector, Toc, Track, TrackFormat};
+
+    struct Dummy(Toc);
+
+    impl Image for Dummy {
+        fn image_format(&self) -> String {
+            "Dummy".to_string()
+        }
+
+        fn read_sector(&mut self, _: DiscPosition) -> CdResult<Sector> {
+            Err(CdError::Unsupported)
+        }
+
+        fn toc(&self) -> &Toc {
+            &self.0
+        }
+    }
+
+    fn probe_dummy(path: &Path) -> Option<CdResult<Box<dyn Image>>> {
+        if !has_extension(path, "dummy") {
+            return None;
+        }
+
+        let track = Track {
+            track: Bcd::ONE,
+            format: TrackFormat::Audio,
+            start: Msf::from_sector_index(150).unwrap(),
+            length: Msf::from_sector_index(1000).unwrap(),
+            control: AdrControl::AUDIO,
+        };
+
+        Some(Toc::new(vec![track]).map(|toc| Box::new(Dummy(toc)) as Box<dyn Image>))
+    }
+
+    let mut registry = Registry::new();
+
+    assert!(matches!(
+        registry.open("image.dummy"),
+        Err(CdError::Unsupported)
+    ));
+
+    registry.register("Dummy", probe_dummy);
+
+    assert_eq!(
+        registry.formats().collect::<Vec<_>>(),
+        vec!["CUE", "ZIP", "Dummy"]
+    );
+
+    let image = registry.open("image.DUMMY").unwrap();
+
+    assert_eq!(image.image_format(), "Dummy");
+    assert_eq!(image.toc().tracks().len(), 1);
+
+    // Built-in formats are still handled, even if this one fails because the file doesn't exist
+    assert!(matches!(
+        registry.open("does-not-exist.cue"),
+        Err(CdError::IoError(_))
+    ));
+    assert!(matches!(
+        registry.open("image.iso"),
+        Err(CdError::Unsupported)
+    ));
+}
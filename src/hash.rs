@@ -0,0 +1,81 @@
+//! Checksums of the disc contents, used to match images against dat files (redump, no-intro...)
+//!
+//! Unlike the CD-ROM EDC these use the standard CRC-32 (IEEE 802.3) polynomial and SHA-1.
+
+use crc32fast::Hasher as Crc32;
+use sha1::{Digest, Sha1};
+
+use msf::{Msf, MsfRange};
+use {Bcd, CdResult, DiscPosition, GapPlacement, Image};
+
+/// Feed the raw 2352 bytes of every sector in `[start, end)` to `f`
+fn stream_sectors<I, F>(image: &mut I, start: Msf, end: Msf, mut f: F) -> CdResult<()>
+where
+    I: Image + ?Sized,
+    F: FnMut(&[u8]),
+{
+    for msf in MsfRange::new(start, end) {
+        let sector = image.read_sector(DiscPosition::Program(msf))?;
+
+        f(sector.data_2352());
+    }
+
+    Ok(())
+}
+
+/// Returns the range of sectors hashed for `track`: its pregap (INDEX 00) is included, like in
+/// the per-track BIN files of redump dumps, so that the track ranges cover the whole disc range
+fn track_range<I: Image + ?Sized>(image: &mut I, track: Bcd) -> CdResult<(Msf, Msf)> {
+    image.track_range(track, GapPlacement::Prepend)
+}
+
+/// Returns the range of sectors hashed for the whole disc
+fn disc_range<I: Image + ?Sized>(image: &I) -> (Msf, Msf) {
+    let toc = image.toc();
+
+    (toc.tracks()[0].start, toc.lead_out_start())
+}
+
+fn crc32<I: Image + ?Sized>(image: &mut I, (start, end): (Msf, Msf)) -> CdResult<u32> {
+    let mut hasher = Crc32::new();
+
+    stream_sectors(image, start, end, |data| hasher.update(data))?;
+
+    Ok(hasher.finalize())
+}
+
+fn sha1<I: Image + ?Sized>(image: &mut I, (start, end): (Msf, Msf)) -> CdResult<[u8; 20]> {
+    let mut hasher = Sha1::new();
+
+    stream_sectors(image, start, end, |data| hasher.update(data))?;
+
+    Ok(hasher.finalize().into())
+}
+
+/// Implementation of `Image::track_crc32`
+pub(crate) fn track_crc32<I: Image + ?Sized>(image: &mut I, track: Bcd) -> CdResult<u32> {
+    let range = track_range(image, track)?;
+
+    crc32(image, range)
+}
+
+/// Implementation of `Image::track_sha1`
+pub(crate) fn track_sha1<I: Image + ?Sized>(image: &mut I, track: Bcd) -> CdResult<[u8; 20]> {
+    let range = track_range(image, track)?;
+
+    sha1(image, range)
+}
+
+/// Implementation of `Image::disc_crc32`
+pub(crate) fn disc_crc32<I: Image + ?Sized>(image: &mut I) -> CdResult<u32> {
+    let range = disc_range(image);
+
+    crc32(image, range)
+}
+
+/// Implementation of `Image::disc_sha1`
+pub(crate) fn disc_sha1<I: Image + ?Sized>(image: &mut I) -> CdResult<[u8; 20]> {
+    let range = disc_range(image);
+
+    sha1(image, range)
+}
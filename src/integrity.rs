@@ -0,0 +1,190 @@
+//! Disc image integrity checks
+
+use bcd::Bcd;
+use msf::{Msf, MsfRange};
+use {CdError, CdResult, DiscPosition, Image, Progress, ProgressPhase};
+
+/// Summary of the integrity of a disc image, built by `Image::integrity_report`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Total number of sectors checked
+    pub total_sectors: u32,
+    /// Per-track results, in the same order as the tracks in the ToC
+    pub tracks: Vec<TrackIntegrity>,
+    /// The first `IntegrityReport::MAX_PROBLEMS` problems encountered, in disc order
+    pub problems: Vec<(Msf, IntegrityProblem)>,
+}
+
+impl IntegrityReport {
+    /// Maximum number of entries in `IntegrityReport::problems`
+    pub const MAX_PROBLEMS: usize = 32;
+
+    /// Total number of sectors with an invalid EDC
+    pub fn edc_errors(&self) -> u32 {
+        self.tracks.iter().map(|t| t.edc_errors).sum()
+    }
+
+    /// Total number of sectors with an invalid CD-ROM header or a header MSF that doesn't match
+    /// the sector's position
+    pub fn header_errors(&self) -> u32 {
+        self.tracks.iter().map(|t| t.header_errors).sum()
+    }
+
+    /// Returns true if no problem was found
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    fn add_problem(&mut self, msf: Msf, problem: IntegrityProblem) {
+        if self.problems.len() < IntegrityReport::MAX_PROBLEMS {
+            self.problems.push((msf, problem));
+        }
+    }
+}
+
+/// Integrity results for a single track
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackIntegrity {
+    /// Track number
+    pub track: Bcd,
+    /// Number of sectors checked in this track
+    pub sectors: u32,
+    /// Number of sectors whose EDC doesn't match their contents
+    pub edc_errors: u32,
+    /// Number of sectors with an invalid CD-ROM header or a header MSF that doesn't match the
+    /// sector's position
+    pub header_errors: u32,
+}
+
+/// Problem found in a single sector
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegrityProblem {
+    /// The EDC doesn't match the contents of the sector
+    BadEdc,
+    /// The CD-ROM header couldn't be parsed (bad sync pattern, invalid BCD or mode)
+    BadHeader,
+    /// The MSF in the CD-ROM header doesn't match the position of the sector
+    HeaderMsfMismatch {
+        /// MSF found in the header
+        header: Msf,
+    },
+}
+
+/// Implementation of `Image::integrity_report`
+pub(crate) fn integrity_report<I: Image + ?Sized>(
+    image: &mut I,
+    mut progress: Option<&mut dyn FnMut(Progress)>,
+) -> CdResult<IntegrityReport> {
+    let tracks = image.toc().tracks().to_vec();
+    let lead_out = image.toc().lead_out_start();
+
+    let total = match tracks.first() {
+        Some(t) => lead_out.sector_index() - t.start.sector_index(),
+        None => 0,
+    };
+
+    let mut report = IntegrityReport {
+        total_sectors: 0,
+        tracks: Vec::with_capacity(tracks.len()),
+        problems: Vec::new(),
+    };
+
+    for (i, t) in tracks.iter().enumerate() {
+        let end = tracks.get(i + 1).map(|n| n.start).unwrap_or(lead_out);
+
+        let mut ti = TrackIntegrity {
+            track: t.track,
+            sectors: 0,
+            edc_errors: 0,
+            header_errors: 0,
+        };
+
+        for msf in MsfRange::new(t.start, end) {
+            let sector = image.read_sector(DiscPosition::Program(msf))?;
+
+            ti.sectors += 1;
+
+            if !sector.edc_valid() {
+                ti.edc_errors += 1;
+                report.add_problem(msf, IntegrityProblem::BadEdc);
+            }
+
+            if sector.format().is_cdrom() {
+                let problem = match sector.validate_position() {
+                    Ok(()) => None,
+                    Err(CdError::HeaderMsfMismatch { header, .. }) => {
+                        Some(IntegrityProblem::HeaderMsfMismatch { header })
+                    }
+                    Err(_) => Some(IntegrityProblem::BadHeader),
+                };
+
+                if let Some(p) = problem {
+                    ti.header_errors += 1;
+                    report.add_problem(msf, p);
+                }
+            }
+
+            if let Some(ref mut progress) = progress {
+                progress(Progress {
+                    phase: ProgressPhase::Verifying,
+                    current: report.total_sectors + ti.sectors,
+                    total,
+                });
+            }
+        }
+
+        report.total_sectors += ti.sectors;
+        report.tracks.push(ti);
+    }
+
+    Ok(report)
+}
+
+#[test]
+fn bad_edc() {
+    use subchannel::AdrControl;
+    use test_image::TestImage;
+    use {Track, TrackFormat};
+
+    let start = Msf::from_sector_index(150).unwrap();
+    let track = Track {
+        track: Bcd::ONE,
+        format: TrackFormat::Mode1,
+        start,
+        length: Msf::from_sector_index(100).unwrap(),
+        control: AdrControl::DATA,
+    };
+
+    let bad = Msf::from_sector_index(200).unwrap();
+
+    let mut image = TestImage::new(vec![track], |_, msf, sector| {
+        if msf == bad {
+            sector.data_2352_mut()[100] ^= 0x01;
+        }
+    });
+
+    let report = image.integrity_report().unwrap();
+
+    assert_eq!(report.total_sectors, 100);
+    assert_eq!(report.tracks.len(), 1);
+    assert_eq!(report.tracks[0].sectors, 100);
+    assert_eq!(report.edc_errors(), 1);
+    assert_eq!(report.header_errors(), 0);
+    assert_eq!(report.problems, vec![(bad, IntegrityProblem::BadEdc)]);
+    assert!(!report.is_ok());
+
+    let mut calls = Vec::new();
+
+    let with_progress = image
+        .integrity_report_with_progress(&mut |p| calls.push(p))
+        .unwrap();
+
+    assert_eq!(with_progress, report);
+    assert_eq!(calls.len(), 100);
+
+    for (i, p) in calls.iter().enumerate() {
+        assert_eq!(p.phase, ProgressPhase::Verifying);
+        assert_eq!(p.current, i as u32 + 1);
+        assert_eq!(p.total, 100);
+    }
+}
@@ -112,7 +112,7 @@ impl<T> cmp::Eq for Index<T> {}
 
 impl<T> cmp::PartialOrd for Index<T> {
     fn partial_cmp(&self, other: &Index<T>) -> Option<cmp::Ordering> {
-        self.sector_index.partial_cmp(&other.sector_index)
+        Some(self.cmp(other))
     }
 }
 
@@ -165,7 +165,9 @@ impl<T> IndexCache<T> {
             }
         }
 
-        // TODO: Add more validation here.
+        if let Err(desc) = validate_indices(&indices, lead_out) {
+            return Err(CdError::BadImage { path: file, desc });
+        }
 
         Ok(IndexCache {
             indices,
@@ -178,6 +180,11 @@ impl<T> IndexCache<T> {
         Msf::from_sector_index(self.lead_out).unwrap()
     }
 
+    /// Return all the indices, sorted by position on the disc
+    pub fn indices(&self) -> &[Index<T>] {
+        &self.indices
+    }
+
     /// Return a reference to the index at position `pos` or `None` if
     /// it's out of bounds
     pub fn get(&self, pos: usize) -> Option<&Index<T>> {
@@ -186,6 +193,8 @@ impl<T> IndexCache<T> {
 
     /// Locate the index directly before `msf` and return its position along with a reference to
     /// the `Index` struct. Returns `None` if the index is in the lead-out.
+    ///
+    /// If several indices start at `msf` (because of empty tracks) the last one is returned.
     pub fn find_index_for_msf(&self, msf: Msf) -> Option<(usize, &Index<T>)> {
         let sector = msf.sector_index();
 
@@ -193,32 +202,51 @@ impl<T> IndexCache<T> {
             return None;
         }
 
-        let pos = match self
+        // Position of the first index past `sector`. The first index is always at 00:00:00 so
+        // this can't be 0.
+        let pos = self
             .indices
-            .binary_search_by(|index| index.sector_index.cmp(&sector))
-        {
-            // The MSF matched an index exactly
-            Ok(i) => i,
-            // No exact match, the function returns the index of
-            // the first element greater than `sector` (on one
-            // past the end if no greater element is found).
-            Err(i) => i - 1,
-        };
+            .partition_point(|index| index.sector_index <= sector)
+            - 1;
 
         Some((pos, &self.indices[pos]))
     }
 
+    /// Change the format and control bits of all the indices of `track`. Returns
+    /// `CdError::BadTrack` if `track` doesn't exist.
+    pub fn set_track_format(
+        &mut self,
+        track: Bcd,
+        format: TrackFormat,
+        control: AdrControl,
+    ) -> CdResult<()> {
+        let mut found = false;
+
+        for index in self.indices.iter_mut().filter(|i| i.track == track) {
+            index.format = format;
+            index.control = control;
+            found = true;
+        }
+
+        if found {
+            Ok(())
+        } else {
+            Err(CdError::BadTrack)
+        }
+    }
+
     /// Locate `index` for `track` and return its position along with
     /// a reference to the `Index` struct.
     pub fn find_index_for_track(&self, track: Bcd, index: Bcd) -> CdResult<(usize, &Index<T>)> {
-        match self
+        // An index can be split into several entries (for instance if it's followed by a
+        // generated postgap), so we look for the first one
+        let i = self
             .indices
-            .binary_search_by(|idx| match idx.track().cmp(&track) {
-                cmp::Ordering::Equal => idx.index().cmp(&index),
-                o => o,
-            }) {
-            Ok(i) => Ok((i, &self.indices[i])),
-            Err(_) => Err(CdError::BadTrack),
+            .partition_point(|idx| (idx.track(), idx.index()) < (track, index));
+
+        match self.indices.get(i) {
+            Some(idx) if idx.track() == track && idx.index() == index => Ok((i, idx)),
+            _ => Err(CdError::BadTrack),
         }
     }
 
@@ -231,6 +259,9 @@ impl<T> IndexCache<T> {
     /// Return the length of the given track starting at INDEX 01, not
     /// counting the pregap. Also returns the position and a reference
     /// to the INDEX 01 for this track.
+    ///
+    /// The length is 0 for empty tracks, whose INDEX 01 is at the same position as the first index
+    /// of the following track.
     pub fn track_length(&self, track: Bcd) -> CdResult<(Msf, usize, &Index<T>)> {
         let (pos01, index01) = self.find_index01_for_track(track)?;
 
@@ -274,6 +305,8 @@ impl<T> IndexCache<T> {
         let track_count = self.indices.last().map(|i| i.track).unwrap_or(Bcd::ZERO);
 
         let mut tracks = Vec::with_capacity(track_count.binary() as usize);
+        let mut sessions = Vec::with_capacity(track_count.binary() as usize);
+
         for b in 1..=99 {
             let track_no = Bcd::from_binary(b).unwrap();
 
@@ -288,13 +321,103 @@ impl<T> IndexCache<T> {
                     };
 
                     tracks.push(track);
+                    // Session 0 means that the image doesn't describe the sessions
+                    sessions.push(cmp::max(idx.session(), 1));
                 }
                 // Last track
                 Err(_) => break,
             }
         }
 
-        Toc::new(tracks)
+        let mut toc = Toc::new(tracks)?;
+
+        toc.set_sessions(sessions);
+
+        Ok(toc)
+    }
+}
+
+/// Check the structure of the sorted index list `indices`, returns a description of the first
+/// problem found
+fn validate_indices<T>(indices: &[Index<T>], lead_out: Msf) -> Result<(), String> {
+    let mut prev: Option<&Index<T>> = None;
+
+    for index in indices {
+        if index.sector_index >= lead_out.sector_index() {
+            return Err(format!(
+                "Track {} index {} at {} is in the lead-out ({})",
+                index.track,
+                index.index,
+                index.msf(),
+                lead_out
+            ));
+        }
+
+        let p = match prev {
+            Some(p) => p,
+            None => {
+                if index.track != Bcd::ONE {
+                    return Err(format!("First track is {}, expected 01", index.track));
+                }
+
+                if index.index.binary() > 1 {
+                    return Err(format!("Track 01 starts with index {}", index.index));
+                }
+
+                prev = Some(index);
+                continue;
+            }
+        };
+
+        // An INDEX 01 immediately followed by the next track is an empty track. The ToC can
+        // represent them, even if no sector can be read from them.
+        let empty_track = index.track != p.track && p.index == Bcd::ONE;
+
+        if index.sector_index == p.sector_index && !empty_track {
+            return Err(format!(
+                "Track {} index {} and track {} index {} both start at {}",
+                p.track,
+                p.index,
+                index.track,
+                index.index,
+                index.msf()
+            ));
+        }
+
+        if index.track == p.track {
+            // An index can be split into several entries, for instance if it's followed by a
+            // generated postgap
+            let expected = p.index.binary() + 1;
+
+            if index.index != p.index && index.index.binary() != expected {
+                return Err(format!(
+                    "Track {} index {} follows index {}",
+                    index.track, index.index, p.index
+                ));
+            }
+        } else {
+            if p.index == Bcd::ZERO {
+                return Err(format!("Track {} has no index 01", p.track));
+            }
+
+            if index.track.binary() != p.track.binary() + 1 {
+                return Err(format!("Track {} follows track {}", index.track, p.track));
+            }
+
+            if index.index.binary() > 1 {
+                return Err(format!(
+                    "Track {} starts with index {}",
+                    index.track, index.index
+                ));
+            }
+        }
+
+        prev = Some(index);
+    }
+
+    match prev {
+        Some(p) if p.index == Bcd::ZERO => Err(format!("Track {} has no index 01", p.track)),
+        _ => Ok(()),
     }
 }
 
@@ -323,3 +446,111 @@ impl<T> fmt::Debug for IndexCache<T> {
         writeln!(f, "Lead-out: {}", self.lead_out())
     }
 }
+
+#[test]
+fn index_validation() {
+    fn cache(indices: &[(u8, u8, u32)], lead_out: u32) -> CdResult<IndexCache<()>> {
+        let indices = indices
+            .iter()
+            .map(|&(track, index, sector)| {
+                Index::new(
+                    Bcd::from_binary(index).unwrap(),
+                    Msf::from_sector_index(sector).unwrap(),
+                    Bcd::from_binary(track).unwrap(),
+                    TrackFormat::Audio,
+                    1,
+                    AdrControl::AUDIO,
+                    (),
+                )
+            })
+            .collect();
+
+        IndexCache::new(
+            PathBuf::new(),
+            indices,
+            Msf::from_sector_index(lead_out).unwrap(),
+        )
+    }
+
+    fn error(indices: &[(u8, u8, u32)], lead_out: u32) -> String {
+        match cache(indices, lead_out) {
+            Err(CdError::BadImage { desc, .. }) => desc,
+            Err(e) => panic!("Unexpected error {}", e),
+            Ok(_) => panic!("Invalid indices accepted: {:?}", indices),
+        }
+    }
+
+    // Valid disc, with a split index 01 in track 2
+    assert!(cache(
+        &[
+            (1, 0, 0),
+            (1, 1, 150),
+            (2, 0, 300),
+            (2, 1, 450),
+            (2, 1, 500),
+            (2, 2, 600)
+        ],
+        1000
+    )
+    .is_ok());
+
+    // Track 2 is empty
+    let empty = cache(&[(1, 0, 0), (1, 1, 150), (2, 1, 300), (3, 1, 300)], 1000).unwrap();
+    let t = |n| Bcd::from_binary(n).unwrap();
+
+    assert_eq!(empty.track_length(t(2)).unwrap().0, Msf::ZERO);
+    assert_eq!(
+        empty.track_length(t(3)).unwrap().0,
+        Msf::from_sector_index(700).unwrap()
+    );
+    assert!(matches!(
+        empty.track_msf(t(2), Msf::ZERO),
+        Err(CdError::EndOfTrack)
+    ));
+    assert_eq!(
+        empty
+            .find_index_for_msf(Msf::from_sector_index(300).unwrap())
+            .unwrap()
+            .1
+            .track(),
+        t(3)
+    );
+    assert_eq!(empty.toc().unwrap().tracks()[1].length, Msf::ZERO);
+
+    assert_eq!(
+        error(&[(1, 0, 0), (1, 1, 150), (1, 2, 150)], 1000),
+        "Track 01 index 01 and track 01 index 02 both start at 00:02:00"
+    );
+    assert_eq!(
+        error(&[(1, 0, 0), (1, 1, 150), (2, 0, 300), (3, 1, 300)], 1000),
+        "Track 02 index 00 and track 03 index 01 both start at 00:04:00"
+    );
+    assert_eq!(
+        error(&[(1, 0, 0), (1, 1, 150), (2, 0, 300), (3, 1, 450)], 1000),
+        "Track 02 has no index 01"
+    );
+    assert_eq!(
+        error(&[(1, 0, 0), (1, 1, 150), (2, 0, 300)], 1000),
+        "Track 02 has no index 01"
+    );
+    assert_eq!(
+        error(&[(1, 0, 0), (1, 1, 150), (1, 3, 300)], 1000),
+        "Track 01 index 03 follows index 01"
+    );
+    assert_eq!(
+        error(&[(1, 0, 0), (1, 1, 150), (2, 2, 300)], 1000),
+        "Track 02 starts with index 02"
+    );
+    assert_eq!(
+        error(&[(1, 0, 0), (1, 1, 150), (3, 1, 300)], 1000),
+        "Track 03 follows track 01"
+    );
+    assert_eq!(
+        error(&[(2, 0, 0), (2, 1, 150)], 1000),
+        "First track is 02, expected 01"
+    );
+    assert_eq!(
+        error(&[(1, 0, 0), (1, 1, 150), (2, 1, 1000)], 1000),
+        "Track 02 index 01 at 00:13:25 is in the lead-out (00:13:25)"
+    );
+}
@@ -0,0 +1,348 @@
+//! Minimal read-only ISO9660 filesystem support, used to locate files within the data track of a
+//! disc image.
+//!
+//! Only the primary volume descriptor is supported, Joliet and Rock Ridge extensions are ignored.
+//! The CD-ROM XA attributes stored in the system use area of the directory records are used to
+//! figure out if a file is stored in Mode 2 Form 1 or Form 2 sectors.
+
+pub use block::BLOCK_SIZE;
+
+use block::block_payload;
+use sector::XaForm;
+use {CdError, CdResult, DiscPosition, Image, Msf};
+
+/// LBA of the primary volume descriptor
+const PVD_LBA: u32 = 16;
+
+/// Location of a file or directory within the filesystem
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    /// Logical block address of the first sector of the file (LBA 0 is at 00:02:00)
+    pub lba: u32,
+    /// Size of the file in bytes
+    pub size: u32,
+    /// Form of the sectors containing the file. Files without CD-ROM XA attributes are reported
+    /// as Form 1.
+    pub form: XaForm,
+    /// True if this entry is a directory
+    pub is_dir: bool,
+}
+
+impl FileEntry {
+    /// Returns the position of the first sector of the file
+    pub fn position(&self) -> CdResult<DiscPosition> {
+        lba_to_position(self.lba)
+    }
+}
+
+/// ISO9660 filesystem reader
+pub struct Iso9660<'a> {
+    image: &'a mut dyn Image,
+    /// Root directory of the filesystem
+    root: FileEntry,
+}
+
+impl<'a> Iso9660<'a> {
+    /// Parse the primary volume descriptor of `image` and return an `Iso9660` instance.
+    pub fn new(image: &'a mut dyn Image) -> CdResult<Iso9660<'a>> {
+        let pvd = read_block(image, PVD_LBA)?;
+
+        if pvd[0] != 1 || &pvd[1..6] != b"CD001" {
+            return Err(CdError::BadFilesystem(
+                "Missing ISO9660 primary volume descriptor".to_string(),
+            ));
+        }
+
+        let root = match parse_record(&pvd[156..190]) {
+            Some((_, root)) => root,
+            None => {
+                return Err(CdError::BadFilesystem(
+                    "Invalid root directory record".to_string(),
+                ))
+            }
+        };
+
+        Ok(Iso9660 { image, root })
+    }
+
+    /// Returns the entry for the root directory
+    pub fn root(&self) -> FileEntry {
+        self.root
+    }
+
+    /// Look for the file or directory at `path`. Path components are separated by `/` and
+    /// compared ignoring ASCII case. The version suffix (`;1`) is optional.
+    ///
+    /// Returns `CdError::FileNotFound` if the path doesn't exist.
+    pub fn find(&mut self, path: &str) -> CdResult<FileEntry> {
+        let mut entry = self.root;
+
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            if !entry.is_dir {
+                return Err(CdError::FileNotFound(path.to_string()));
+            }
+
+            entry = match self.find_in_dir(entry, component)? {
+                Some(e) => e,
+                None => return Err(CdError::FileNotFound(path.to_string())),
+            };
+        }
+
+        Ok(entry)
+    }
+
+    /// Look for `name` in directory `dir`
+    fn find_in_dir(&mut self, dir: FileEntry, name: &str) -> CdResult<Option<FileEntry>> {
+        let nblocks = (dir.size as usize).div_ceil(BLOCK_SIZE) as u32;
+
+        for b in 0..nblocks {
+            let lba = dir.lba.checked_add(b).ok_or_else(|| {
+                CdError::BadFilesystem("Directory extent past the end of the disc".to_string())
+            })?;
+
+            let block = read_block(self.image, lba)?;
+
+            let mut pos = 0;
+
+            // Directory records never cross block boundaries. A zero-length record means that
+            // there are no more records in this block.
+            while pos < BLOCK_SIZE && block[pos] != 0 {
+                let len = block[pos] as usize;
+
+                if pos + len > BLOCK_SIZE {
+                    return Err(CdError::BadFilesystem(
+                        "Directory record crosses block boundary".to_string(),
+                    ));
+                }
+
+                let record = &block[pos..pos + len];
+
+                if let Some((record_name, entry)) = parse_record(record) {
+                    if name_matches(record_name, name) {
+                        return Ok(Some(entry));
+                    }
+                }
+
+                pos += len;
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Convert an LBA into a position in the program area
+fn lba_to_position(lba: u32) -> CdResult<DiscPosition> {
+    lba.checked_add(150)
+        .and_then(Msf::from_sector_index)
+        .map(DiscPosition::Program)
+        .ok_or(CdError::OutOfDiscPosition)
+}
+
+/// Read the 2048-byte payload of the sector at `lba`
+fn read_block(image: &mut dyn Image, lba: u32) -> CdResult<[u8; BLOCK_SIZE]> {
+    let sector = image.read_sector(lba_to_position(lba)?)?;
+
+    let mut block = [0; BLOCK_SIZE];
+    block.copy_from_slice(block_payload(&sector)?);
+
+    Ok(block)
+}
+
+/// Parse a directory record and return the raw file identifier and the corresponding entry.
+/// Returns `None` if the record is truncated.
+fn parse_record(record: &[u8]) -> Option<(&[u8], FileEntry)> {
+    if record.len() < 34 {
+        return None;
+    }
+
+    let lba = u32::from_le_bytes([record[2], record[3], record[4], record[5]]);
+    let size = u32::from_le_bytes([record[10], record[11], record[12], record[13]]);
+    let is_dir = record[25] & 2 != 0;
+    let name_len = record[32] as usize;
+
+    let name_end = 33 + name_len;
+
+    if name_end > record.len() {
+        return None;
+    }
+
+    let name = &record[33..name_end];
+
+    // The system use area starts after the name, aligned on a 16-bit boundary
+    let su_start = name_end + (name_end & 1);
+
+    let mut form = XaForm::Form1;
+
+    if let Some(xa) = record.get(su_start..su_start + 14) {
+        if &xa[6..8] == b"XA" {
+            let attributes = u16::from_be_bytes([xa[4], xa[5]]);
+
+            if attributes & 0x1000 != 0 {
+                form = XaForm::Form2;
+            }
+        }
+    }
+
+    let entry = FileEntry {
+        lba,
+        size,
+        form,
+        is_dir,
+    };
+
+    Some((name, entry))
+}
+
+/// Compare a raw ISO9660 file identifier with `name`, ignoring ASCII case and the version suffix
+/// if `name` doesn't have one
+fn name_matches(raw: &[u8], name: &str) -> bool {
+    let name = name.as_bytes();
+
+    if raw.eq_ignore_ascii_case(name) {
+        return true;
+    }
+
+    // Strip the version
+    let raw = match raw.iter().position(|&b| b == b';') {
+        Some(p) => &raw[..p],
+        None => raw,
+    };
+
+    // Files without extension are stored with a trailing '.'
+    let raw = raw.strip_suffix(b".").unwrap_or(raw);
+
+    !raw.is_empty() && raw.eq_ignore_ascii_case(name)
+}
+
+#[test]
+fn find_files() {
+    use bcd::Bcd;
+    use subchannel::AdrControl;
+    use test_image::TestImage;
+    use {Track, TrackFormat};
+
+    fn record(name: &[u8], lba: u32, size: u32, is_dir: bool, xa_attr: Option<u16>) -> Vec<u8> {
+        let mut r = vec![0; 33];
+
+        r[2..6].copy_from_slice(&lba.to_le_bytes());
+        r[6..10].copy_from_slice(&lba.to_be_bytes());
+        r[10..14].copy_from_slice(&size.to_le_bytes());
+        r[14..18].copy_from_slice(&size.to_be_bytes());
+        r[25] = if is_dir { 2 } else { 0 };
+        r[32] = name.len() as u8;
+        r.extend_from_slice(name);
+
+        if r.len() & 1 != 0 {
+            r.push(0);
+        }
+
+        if let Some(attr) = xa_attr {
+            let mut xa = [0; 14];
+            xa[4..6].copy_from_slice(&attr.to_be_bytes());
+            xa[6..8].copy_from_slice(b"XA");
+            r.extend_from_slice(&xa);
+        }
+
+        r[0] = r.len() as u8;
+
+        r
+    }
+
+    fn dir(records: &[Vec<u8>]) -> [u8; BLOCK_SIZE] {
+        let mut block = [0; BLOCK_SIZE];
+        let mut pos = 0;
+
+        for r in records {
+            block[pos..pos + r.len()].copy_from_slice(r);
+            pos += r.len();
+        }
+
+        block
+    }
+
+    let mut blocks = vec![[0; BLOCK_SIZE]; 40];
+
+    // Primary volume descriptor
+    blocks[16][0] = 1;
+    blocks[16][1..6].copy_from_slice(b"CD001");
+    let root = record(&[0], 18, 2048, true, None);
+    blocks[16][156..190].copy_from_slice(&root);
+
+    // Volume descriptor set terminator
+    blocks[17][0] = 0xff;
+    blocks[17][1..6].copy_from_slice(b"CD001");
+
+    blocks[18] = dir(&[
+        record(&[0], 18, 2048, true, None),
+        record(&[1], 18, 2048, true, None),
+        record(b"DATA", 19, 2048, true, Some(0x8d55)),
+        record(b"SYSTEM.CNF;1", 20, 68, false, Some(0x0d55)),
+        record(b"BOGUS", u32::MAX - 1, 2048 * 4, true, None),
+    ]);
+
+    blocks[19] = dir(&[
+        record(&[0], 19, 2048, true, None),
+        record(&[1], 18, 2048, true, None),
+        record(b"MOVIE.STR;1", 21, 2324 * 10, false, Some(0x1555)),
+        record(b"README.;1", 31, 5, false, None),
+    ]);
+
+    let track = Track {
+        track: Bcd::ONE,
+        format: TrackFormat::Mode1,
+        start: Msf::from_sector_index(150).unwrap(),
+        length: Msf::from_sector_index(blocks.len() as u32).unwrap(),
+        control: AdrControl::DATA,
+    };
+
+    let mut image = TestImage::new(vec![track], |t, msf, sector| {
+        let lba = (msf - t.start).sector_index() as usize;
+
+        sector.data_2352_mut()[16..2064].copy_from_slice(&blocks[lba]);
+        sector.write_edc_ecc();
+    });
+
+    let mut iso = Iso9660::new(&mut image).unwrap();
+
+    assert_eq!(
+        iso.find("SYSTEM.CNF;1").unwrap(),
+        FileEntry {
+            lba: 20,
+            size: 68,
+            form: XaForm::Form1,
+            is_dir: false,
+        }
+    );
+    assert_eq!(iso.find("/system.cnf").unwrap().lba, 20);
+    assert_eq!(
+        iso.find("/system.cnf").unwrap().position().unwrap(),
+        "+00:02:20".parse().unwrap()
+    );
+
+    let movie = iso.find("DATA/MOVIE.STR").unwrap();
+    assert_eq!(movie.lba, 21);
+    assert_eq!(movie.size, 23240);
+    assert_eq!(movie.form, XaForm::Form2);
+
+    assert!(iso.find("DATA").unwrap().is_dir);
+    assert_eq!(iso.find("DATA/README").unwrap().lba, 31);
+
+    assert!(matches!(
+        iso.find("DATA/NOPE.BIN"),
+        Err(CdError::FileNotFound(_))
+    ));
+    assert!(matches!(
+        iso.find("SYSTEM.CNF/FOO"),
+        Err(CdError::FileNotFound(_))
+    ));
+
+    // Directory extents near the top of the LBA range must not overflow
+    let bogus = iso.find("BOGUS").unwrap();
+    assert_eq!(bogus.lba, u32::MAX - 1);
+    assert!(matches!(bogus.position(), Err(CdError::OutOfDiscPosition)));
+    assert!(matches!(
+        iso.find("BOGUS/FOO"),
+        Err(CdError::OutOfDiscPosition)
+    ));
+}
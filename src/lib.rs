@@ -6,33 +6,52 @@
 
 #[macro_use]
 extern crate arrayref;
+extern crate crc32fast;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
 #[cfg(feature = "serde")]
 extern crate serde;
 #[cfg(feature = "serde")]
 extern crate serde_big_array;
+extern crate sha1;
 extern crate thiserror;
+#[cfg(feature = "async")]
+extern crate tokio;
 extern crate zip;
 
+pub mod adapters;
+#[cfg(feature = "async")]
+pub mod async_image;
 pub mod bcd;
+pub mod block;
+pub mod checksums;
 mod crc;
 pub mod cue;
 pub mod disc_position;
 mod ecc;
+pub mod formats;
+mod hash;
+pub mod integrity;
 pub mod internal;
+pub mod iso9660;
+mod metadata;
 pub mod msf;
 pub mod sector;
 pub mod subchannel;
+#[cfg(test)]
+mod test_image;
 mod toc;
+pub mod wav;
 
 pub use bcd::Bcd;
 pub use disc_position::DiscPosition;
 pub use msf::Msf;
 pub use sector::Sector;
 use std::clone::Clone;
-use std::io;
 use std::path::PathBuf;
+use std::{cmp, fmt, io};
 use thiserror::Error;
-pub use toc::Toc;
+pub use toc::{DiscType, LeadOutMode, MmcTocFormat, Toc, TocDifference, TrackGap};
 
 /// Abstract read-only interface to an image format
 pub trait Image {
@@ -44,13 +63,381 @@ pub trait Image {
     /// Read a single sector at the given absolute MSF
     fn read_sector(&mut self, position: DiscPosition) -> CdResult<Sector>;
 
+    /// Read a single sector at the given absolute MSF into `sector`, overwriting its previous
+    /// contents. This lets the caller reuse the same `Sector` for successive reads instead of
+    /// getting a new one every time.
+    ///
+    /// The default implementation simply calls `read_sector`, backends that can read directly
+    /// into the existing buffer should override it.
+    fn read_sector_into(&mut self, position: DiscPosition, sector: &mut Sector) -> CdResult<()> {
+        *sector = self.read_sector(position)?;
+
+        Ok(())
+    }
+
+    /// Read `count` consecutive sectors starting at `position`.
+    ///
+    /// The default implementation calls `read_sector` for every sector, backends that can read
+    /// several sectors at once more efficiently should override it.
+    fn read_sectors(&mut self, position: DiscPosition, count: u32) -> CdResult<Vec<Sector>> {
+        // Don't trust `count` for the allocation, it can be arbitrarily large
+        let mut sectors = Vec::with_capacity(cmp::min(count, 256) as usize);
+
+        for n in 0..count {
+            let position = position
+                .checked_add_sectors(n)
+                .ok_or(CdError::OutOfDiscPosition)?;
+
+            sectors.push(self.read_sector(position)?);
+        }
+
+        Ok(sectors)
+    }
+
     /// Get the table of contents
     fn toc(&self) -> &Toc;
+
+    /// Returns the Q subchannel data of the sector at `position`. This is always the Mode 1
+    /// position data, the Mode 2 and Mode 3 frames carrying the media catalog number and ISRCs are
+    /// only available through `Sector::raw_subchannel`.
+    ///
+    /// The default implementation reads the whole sector, backends that can compute the Q data
+    /// without reading the sector's contents should override it.
+    fn read_subq(&mut self, position: DiscPosition) -> CdResult<subchannel::Q> {
+        let sector = self.read_sector(position)?;
+
+        Ok(sector.q().clone())
+    }
+
+    /// Read the sector at position `track_msf` relative to the start of `track` (INDEX 01), see
+    /// `Track::disc_position`. Returns `CdError::BadTrack` if `track` doesn't exist and
+    /// `CdError::EndOfTrack` if `track_msf` is past the end of the track.
+    fn read_track_sector(&mut self, track: Bcd, track_msf: Msf) -> CdResult<Sector> {
+        let position = self.toc().track(track)?.disc_position(track_msf)?;
+
+        self.read_sector(position)
+    }
+
+    /// Read the sector under the laser at radius `r` from the center of the disc, using the
+    /// approximate physical model of `DiscPosition::from_radius`. The position is clamped to the
+    /// disc with `Toc::clamp_position`: radiuses before the start of the lead-in read the first
+    /// lead-in (ToC) sector and radiuses past the end of the lead-out read its last sector.
+    fn sector_at_radius(&mut self, r: disc_position::Radius) -> CdResult<Sector> {
+        let position = match DiscPosition::from_radius(r) {
+            Ok(p) => p,
+            Err(CdError::PreLeadInPosition) => DiscPosition::INNERMOST,
+            Err(CdError::OutOfDiscPosition) => DiscPosition::Program(Msf::MAX),
+            Err(e) => return Err(e),
+        };
+
+        let position = self.toc().clamp_position(position);
+
+        self.read_sector(position)
+    }
+
+    /// Returns the track and index numbers at the absolute MSF `msf` in the program area, as they
+    /// would be reported in the Q subchannel.
+    ///
+    /// Returns `CdError::BadTrack` if `msf` is in the lead-out. The default implementation reads
+    /// the sector and decodes its Q data, backends that can do better should override it.
+    fn current_index(&mut self, msf: Msf) -> CdResult<(Bcd, Bcd)> {
+        let sector = self.read_sector(DiscPosition::Program(msf))?;
+
+        match *sector.q().data() {
+            subchannel::QData::Mode1 { track, index, .. } => Ok((track, index)),
+            _ => Err(CdError::BadTrack),
+        }
+    }
+
+    /// Returns the length of the pregap (INDEX 00) of `track`.
+    ///
+    /// The default implementation looks for the start of the pregap with `Image::current_index`,
+    /// backends that can do better should override it.
+    fn track_pregap(&mut self, track: Bcd) -> CdResult<Msf> {
+        let start = self.toc().track(track)?.start;
+
+        let mut pregap_start = start;
+
+        while let Some(prev) = pregap_start.checked_sub(Msf::from_sector_index(1).unwrap()) {
+            if self.current_index(prev)? != (track, Bcd::ZERO) {
+                break;
+            }
+
+            pregap_start = prev;
+        }
+
+        Ok(start - pregap_start)
+    }
+
+    /// Returns the range of sectors `[start, end)` making up `track` when the gaps between the
+    /// tracks are placed according to `placement`
+    fn track_range(&mut self, track: Bcd, placement: GapPlacement) -> CdResult<(Msf, Msf)> {
+        let toc = self.toc();
+        let t = toc.track(track)?;
+
+        let start = t.start;
+        let end = t.start + t.length;
+
+        let range = match placement {
+            GapPlacement::Omit => (start, end),
+            GapPlacement::Append => {
+                let next = Bcd::from_binary(track.binary() + 1).and_then(|n| toc.track(n).ok());
+
+                let end = match next {
+                    Some(next) => next.start,
+                    None => toc.lead_out_start(),
+                };
+
+                (start, end)
+            }
+            // Track 1's pregap is never included, like most ripping tools do
+            GapPlacement::Prepend if track == Bcd::ONE => (start, end),
+            GapPlacement::Prepend => (start - self.track_pregap(track)?, end),
+        };
+
+        Ok(range)
+    }
+
+    /// Returns which part of the disc `position` is in: lead-in, pregap or index of a track or
+    /// lead-out. Positions in the program area are classified using `Image::current_index`.
+    fn classify(&mut self, position: DiscPosition) -> CdResult<PositionKind> {
+        let msf = match position {
+            DiscPosition::LeadIn(_) => return Ok(PositionKind::LeadIn),
+            DiscPosition::Program(msf) => msf,
+        };
+
+        if msf >= self.toc().lead_out_start() {
+            return Ok(PositionKind::LeadOut);
+        }
+
+        let (track, index) = self.current_index(msf)?;
+
+        if index == Bcd::ZERO {
+            Ok(PositionKind::Pregap { track })
+        } else {
+            Ok(PositionKind::Track { track, index })
+        }
+    }
+
+    /// Read every sector from the start of the first track to the lead-out and check their
+    /// integrity: EDC and, for CD-ROM sectors, the validity of the header and its MSF.
+    fn integrity_report(&mut self) -> CdResult<integrity::IntegrityReport> {
+        integrity::integrity_report(self, None)
+    }
+
+    /// Same as `Image::integrity_report` but calls `progress` after every sector checked
+    fn integrity_report_with_progress(
+        &mut self,
+        progress: &mut dyn FnMut(Progress),
+    ) -> CdResult<integrity::IntegrityReport> {
+        integrity::integrity_report(self, Some(progress))
+    }
+
+    /// Compute the standard CRC-32 of the raw 2352-byte sectors of `track`, from its pregap
+    /// (INDEX 00) to the pregap of the next track, like the per-track BIN files of redump dumps
+    /// (see `GapPlacement::Prepend`). The pregap of track 1 is not included, so the tracks
+    /// together cover the same sectors as `Image::disc_crc32`.
+    fn track_crc32(&mut self, track: Bcd) -> CdResult<u32> {
+        hash::track_crc32(self, track)
+    }
+
+    /// Compute the SHA-1 of the raw 2352-byte sectors of `track`. Covers the same sectors as
+    /// `Image::track_crc32`.
+    fn track_sha1(&mut self, track: Bcd) -> CdResult<[u8; 20]> {
+        hash::track_sha1(self, track)
+    }
+
+    /// Returns the media catalog number (UPC/EAN) of the disc, if any.
+    ///
+    /// The default implementation looks for Mode 2 Q frames in the raw subchannel data of the
+    /// first sectors of the program area and returns `Ok(None)` if none is found, for instance if
+    /// the image doesn't have raw subchannel data. Backends that store the MCN elsewhere (for instance in a cue sheet) should
+    /// override it.
+    fn media_catalog_number(&mut self) -> CdResult<Option<String>> {
+        metadata::media_catalog_number(self)
+    }
+
+    /// Returns the ISRC of `track`, if any. Like `Image::media_catalog_number` the default
+    /// implementation looks for Mode 3 Q frames in the raw subchannel data of the first sectors of
+    /// the track.
+    fn track_isrc(&mut self, track: Bcd) -> CdResult<Option<String>> {
+        metadata::track_isrc(self, track)
+    }
+
+    /// Write audio `track` to `out` as a 44.1kHz 16-bit stereo WAV file, from its INDEX 01 to the
+    /// start of the next track. Returns `CdError::BadFormat` if `track` is not an audio track. See
+    /// `wav::export_track` to include the gaps or apply de-emphasis.
+    fn export_track_wav(&mut self, track: Bcd, out: &mut dyn io::Write) -> CdResult<()> {
+        wav::export_track(self, track, out, &wav::WavOptions::default())
+    }
+
+    /// Write every sector from the start of the first track to the lead-out to `out` as 2352 bytes
+    /// of data followed by 96 bytes of raw interleaved subchannel data (see `Sector::data_2448`).
+    /// This is the "raw+sub" layout used by CloneCD and similar tools. The Q subchannel is
+    /// generated for images that don't store it, the other subchannels are then set to 0.
+    fn export_raw_2448(&mut self, out: &mut dyn io::Write) -> CdResult<()> {
+        let toc = self.toc();
+        let range = msf::MsfRange::new(toc.tracks()[0].start, toc.lead_out_start());
+
+        for msf in range {
+            let sector = self.read_sector(DiscPosition::Program(msf))?;
+
+            out.write_all(&sector.data_2448())?;
+        }
+
+        Ok(())
+    }
+
+    /// Compute the standard CRC-32 of the raw 2352-byte sectors of all the tracks, from the start
+    /// of the first track to the lead-out.
+    fn disc_crc32(&mut self) -> CdResult<u32> {
+        hash::disc_crc32(self)
+    }
+
+    /// Compute the SHA-1 of the raw 2352-byte sectors of all the tracks, from the start of the
+    /// first track to the lead-out.
+    fn disc_sha1(&mut self) -> CdResult<[u8; 20]> {
+        hash::disc_sha1(self)
+    }
+
+    /// Returns an iterator over all the sectors of the disc, from the start of the first track up
+    /// to (but not including) the lead-out. Use `SectorIter::new` directly for trait objects.
+    fn sectors(&mut self) -> SectorIter<'_, Self>
+    where
+        Self: Sized,
+    {
+        SectorIter::new(self)
+    }
+
+    /// Returns an iterator over the Q subchannel data of every sector of the disc in order: the
+    /// lead-in from `DiscPosition::INNERMOST`, the program area and the lead-out up to
+    /// `Toc::lead_out_end`. Use `SubqIter::new` directly for trait objects.
+    fn subq_frames(&mut self) -> SubqIter<'_, Self>
+    where
+        Self: Sized,
+    {
+        SubqIter::new(self)
+    }
+
+    /// Move `delta` tracks away from the track containing `current` and return the position of the
+    /// start (INDEX 01) of the resulting track. A `delta` of 0 returns the start of the current
+    /// track.
+    ///
+    /// Positions in the lead-in or before the start of the first track are considered to be just
+    /// before track 01, positions in the lead-out are considered to be just after the last track.
+    /// If the resulting track would be before the first track, the start of the first track is
+    /// returned. If it would be past the last track, the start of the lead-out is returned.
+    fn seek_track(&self, current: DiscPosition, delta: i32) -> CdResult<DiscPosition> {
+        let toc = self.toc();
+        let tracks = toc.tracks();
+
+        let cur = match current {
+            DiscPosition::LeadIn(_) => 0,
+            DiscPosition::Program(msf) => {
+                if msf >= toc.lead_out_start() {
+                    tracks.len() + 1
+                } else {
+                    tracks.iter().take_while(|t| t.start <= msf).count()
+                }
+            }
+        };
+
+        let target = cur as i64 + i64::from(delta);
+
+        let start = if target < 1 {
+            tracks[0].start
+        } else if target as usize > tracks.len() {
+            toc.lead_out_start()
+        } else {
+            tracks[target as usize - 1].start
+        };
+
+        Ok(DiscPosition::Program(start))
+    }
+}
+
+/// Iterator over the sectors of an `Image`, returned by `Image::sectors`
+pub struct SectorIter<'a, I: Image + ?Sized + 'a> {
+    image: &'a mut I,
+    /// Positions left to be read
+    range: msf::MsfRange,
+}
+
+impl<'a, I: Image + ?Sized> SectorIter<'a, I> {
+    /// Create an iterator over the sectors of `image`, from the start of the first track up to
+    /// the lead-out
+    pub fn new(image: &'a mut I) -> SectorIter<'a, I> {
+        let toc = image.toc();
+        let range = msf::MsfRange::new(toc.tracks()[0].start, toc.lead_out_start());
+
+        SectorIter { image, range }
+    }
+}
+
+impl<'a, I: Image + ?Sized> Iterator for SectorIter<'a, I> {
+    type Item = CdResult<Sector>;
+
+    fn next(&mut self) -> Option<CdResult<Sector>> {
+        let msf = self.range.next()?;
+
+        Some(self.image.read_sector(DiscPosition::Program(msf)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl<'a, I: Image + ?Sized> ExactSizeIterator for SectorIter<'a, I> {}
+
+/// Iterator over the Q subchannel data of every sector of an `Image`, including the lead-in and
+/// lead-out. Returned by `Image::subq_frames`.
+pub struct SubqIter<'a, I: Image + ?Sized + 'a> {
+    image: &'a mut I,
+    /// Next position to be read
+    position: DiscPosition,
+    /// First position past the end of the lead-out
+    end: DiscPosition,
+}
+
+impl<'a, I: Image + ?Sized> SubqIter<'a, I> {
+    /// Create an iterator over the Q subchannel data of `image`, from `DiscPosition::INNERMOST` up
+    /// to the end of the lead-out
+    pub fn new(image: &'a mut I) -> SubqIter<'a, I> {
+        let end = DiscPosition::Program(image.toc().lead_out_end());
+
+        SubqIter {
+            image,
+            position: DiscPosition::INNERMOST,
+            end,
+        }
+    }
+}
+
+impl<'a, I: Image + ?Sized> Iterator for SubqIter<'a, I> {
+    type Item = CdResult<subchannel::Q>;
+
+    fn next(&mut self) -> Option<CdResult<subchannel::Q>> {
+        if self.position >= self.end {
+            return None;
+        }
+
+        let position = self.position;
+
+        self.position = match position.next() {
+            Some(p) => p,
+            // Past 99:59:74, we're done after this one
+            None => self.end,
+        };
+
+        Some(self.image.read_subq(position))
+    }
 }
 
-/// Struct representing a track's attributes
+/// Struct representing a track's attributes. Tracks are ordered by position on the disc, then by
+/// track number.
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Track {
     /// Track number
     pub track: Bcd,
@@ -65,6 +452,44 @@ pub struct Track {
     pub control: subchannel::AdrControl,
 }
 
+/// Single line summary of the track: number, format, start and length, for instance
+/// `Track 02 [AUDIO] 00:02:32 +03:14:10`
+impl fmt::Display for Track {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "Track {} [{}] {} +{}",
+            self.track, self.format, self.start, self.length
+        )
+    }
+}
+
+impl cmp::Ord for Track {
+    fn cmp(&self, other: &Track) -> cmp::Ordering {
+        // The remaining fields are only compared to remain consistent with `Eq`
+        (
+            self.start,
+            self.track,
+            self.length,
+            self.format,
+            self.control,
+        )
+            .cmp(&(
+                other.start,
+                other.track,
+                other.length,
+                other.format,
+                other.control,
+            ))
+    }
+}
+
+impl cmp::PartialOrd for Track {
+    fn partial_cmp(&self, other: &Track) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Track {
     /// Return the absolute Msf for the position `track_msf` in `track`. Will return an error if
     /// the `track_msf` is outside of the track.
@@ -72,7 +497,7 @@ impl Track {
         if track_msf < self.length {
             // If the image format is not bogus that shouldn't happen, since it would mean that a
             // track has data past the max MSF value
-            self.start.checked_add(track_msf).ok_or(CdError::InvalidMsf)
+            self.start.try_add(track_msf)
         } else {
             Err(CdError::EndOfTrack)
         }
@@ -85,11 +510,95 @@ impl Track {
     pub fn disc_position(&self, track_msf: Msf) -> CdResult<DiscPosition> {
         self.absolute_msf(track_msf).map(DiscPosition::Program)
     }
+
+    /// Return a copy of this track moved to `new_start`, for instance to build a new disc out of
+    /// tracks coming from other images. The length, format and control bits are preserved.
+    ///
+    /// The Q subchannel of the track's sectors must be regenerated for the new position when
+    /// they're re-emitted: the relative MSF of a sector stays the same but its absolute MSF is
+    /// now `new_start + track_msf` (see `Track::absolute_msf`).
+    ///
+    /// Returns `CdError::OutOfDiscPosition` if the track would end past the end of the disc.
+    pub fn shifted(&self, new_start: Msf) -> CdResult<Track> {
+        if new_start.checked_add(self.length).is_none() {
+            return Err(CdError::OutOfDiscPosition);
+        }
+
+        Ok(Track {
+            start: new_start,
+            ..self.clone()
+        })
+    }
+
+    /// Return a copy of this track renumbered to `track`
+    pub fn with_number(&self, track: Bcd) -> Track {
+        Track {
+            track,
+            ..self.clone()
+        }
+    }
+}
+
+/// Where the gaps between tracks (the pregaps of the tracks after the first one) are placed when
+/// splitting a disc into tracks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum GapPlacement {
+    /// The gaps are not part of any track, a track goes from its INDEX 01 to the first index of
+    /// the next track. That's how the tracks are described in the ToC. This is the default.
+    #[default]
+    Omit,
+    /// The gap is appended to the end of the previous track, a track goes from its INDEX 01 to
+    /// the INDEX 01 of the next track
+    Append,
+    /// The gap is prepended to the start of its track, a track goes from its first index to the
+    /// first index of the next track. The pregap of track 1 is not included.
+    Prepend,
+}
+
+/// Part of the disc a position belongs to, returned by `Image::classify`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionKind {
+    /// Lead-in area, containing the table of contents
+    LeadIn,
+    /// Pregap (INDEX 00) of a track
+    Pregap {
+        /// Track number
+        track: Bcd,
+    },
+    /// Index 01 or greater of a track
+    Track {
+        /// Track number
+        track: Bcd,
+        /// Index within the track
+        index: Bcd,
+    },
+    /// Lead-out area, past the last track
+    LeadOut,
+}
+
+/// Progress of a long-running operation, passed to the progress callbacks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// Operation currently running
+    pub phase: ProgressPhase,
+    /// Number of units (sectors, lines...) processed so far
+    pub current: u32,
+    /// Total number of units to process
+    pub total: u32,
+}
+
+/// Long-running operations reporting their `Progress`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressPhase {
+    /// Parsing an image description (for instance a cue sheet), in lines
+    Parsing,
+    /// Checking the integrity of the image, in sectors
+    Verifying,
 }
 
 /// Possible session formats.
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum SessionFormat {
     /// CD-DA (audio CD, "red book" specification) or CD-ROM ("yellow
     /// book" specification) session
@@ -104,23 +613,41 @@ pub enum SessionFormat {
 
 /// Possible track types
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
 pub enum TrackFormat {
     /// CD-DA audio track (red book audio)
     Audio,
     /// CD-ROM Mode1 data
     Mode1,
+    /// CD-ROM Mode 2 data without the XA extensions: 2336 bytes of user data, no subheader and no
+    /// EDC/ECC
+    Mode2,
     /// CD-ROM XA Mode 2 data
     Mode2Xa,
     /// CD-i Mode 2 data
     Mode2CdI,
 }
 
+impl fmt::Display for TrackFormat {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            TrackFormat::Audio => "AUDIO",
+            TrackFormat::Mode1 => "MODE1",
+            TrackFormat::Mode2 => "MODE2",
+            TrackFormat::Mode2Xa => "MODE2/XA",
+            TrackFormat::Mode2CdI => "CD-I",
+        };
+
+        fmt.write_str(s)
+    }
+}
+
 impl TrackFormat {
     /// Return the CD-ROM mode for this track format, or `None` if this is not a CD-ROM format
     pub fn cdrom_mode(self) -> Option<sector::CdRomMode> {
         let m = match self {
             TrackFormat::Mode1 => sector::CdRomMode::Mode1,
+            TrackFormat::Mode2 => sector::CdRomMode::Mode2,
             TrackFormat::Mode2Xa => sector::CdRomMode::Mode2,
             TrackFormat::Mode2CdI => sector::CdRomMode::Mode2,
             _ => return None,
@@ -129,6 +656,23 @@ impl TrackFormat {
         Some(m)
     }
 
+    /// Returns the most likely track format for a sector with CD-ROM mode `mode` in a session of
+    /// format `session`. This is the reverse of `TrackFormat::cdrom_mode`: Mode 2 sectors are
+    /// CD-ROM XA in CD-ROM XA sessions, CD-i in CD-i sessions and plain Mode 2 otherwise.
+    ///
+    /// Returns `None` for `CdRomMode::Empty` since empty sectors can appear in any CD-ROM track.
+    pub fn from_cdrom_mode(mode: sector::CdRomMode, session: SessionFormat) -> Option<TrackFormat> {
+        let f = match (mode, session) {
+            (sector::CdRomMode::Empty, _) => return None,
+            (sector::CdRomMode::Mode1, _) => TrackFormat::Mode1,
+            (sector::CdRomMode::Mode2, SessionFormat::CdDaCdRom) => TrackFormat::Mode2,
+            (sector::CdRomMode::Mode2, SessionFormat::CdXa) => TrackFormat::Mode2Xa,
+            (sector::CdRomMode::Mode2, SessionFormat::Cdi) => TrackFormat::Mode2CdI,
+        };
+
+        Some(f)
+    }
+
     /// Return true if this is a CD-ROM track
     pub fn is_cdrom(self) -> bool {
         self.cdrom_mode().is_some()
@@ -138,6 +682,60 @@ impl TrackFormat {
     pub fn is_audio(self) -> bool {
         self == TrackFormat::Audio
     }
+
+    /// Returns the size of a full sector of this format, including the sync pattern and header
+    /// for CD-ROM formats. This is always 2352.
+    pub fn default_raw_sector_size(self) -> u16 {
+        SectorLayout::Raw.sector_size()
+    }
+
+    /// Returns true if sectors of this format can be stored using `layout`
+    pub fn supports_layout(self, layout: SectorLayout) -> bool {
+        match layout {
+            SectorLayout::Raw | SectorLayout::RawWithSubchannel => true,
+            SectorLayout::Mode2Headerless => match self {
+                TrackFormat::Mode2 | TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => true,
+                TrackFormat::Audio | TrackFormat::Mode1 => false,
+            },
+            SectorLayout::Form2Payload => match self {
+                TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => true,
+                TrackFormat::Audio | TrackFormat::Mode1 | TrackFormat::Mode2 => false,
+            },
+            SectorLayout::Payload => match self {
+                TrackFormat::Mode1 | TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => true,
+                TrackFormat::Audio | TrackFormat::Mode2 => false,
+            },
+        }
+    }
+}
+
+/// The various ways sectors are commonly stored in image files
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum SectorLayout {
+    /// Full 2352-byte sectors (audio samples, or sync pattern, header, payload and EDC/ECC)
+    Raw,
+    /// Full 2352-byte sectors followed by the 96 bytes of subchannel data (for instance CD+G)
+    RawWithSubchannel,
+    /// Mode 2 sectors without the sync pattern and header: 2336 bytes
+    Mode2Headerless,
+    /// Only the 2324-byte payload of CD-ROM XA Mode 2 Form 2 sectors
+    Form2Payload,
+    /// Only the 2048-byte payload of Mode 1 or CD-ROM XA Mode 2 Form 1 sectors
+    Payload,
+}
+
+impl SectorLayout {
+    /// Returns the number of bytes used to store a sector with this layout
+    pub fn sector_size(self) -> u16 {
+        match self {
+            SectorLayout::Raw => 2352,
+            SectorLayout::RawWithSubchannel => 2448,
+            SectorLayout::Mode2Headerless => 2336,
+            SectorLayout::Form2Payload => 2324,
+            SectorLayout::Payload => 2048,
+        }
+    }
 }
 
 /// Error type for disc operations.
@@ -157,6 +755,21 @@ pub enum CdError {
         line: u32,
         desc: String,
     },
+    /// An `INDEX` of a cue sheet points past the end of its BIN file, usually because the BIN
+    /// file is truncated. `bin_sectors` is the size of the BIN file in sectors of the format of
+    /// the track containing the index.
+    #[error(
+        "Index {index} of track {track} at {offset} is past the end of the BIN file \
+            ({bin_sectors} sectors) `{path}`|{line}"
+    )]
+    IndexPastEndOfFile {
+        path: PathBuf,
+        line: u32,
+        track: Bcd,
+        index: Bcd,
+        offset: Msf,
+        bin_sectors: u64,
+    },
     #[error("Disc format error in file `{path}`: {desc}")]
     BadImage { path: PathBuf, desc: String },
     #[error("Attempted to access an invalid track number")]
@@ -185,6 +798,12 @@ pub enum CdError {
     PreLeadInPosition,
     #[error("Couldn't handle disc position that's outside of the disc")]
     OutOfDiscPosition,
+    #[error("Sector header MSF {header} doesn't match the subchannel Q MSF {subq}")]
+    HeaderMsfMismatch { header: Msf, subq: Msf },
+    #[error("File not found: {0}")]
+    FileNotFound(String),
+    #[error("Invalid filesystem: {0}")]
+    BadFilesystem(String),
     #[error("ZIP format error: {0}")]
     ZipError(#[from] zip::result::ZipError),
 }
@@ -198,3 +817,191 @@ fn cderror_display() {
     // `#[error("...")]` for every variant
     println!("{}", CdError::BadTrack);
 }
+
+#[test]
+fn sector_layouts() {
+    let formats = [
+        TrackFormat::Audio,
+        TrackFormat::Mode1,
+        TrackFormat::Mode2,
+        TrackFormat::Mode2Xa,
+        TrackFormat::Mode2CdI,
+    ];
+
+    for &f in &formats {
+        assert_eq!(f.default_raw_sector_size(), 2352);
+        assert!(f.supports_layout(SectorLayout::Raw));
+        assert!(f.supports_layout(SectorLayout::RawWithSubchannel));
+    }
+
+    for &(layout, size) in &[
+        (SectorLayout::Raw, 2352),
+        (SectorLayout::RawWithSubchannel, 2448),
+        (SectorLayout::Mode2Headerless, 2336),
+        (SectorLayout::Form2Payload, 2324),
+        (SectorLayout::Payload, 2048),
+    ] {
+        assert_eq!(layout.sector_size(), size);
+    }
+
+    assert!(!TrackFormat::Audio.supports_layout(SectorLayout::Payload));
+    assert!(!TrackFormat::Audio.supports_layout(SectorLayout::Mode2Headerless));
+    assert!(TrackFormat::Mode1.supports_layout(SectorLayout::Payload));
+    assert!(!TrackFormat::Mode1.supports_layout(SectorLayout::Mode2Headerless));
+    assert!(!TrackFormat::Mode2.supports_layout(SectorLayout::Payload));
+    assert!(TrackFormat::Mode2.supports_layout(SectorLayout::Mode2Headerless));
+    assert!(TrackFormat::Mode2Xa.supports_layout(SectorLayout::Form2Payload));
+    assert!(!TrackFormat::Mode1.supports_layout(SectorLayout::Form2Payload));
+}
+
+#[test]
+fn shift_track() {
+    let track = Track {
+        track: Bcd::ONE,
+        format: TrackFormat::Audio,
+        start: Msf::from_sector_index(150).unwrap(),
+        length: Msf::from_sector_index(1000).unwrap(),
+        control: subchannel::AdrControl::AUDIO,
+    };
+
+    let new_start = Msf::from_sector_index(20_000).unwrap();
+
+    let shifted = track
+        .shifted(new_start)
+        .unwrap()
+        .with_number(Bcd::from_binary(7).unwrap());
+
+    assert_eq!(shifted.track, Bcd::from_binary(7).unwrap());
+    assert_eq!(shifted.start, new_start);
+    assert_eq!(shifted.length, track.length);
+    assert_eq!(shifted.format, track.format);
+    assert_eq!(shifted.control, track.control);
+
+    for &i in &[0, 1, 500, 999] {
+        let track_msf = Msf::from_sector_index(i).unwrap();
+
+        let abs = shifted.absolute_msf(track_msf).unwrap();
+
+        assert_eq!(abs, new_start + track_msf);
+        assert_eq!(abs - shifted.start, track_msf);
+        assert_eq!(
+            abs.sector_index() - track.absolute_msf(track_msf).unwrap().sector_index(),
+            20_000 - 150
+        );
+    }
+
+    assert!(matches!(
+        shifted.absolute_msf(track.length),
+        Err(CdError::EndOfTrack)
+    ));
+
+    // The track would end past 99:59:74
+    assert!(matches!(
+        track.shifted(Msf::MAX),
+        Err(CdError::OutOfDiscPosition)
+    ));
+}
+
+#[test]
+fn from_cdrom_mode() {
+    use sector::CdRomMode;
+
+    let sessions = [
+        SessionFormat::CdDaCdRom,
+        SessionFormat::CdXa,
+        SessionFormat::Cdi,
+    ];
+
+    for &session in &sessions {
+        assert_eq!(
+            TrackFormat::from_cdrom_mode(CdRomMode::Empty, session),
+            None
+        );
+        assert_eq!(
+            TrackFormat::from_cdrom_mode(CdRomMode::Mode1, session),
+            Some(TrackFormat::Mode1)
+        );
+    }
+
+    for &(session, format) in &[
+        (SessionFormat::CdDaCdRom, TrackFormat::Mode2),
+        (SessionFormat::CdXa, TrackFormat::Mode2Xa),
+        (SessionFormat::Cdi, TrackFormat::Mode2CdI),
+    ] {
+        assert_eq!(
+            TrackFormat::from_cdrom_mode(CdRomMode::Mode2, session),
+            Some(format)
+        );
+    }
+
+    // Round-trip through `cdrom_mode`
+    for &format in &[
+        TrackFormat::Mode1,
+        TrackFormat::Mode2,
+        TrackFormat::Mode2Xa,
+        TrackFormat::Mode2CdI,
+    ] {
+        let session = Toc::new(vec![Track {
+            track: Bcd::ONE,
+            format,
+            start: Msf::from_sector_index(150).unwrap(),
+            length: Msf::from_sector_index(1000).unwrap(),
+            control: subchannel::AdrControl::DATA,
+        }])
+        .unwrap()
+        .session_format();
+
+        let mode = format.cdrom_mode().unwrap();
+
+        assert_eq!(TrackFormat::from_cdrom_mode(mode, session), Some(format));
+    }
+}
+
+#[test]
+fn track_ordering() {
+    use std::collections::{BTreeSet, HashSet};
+    use subchannel::AdrControl;
+
+    let track = |n: u8, format: TrackFormat, start: &str, length: &str| Track {
+        track: Bcd::from_binary(n).unwrap(),
+        format,
+        start: start.parse().unwrap(),
+        length: length.parse().unwrap(),
+        control: if format.is_audio() {
+            AdrControl::AUDIO
+        } else {
+            AdrControl::DATA
+        },
+    };
+
+    let toc_order = vec![
+        track(1, TrackFormat::Mode2Xa, "00:02:00", "01:02:51"),
+        // Empty track, starts at the same position as the next one
+        track(2, TrackFormat::Audio, "01:06:51", "00:00:00"),
+        track(3, TrackFormat::Audio, "01:06:51", "00:07:12"),
+        track(4, TrackFormat::Audio, "01:15:63", "01:41:01"),
+    ];
+
+    let mut shuffled = vec![
+        toc_order[3].clone(),
+        toc_order[1].clone(),
+        toc_order[0].clone(),
+        toc_order[2].clone(),
+    ];
+
+    shuffled.sort();
+
+    assert!(shuffled == toc_order);
+
+    let set: HashSet<Track> = shuffled.iter().cloned().collect();
+    assert_eq!(set.len(), 4);
+    assert!(set.contains(&toc_order[2]));
+
+    let sorted: Vec<Track> = shuffled
+        .into_iter()
+        .rev()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    assert!(sorted == toc_order);
+}
@@ -0,0 +1,49 @@
+//! Lookup of the media catalog number and ISRCs stored in the Q subchannel
+
+use msf::{Msf, MsfRange};
+use subchannel;
+use {Bcd, CdResult, DiscPosition, Image};
+
+/// Number of sectors scanned when looking for Mode 2 and Mode 3 Q frames. The red book requires
+/// them to be present in at least one out of every 100 consecutive sectors.
+const SCAN_SECTORS: u32 = 100;
+
+/// Decode the raw Q subchannel of the first `SCAN_SECTORS` sectors in `[start, end)` with
+/// `decode` and return the first value found. Sectors without raw subchannel data are skipped,
+/// some images only attach it to the sectors containing Mode 2 and Mode 3 frames.
+fn scan_subq<I, F>(image: &mut I, start: Msf, end: Msf, decode: F) -> CdResult<Option<String>>
+where
+    I: Image + ?Sized,
+    F: Fn(&[u8; 12]) -> Option<String>,
+{
+    for msf in MsfRange::new(start, end).take(SCAN_SECTORS as usize) {
+        let sector = image.read_sector(DiscPosition::Program(msf))?;
+
+        let subq = match sector.raw_subchannel() {
+            Some(raw) => raw.q(),
+            None => continue,
+        };
+
+        if let Some(v) = decode(&subq) {
+            return Ok(Some(v));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Implementation of `Image::media_catalog_number`
+pub(crate) fn media_catalog_number<I: Image + ?Sized>(image: &mut I) -> CdResult<Option<String>> {
+    let toc = image.toc();
+    let (start, end) = (toc.tracks()[0].start, toc.lead_out_start());
+
+    scan_subq(image, start, end, subchannel::mcn_from_q_raw)
+}
+
+/// Implementation of `Image::track_isrc`
+pub(crate) fn track_isrc<I: Image + ?Sized>(image: &mut I, track: Bcd) -> CdResult<Option<String>> {
+    let t = image.toc().track(track)?;
+    let (start, end) = (t.start, t.start + t.length);
+
+    scan_subq(image, start, end, subchannel::isrc_from_q_raw)
+}
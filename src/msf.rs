@@ -9,11 +9,16 @@ use std::str::FromStr;
 use std::{cmp, fmt, ops};
 
 use bcd::Bcd;
-use {CdError, DiscPosition};
+use {CdError, CdResult, DiscPosition};
 
 /// CD "minute:second:frame" timestamp, given as triplet of *BCD*
 /// encoded bytes. In this context "frame" is synonymous with
 /// "sector".
+///
+/// The `+` and `-` operators panic if the result is out of range, they should only be used when
+/// the operands are known to be valid. Code dealing with values read from an image should use
+/// `try_add`/`try_sub` (which return `CdError::InvalidMsf`), the `checked_*` or the
+/// `saturating_*` methods instead.
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Msf(Bcd, Bcd, Bcd);
@@ -82,6 +87,29 @@ impl Msf {
         self.2.binary()
     }
 
+    /// Returns a copy of this MSF with the minutes replaced by `m`. Returns `None` if `m` is
+    /// greater than 99.
+    pub fn with_minutes(self, m: u8) -> Option<Msf> {
+        Msf::new(Bcd::from_binary(m)?, self.1, self.2)
+    }
+
+    /// Returns a copy of this MSF with the seconds replaced by `s`. Returns `None` if `s` is
+    /// greater than 59.
+    pub fn with_seconds(self, s: u8) -> Option<Msf> {
+        Msf::new(self.0, Bcd::from_binary(s)?, self.2)
+    }
+
+    /// Returns a copy of this MSF with the frames replaced by `f`. Returns `None` if `f` is
+    /// greater than 74.
+    pub fn with_frames(self, f: u8) -> Option<Msf> {
+        Msf::new(self.0, self.1, Bcd::from_binary(f)?)
+    }
+
+    /// Returns this MSF rounded down to the start of the second
+    pub const fn floor_to_second(self) -> Msf {
+        Msf(self.0, self.1, Bcd::ZERO)
+    }
+
     /// Takes this MSF as an absolute position and turn it into a `DiscPosition`
     pub const fn to_disc_position(self) -> DiscPosition {
         DiscPosition::Program(self)
@@ -152,6 +180,13 @@ impl Msf {
         Msf::from_sector_index(a + b)
     }
 
+    /// Computes `self + nsectors`, returning `None` if overflow occurred
+    pub fn checked_add_sectors(self, nsectors: u32) -> Option<Msf> {
+        self.sector_index()
+            .checked_add(nsectors)
+            .and_then(Msf::from_sector_index)
+    }
+
     /// Computes `self - rhs`, returning `None` if overflow occurred
     pub fn checked_sub(self, rhs: Msf) -> Option<Msf> {
         let a = self.sector_index();
@@ -160,6 +195,28 @@ impl Msf {
         a.checked_sub(b).and_then(Msf::from_sector_index)
     }
 
+    /// Computes `self + rhs`, returning `CdError::InvalidMsf` if overflow occurred
+    #[must_use = "this returns the result of the operation, without modifying the original"]
+    pub fn try_add(self, rhs: Msf) -> CdResult<Msf> {
+        self.checked_add(rhs).ok_or(CdError::InvalidMsf)
+    }
+
+    /// Computes `self - rhs`, returning `CdError::InvalidMsf` if overflow occurred
+    #[must_use = "this returns the result of the operation, without modifying the original"]
+    pub fn try_sub(self, rhs: Msf) -> CdResult<Msf> {
+        self.checked_sub(rhs).ok_or(CdError::InvalidMsf)
+    }
+
+    /// Computes `self + rhs`, saturating at `Msf::MAX`
+    pub fn saturating_add(self, rhs: Msf) -> Msf {
+        self.checked_add(rhs).unwrap_or(Msf::MAX)
+    }
+
+    /// Computes `self - rhs`, saturating at `Msf::ZERO`
+    pub fn saturating_sub(self, rhs: Msf) -> Msf {
+        self.checked_sub(rhs).unwrap_or(Msf::ZERO)
+    }
+
     /// Pack the Msf in a single BCD u32, makes it easier to do
     /// comparisons without having to do a full decimal conversion
     /// like `sector_index`.
@@ -170,6 +227,79 @@ impl Msf {
     }
 }
 
+/// Iterator over a half-open range of MSFs `[start, end)`. Can be iterated in both directions.
+///
+/// ```
+/// use cdimage::msf::{Msf, MsfRange};
+///
+/// let start: Msf = "00:01:73".parse().unwrap();
+/// let end: Msf = "00:02:01".parse().unwrap();
+///
+/// let msfs: Vec<Msf> = MsfRange::from(start..end).collect();
+///
+/// assert_eq!(msfs.len(), 3);
+/// assert_eq!(msfs[2], "00:02:00".parse().unwrap());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MsfRange {
+    /// Sector index of the next MSF returned from the front
+    front: u32,
+    /// Sector index one past the next MSF returned from the back
+    back: u32,
+}
+
+impl MsfRange {
+    /// Create a range containing all MSFs from `start` (inclusive) to `end` (exclusive). If
+    /// `end <= start` the range is empty.
+    pub fn new(start: Msf, end: Msf) -> MsfRange {
+        let front = start.sector_index();
+        let back = cmp::max(front, end.sector_index());
+
+        MsfRange { front, back }
+    }
+}
+
+impl From<ops::Range<Msf>> for MsfRange {
+    fn from(r: ops::Range<Msf>) -> MsfRange {
+        MsfRange::new(r.start, r.end)
+    }
+}
+
+impl Iterator for MsfRange {
+    type Item = Msf;
+
+    fn next(&mut self) -> Option<Msf> {
+        if self.front >= self.back {
+            return None;
+        }
+
+        let msf = Msf::from_sector_index(self.front);
+        self.front += 1;
+
+        msf
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.back - self.front) as usize;
+
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for MsfRange {
+    fn next_back(&mut self) -> Option<Msf> {
+        if self.front >= self.back {
+            return None;
+        }
+
+        self.back -= 1;
+
+        Msf::from_sector_index(self.back)
+    }
+}
+
+impl ExactSizeIterator for MsfRange {}
+
 impl fmt::Display for Msf {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let Msf(m, s, f) = *self;
@@ -255,9 +385,10 @@ impl FromStr for Msf {
 
 #[cfg(test)]
 mod test {
-    use super::Msf;
+    use super::{Msf, MsfRange};
     use bcd::Bcd;
     use std::str::FromStr;
+    use CdError;
 
     #[test]
     fn conversions() {
@@ -298,6 +429,29 @@ mod test {
         assert!(m - n == msf(0x11, 0x41, 0x66));
     }
 
+    #[test]
+    fn fallible_arithmetic() {
+        let one = msf(0x00, 0x00, 0x01);
+        let m = msf(0x99, 0x59, 0x73);
+
+        assert_eq!(m.try_add(one).unwrap(), Msf::MAX);
+        assert!(matches!(Msf::MAX.try_add(one), Err(CdError::InvalidMsf)));
+        assert!(matches!(
+            Msf::MAX.try_add(Msf::MAX),
+            Err(CdError::InvalidMsf)
+        ));
+
+        assert_eq!(Msf::MAX.try_sub(Msf::MAX).unwrap(), Msf::ZERO);
+        assert_eq!(Msf::MAX.try_sub(one).unwrap(), m);
+        assert!(matches!(Msf::ZERO.try_sub(one), Err(CdError::InvalidMsf)));
+        assert!(matches!(m.try_sub(Msf::MAX), Err(CdError::InvalidMsf)));
+
+        assert_eq!(m.saturating_add(one), Msf::MAX);
+        assert_eq!(Msf::MAX.saturating_add(Msf::MAX), Msf::MAX);
+        assert_eq!(Msf::MAX.saturating_sub(one), m);
+        assert_eq!(one.saturating_sub(Msf::MAX), Msf::ZERO);
+    }
+
     #[test]
     fn from_str() {
         assert!(Msf::from_str("00:00:00").unwrap() == msf(0x00, 0x00, 0x00));
@@ -314,6 +468,67 @@ mod test {
         assert!(Msf::from_str("00:00:75").is_err());
     }
 
+    #[test]
+    fn range() {
+        let start = msf(0x00, 0x59, 0x73);
+        let end = msf(0x01, 0x00, 0x02);
+
+        let expected = [
+            msf(0x00, 0x59, 0x73),
+            msf(0x00, 0x59, 0x74),
+            msf(0x01, 0x00, 0x00),
+            msf(0x01, 0x00, 0x01),
+        ];
+
+        let r = MsfRange::from(start..end);
+        assert_eq!(r.len(), 4);
+
+        assert_eq!(r.clone().collect::<Vec<_>>(), expected);
+        assert_eq!(
+            r.rev().collect::<Vec<_>>(),
+            expected.iter().rev().cloned().collect::<Vec<_>>()
+        );
+
+        // Mixing both ends
+        let mut r = MsfRange::new(start, end);
+        assert_eq!(r.next(), Some(expected[0]));
+        assert_eq!(r.next_back(), Some(expected[3]));
+        assert_eq!(r.next_back(), Some(expected[2]));
+        assert_eq!(r.next(), Some(expected[1]));
+        assert_eq!(r.next(), None);
+        assert_eq!(r.next_back(), None);
+
+        assert_eq!(MsfRange::new(end, start).count(), 0);
+        assert_eq!(MsfRange::new(start, start).count(), 0);
+        assert_eq!(MsfRange::new(Msf::MAX, Msf::MAX).count(), 0);
+    }
+
+    #[test]
+    fn add_sectors() {
+        let m = msf(0x00, 0x59, 0x70);
+
+        assert_eq!(m.checked_add_sectors(0), Some(m));
+        assert_eq!(m.checked_add_sectors(10), Some(msf(0x01, 0x00, 0x05)));
+        assert_eq!(m.checked_add_sectors(75 * 60), Some(msf(0x01, 0x59, 0x70)));
+        assert_eq!(Msf::MAX.checked_add_sectors(1), None);
+        assert_eq!(Msf::ZERO.checked_add_sectors(u32::MAX), None);
+    }
+
+    #[test]
+    fn withers() {
+        let m = msf(0x12, 0x34, 0x56);
+
+        assert_eq!(m.with_frames(0), Some(msf(0x12, 0x34, 0x00)));
+        assert_eq!(m.with_frames(74), Some(msf(0x12, 0x34, 0x74)));
+        assert_eq!(m.with_frames(75), None);
+        assert_eq!(m.with_seconds(7), Some(msf(0x12, 0x07, 0x56)));
+        assert_eq!(m.with_seconds(60), None);
+        assert_eq!(m.with_minutes(99), Some(msf(0x99, 0x34, 0x56)));
+        assert_eq!(m.with_minutes(100), None);
+        assert_eq!(m.floor_to_second(), msf(0x12, 0x34, 0x00));
+        assert_eq!(Msf::MAX.floor_to_second(), msf(0x99, 0x59, 0x00));
+    }
+
     fn msf(m: u8, s: u8, f: u8) -> Msf {
         Msf::new(
             Bcd::from_bcd(m).unwrap(),
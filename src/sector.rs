@@ -1,10 +1,15 @@
 //! CD sector interface.
 
+use bcd::Bcd;
 use crc::crc32;
 use ecc::compute_ecc;
-use msf::Msf;
-use subchannel::Q;
-use {CdError, CdResult, TrackFormat};
+use msf::{Msf, MsfRange};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use subchannel::{self, RawSubchannel, Q};
+use {CdError, CdResult, DiscPosition, Image, TrackFormat};
+
+pub mod deemphasis;
 
 /// Structure containing a single sector. For better peformance it tries to be as lazy as possible
 /// and regenerate missing sector data only if it's requested.
@@ -18,36 +23,67 @@ pub struct Sector {
     q: Q,
     /// Format of the track this sector is contained in
     format: TrackFormat,
+    /// Raw subchannel data, if the image format stores it
+    subchannel: Option<RawSubchannel>,
+    /// True if the sector couldn't be read from the image and was synthesized instead
+    #[cfg_attr(feature = "serde", serde(default))]
+    synthesized: bool,
 }
 
 impl Sector {
+    /// Size of a sector serialized with `Sector::serialize_compact`, without raw subchannel data
+    pub const COMPACT_SIZE: usize = 2352 + 12 + 1;
+
     /// Create a sector containing only zeroes with the given Q subchannel data and track format.
     /// The resulting sector may not be valid since any ECC/EDC data will not be generated. Use
     /// `Sector::empty()` if you want a correctly formatted sector with no payload.
     ///
     /// Returns an error if the format and Q data are not compatible.
     pub fn uninitialized(q: Q, format: TrackFormat) -> CdResult<Sector> {
-        let fmt_ok = match format {
-            TrackFormat::Audio => q.is_audio(),
-            _ => q.is_data(),
-        };
-
-        if !fmt_ok {
-            return Err(CdError::BadFormat);
-        }
+        check_q_format(&q, format)?;
 
         Ok(Sector {
             data: [0; 2352],
             q,
             format,
+            subchannel: None,
+            synthesized: false,
         })
     }
 
+    /// Create an audio sector containing 2352 bytes of digital silence with the given Q subchannel
+    /// data.
+    ///
+    /// Returns `CdError::BadFormat` if `q` is not for an audio track.
+    pub fn silence(q: Q) -> CdResult<Sector> {
+        Sector::uninitialized(q, TrackFormat::Audio)
+    }
+
+    /// Replace the Q subchannel data and track format of this sector, leaving the data untouched.
+    /// The raw subchannel data is removed. Used to reuse an existing `Sector` when reading a new
+    /// one.
+    ///
+    /// Returns an error if the format and Q data are not compatible.
+    pub(crate) fn reset(&mut self, q: Q, format: TrackFormat) -> CdResult<()> {
+        check_q_format(&q, format)?;
+
+        self.q = q;
+        self.format = format;
+        self.subchannel = None;
+        self.synthesized = false;
+
+        Ok(())
+    }
+
     /// Create an empty sector with the given Q subchannel data and track format. If the format
     /// requires headers or CDC/ECC data, it will be generated, the rest of the payload will be all
     /// zeroes.
     ///
-    /// Returns an error if the format and Q data are not compatible.
+    /// Audio sectors have no header or EDC/ECC, so for `TrackFormat::Audio` this returns the same
+    /// sector as `Sector::silence`.
+    ///
+    /// Returns an error if the format and Q data are not compatible, for instance audio Q data
+    /// with a CD-ROM format.
     pub fn empty(q: Q, format: TrackFormat) -> CdResult<Sector> {
         let mut sector = Sector::uninitialized(q, format)?;
 
@@ -130,68 +166,62 @@ impl Sector {
         }
     }
 
+    /// Make the sector valid for its format after its payload has been modified: rewrite the
+    /// CD-ROM header (and the default XA submode if needed, see `Sector::write_headers`) then the
+    /// EDC/ECC.
+    ///
+    /// Returns `CdError::BadFormat` if the Q subchannel data is not compatible with the sector's
+    /// format, in which case the sector is left untouched.
+    pub fn normalize(&mut self) -> CdResult<()> {
+        check_q_format(&self.q, self.format)?;
+
+        self.write_headers();
+        self.write_edc_ecc();
+
+        Ok(())
+    }
+
     /// If the sector's format includes ECC and/or EDC data, recompute it and write it to the
     /// sector.
     pub fn write_edc_ecc(&mut self) {
-        // Calculate and add the ECC/EDC data
+        // Mode 2 and audio have no ECC or EDC, the whole sector after the header is user data
+        let coverage = match self.edc_coverage() {
+            Some(c) => c,
+            None => return,
+        };
+
+        let edc = coverage.end;
+
+        // Form 2 has EDC but no ECC. Technically even the EDC is optional and could be left to
+        // all zeroes (the green book doesn't even call it EDC but rather "reserved for quality
+        // control"), but let's write it to make accidental corruptions easier to identify
+        let crc = crc32(&self.data[coverage]).to_le_bytes();
+        self.data[edc..edc + 4].copy_from_slice(&crc);
+
         match self.format {
-            TrackFormat::Audio => (),
             TrackFormat::Mode1 => {
-                let crc = crc32(&self.data[0..2064]).to_le_bytes();
-                self.data[2064] = crc[0];
-                self.data[2065] = crc[1];
-                self.data[2066] = crc[2];
-                self.data[2067] = crc[3];
-
                 for i in (2068..).take(8) {
                     self.data[i] = 0;
                 }
 
                 compute_ecc(array_mut_ref![self.data, 12, 2340]);
             }
-            TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => {
-                // Look for the form in the Mode2 XA/CDi subheader
-                let form = if self.data[18] & (1 << 5) == 0 {
-                    XaForm::Form1
-                } else {
-                    XaForm::Form2
-                };
+            _ if self.has_ecc() => {
+                // Unlike Mode-1, we must zero the MSF and Mode before computing the ECC
+                let tmp = [self.data[12], self.data[13], self.data[14], self.data[15]];
+                self.data[12] = 0;
+                self.data[13] = 0;
+                self.data[14] = 0;
+                self.data[15] = 0;
 
-                match form {
-                    XaForm::Form1 => {
-                        let crc = crc32(&self.data[16..2072]).to_le_bytes();
-                        self.data[2072] = crc[0];
-                        self.data[2073] = crc[1];
-                        self.data[2074] = crc[2];
-                        self.data[2075] = crc[3];
-
-                        // Unlike Mode-1, we must zero the MSF and Mode before computing the ECC
-                        let tmp = [self.data[12], self.data[13], self.data[14], self.data[15]];
-                        self.data[12] = 0;
-                        self.data[13] = 0;
-                        self.data[14] = 0;
-                        self.data[15] = 0;
-
-                        compute_ecc(array_mut_ref![self.data, 12, 2340]);
-
-                        self.data[12] = tmp[0];
-                        self.data[13] = tmp[1];
-                        self.data[14] = tmp[2];
-                        self.data[15] = tmp[3];
-                    }
-                    XaForm::Form2 => {
-                        // Form 2 has EDC but no ECC. Technically even the EDC is optional and
-                        // could be left to all zeroes (the green book doesn't even call it EDC but
-                        // rather "reserved for quality control"), but let's write it to make
-                        // accidental corruptions easier to identify
-                        let crc = crc32(&self.data[16..2348]).to_le_bytes();
-                        self.data[2348] = crc[0];
-                        self.data[2349] = crc[1];
-                        self.data[2350] = crc[2];
-                        self.data[2351] = crc[3];
-                    }
-                }
+                compute_ecc(array_mut_ref![self.data, 12, 2340]);
+
+                self.data[12] = tmp[0];
+                self.data[13] = tmp[1];
+                self.data[14] = tmp[2];
+                self.data[15] = tmp[3];
             }
+            _ => (),
         }
     }
 
@@ -199,54 +229,143 @@ impl Sector {
     /// Returns true if the EDC is valid or if the sector does not contain any EDC (for instance
     /// for a CD-DA audio track)
     pub fn edc_valid(&self) -> bool {
-        match self.format {
-            TrackFormat::Audio => true,
-            TrackFormat::Mode1 => {
-                let crc = crc32(&self.data[0..2064]);
-                let expected = u32::from_le_bytes([
-                    self.data[2064],
-                    self.data[2065],
-                    self.data[2066],
-                    self.data[2067],
-                ]);
-
-                expected == crc
-            }
-            TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => {
-                // Look for the form in the Mode2 XA/CDi subheader
-                let form = if self.data[18] & (1 << 5) == 0 {
-                    XaForm::Form1
-                } else {
-                    XaForm::Form2
-                };
+        let coverage = match self.edc_coverage() {
+            Some(c) => c,
+            None => return true,
+        };
+
+        // The Form 2 CRC is optional and is set to zero if not used
+        self.edc_matches(coverage) || (!self.has_ecc() && self.stored_edc(2348) == 0)
+    }
+
+    /// Guess the format of the sector from its raw contents, ignoring `Sector::format`. This is
+    /// useful to find tracks whose format is mislabeled in the image.
+    ///
+    /// Sectors without a CD-ROM sync pattern are assumed to be audio. Mode 1 sectors are only
+    /// recognized if their EDC is valid. Mode 2 sectors with identical subheader copies and a valid
+    /// Form 1 or Form 2 EDC are reported as `TrackFormat::Mode2Xa` (CD-i sectors can't be told
+    /// apart from CD-ROM XA ones), other Mode 2 sectors as `TrackFormat::Mode2`.
+    ///
+    /// Returns `None` for empty (mode 0) sectors, invalid modes and Mode 1 sectors with a bad EDC.
+    pub fn detect_mode(&self) -> Option<TrackFormat> {
+        let sync =
+            self.data[0] == 0 && self.data[11] == 0 && self.data[1..11].iter().all(|&b| b == 0xff);
+
+        if !sync {
+            return Some(TrackFormat::Audio);
+        }
 
-                match form {
-                    XaForm::Form1 => {
-                        let crc = crc32(&self.data[16..2072]);
-                        let expected = u32::from_le_bytes([
-                            self.data[2072],
-                            self.data[2073],
-                            self.data[2074],
-                            self.data[2075],
-                        ]);
-
-                        expected == crc
-                    }
-                    XaForm::Form2 => {
-                        // Form 2 has EDC but no ECC
-                        let crc = crc32(&self.data[16..2348]);
-                        let expected = u32::from_le_bytes([
-                            self.data[2348],
-                            self.data[2349],
-                            self.data[2350],
-                            self.data[2351],
-                        ]);
-
-                        // The CRC is optional and is set to zero if not used
-                        expected == crc || expected == 0
-                    }
+        match self.data[15] {
+            1 if self.edc_matches(0..2064) => Some(TrackFormat::Mode1),
+            2 => {
+                let xa = self.data[16..20] == self.data[20..24]
+                    && match self.raw_xa_form() {
+                        XaForm::Form1 => self.edc_matches(16..2072),
+                        XaForm::Form2 => self.edc_matches(16..2348) || self.stored_edc(2348) == 0,
+                    };
+
+                if xa {
+                    Some(TrackFormat::Mode2Xa)
+                } else {
+                    Some(TrackFormat::Mode2)
                 }
             }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the EDC stored right after `coverage` matches its contents
+    fn edc_matches(&self, coverage: Range<usize>) -> bool {
+        self.stored_edc(coverage.end) == crc32(&self.data[coverage])
+    }
+
+    /// Returns the EDC stored at `offset`
+    fn stored_edc(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(*array_ref![self.data, offset, 4])
+    }
+
+    /// Returns the 4 bytes of the EDC stored in the sector, in the order they're stored in (the
+    /// CRC is little-endian). The EDC is located after the user data of Mode 1 and Mode 2 XA/CD-i
+    /// sectors (both Form 1 and Form 2).
+    ///
+    /// Returns `CdError::BadFormat` for audio and plain Mode 2 sectors which don't have an EDC.
+    pub fn edc_bytes(&self) -> CdResult<[u8; 4]> {
+        match self.edc_coverage() {
+            Some(c) => Ok(*array_ref![self.data, c.end, 4]),
+            None => Err(CdError::BadFormat),
+        }
+    }
+
+    /// Returns the 276 bytes of the P and Q parity ECC stored at the end of Mode 1 and Mode 2
+    /// XA/CD-i Form 1 sectors.
+    ///
+    /// Returns `CdError::BadFormat` for Form 2, audio and plain Mode 2 sectors which don't have
+    /// any ECC.
+    pub fn ecc_bytes(&self) -> CdResult<&[u8; 276]> {
+        if self.has_ecc() {
+            Ok(array_ref![self.data, 2076, 276])
+        } else {
+            Err(CdError::BadFormat)
+        }
+    }
+
+    /// Returns the range of bytes covered by the EDC, the EDC itself is stored right after it.
+    /// Returns `None` if the sector doesn't have an EDC.
+    fn edc_coverage(&self) -> Option<Range<usize>> {
+        match self.format {
+            TrackFormat::Audio | TrackFormat::Mode2 => None,
+            TrackFormat::Mode1 => Some(0..2064),
+            TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => match self.raw_xa_form() {
+                XaForm::Form1 => Some(16..2072),
+                XaForm::Form2 => Some(16..2348),
+            },
+        }
+    }
+
+    /// Returns true if the sector contains ECC data (Mode 1 and Mode 2 XA/CD-i Form 1)
+    fn has_ecc(&self) -> bool {
+        match self.format {
+            TrackFormat::Audio | TrackFormat::Mode2 => false,
+            TrackFormat::Mode1 => true,
+            TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => self.raw_xa_form() == XaForm::Form1,
+        }
+    }
+
+    /// Look for the form in the Mode2 XA/CDi subheader, without validating it
+    fn raw_xa_form(&self) -> XaForm {
+        if self.data[18] & (1 << 5) == 0 {
+            XaForm::Form1
+        } else {
+            XaForm::Form2
+        }
+    }
+
+    /// Returns true if `other` has the same user data as this sector. Unlike `==` the Q subchannel
+    /// data and the fields that can be regenerated (sync pattern, header, XA subheader, EDC and
+    /// ECC) are ignored, so that sectors with stale or missing EDC/ECC compare equal.
+    ///
+    /// The user data is the whole sector for audio tracks, 2048 bytes for Mode 1 and Mode 2 Form 1,
+    /// 2324 bytes for Mode 2 Form 2 and 2336 bytes for plain Mode 2.
+    pub fn content_eq(&self, other: &Sector) -> bool {
+        self.user_data() == other.user_data()
+    }
+
+    /// Returns true if the user data of the sector (see `Sector::content_eq`) only contains zeroes,
+    /// like the sectors returned by `Sector::empty`. For audio sectors that means digital silence.
+    pub fn is_blank(&self) -> bool {
+        self.user_data().iter().all(|&b| b == 0)
+    }
+
+    /// Returns the portion of the sector containing the user data, see `Sector::content_eq`
+    fn user_data(&self) -> &[u8] {
+        match self.format {
+            TrackFormat::Audio => &self.data,
+            TrackFormat::Mode1 => &self.data[16..2064],
+            TrackFormat::Mode2 => &self.data[16..],
+            TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => match self.raw_xa_form() {
+                XaForm::Form1 => &self.data[24..2072],
+                XaForm::Form2 => &self.data[24..2348],
+            },
         }
     }
 
@@ -270,6 +389,103 @@ impl Sector {
         self.format
     }
 
+    /// Returns the raw subchannel data of this sector if the image format stores it
+    pub fn raw_subchannel(&self) -> Option<&RawSubchannel> {
+        self.subchannel.as_ref()
+    }
+
+    /// Attach raw subchannel data to this sector. The Q subchannel returned by `Sector::q` is left
+    /// untouched.
+    pub fn set_raw_subchannel(&mut self, subchannel: RawSubchannel) {
+        self.subchannel = Some(subchannel);
+    }
+
+    /// Returns true if this sector couldn't be read from the image because of an error and was
+    /// synthesized instead, see `cue::ReadErrorPolicy`
+    pub fn is_synthesized(&self) -> bool {
+        self.synthesized
+    }
+
+    /// Flag this sector as synthesized, see `Sector::is_synthesized`
+    pub(crate) fn set_synthesized(&mut self) {
+        self.synthesized = true;
+    }
+
+    /// Returns the 2352 bytes of sector data followed by the 96 bytes of raw interleaved
+    /// subchannel data, the layout used by many drives and image formats for raw reads. The Q
+    /// subchannel is generated from `Sector::q`, the other subchannels come from the raw
+    /// subchannel data if available and are set to 0 otherwise.
+    ///
+    /// If the raw subchannel data contains a Mode 2 (media catalog number) or Mode 3 (ISRC) Q
+    /// frame it's used instead of `Sector::q`, which can only hold Mode 1 position data.
+    pub fn data_2448(&self) -> [u8; 2448] {
+        let mut raw = [0u8; 2448];
+
+        raw[..2352].copy_from_slice(&self.data);
+
+        let raw_q = match self.subchannel {
+            Some(ref sub) => subchannel::is_mcn_or_isrc_q_raw(&sub.q()),
+            None => false,
+        };
+
+        if !raw_q {
+            raw[2352..].copy_from_slice(&self.q.to_raw_interleaved());
+        }
+
+        if let Some(ref sub) = self.subchannel {
+            let mask = if raw_q { 0xff } else { !0x40 };
+
+            for (r, &s) in raw[2352..].iter_mut().zip(sub.as_bytes().iter()) {
+                *r |= s & mask;
+            }
+        }
+
+        raw
+    }
+
+    /// Serialize the sector in a compact binary format that doesn't require serde: the 2352 bytes
+    /// of data, the 12 bytes of raw Q subchannel (see `Q::to_raw`) and a format tag, optionally
+    /// followed by the 96 bytes of raw subchannel data if the sector has some. The result is
+    /// `Sector::COMPACT_SIZE` or `Sector::COMPACT_SIZE + 96` bytes long.
+    pub fn serialize_compact(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Sector::COMPACT_SIZE + 96);
+
+        out.extend_from_slice(&self.data);
+        out.extend_from_slice(&self.q.to_raw());
+        out.push(format_tag(self.format));
+
+        if let Some(ref sub) = self.subchannel {
+            out.extend_from_slice(sub.as_bytes());
+        }
+
+        out
+    }
+
+    /// Rebuild a sector serialized with `Sector::serialize_compact`. Returns `CdError::BadFormat`
+    /// if `bytes` has the wrong length or an unknown format tag, or an error if the Q data is
+    /// invalid or doesn't match the format.
+    pub fn deserialize_compact(bytes: &[u8]) -> CdResult<Sector> {
+        let subchannel = match bytes.len() {
+            Sector::COMPACT_SIZE => None,
+            n if n == Sector::COMPACT_SIZE + 96 => Some(RawSubchannel::new(*array_ref![
+                bytes,
+                Sector::COMPACT_SIZE,
+                96
+            ])),
+            _ => return Err(CdError::BadFormat),
+        };
+
+        let q = Q::from_raw(*array_ref![bytes, 2352, 12])?;
+        let format = format_from_tag(bytes[2364]).ok_or(CdError::BadFormat)?;
+
+        let mut sector = Sector::uninitialized(q, format)?;
+
+        sector.data.copy_from_slice(&bytes[..2352]);
+        sector.subchannel = subchannel;
+
+        Ok(sector)
+    }
+
     /// Returns the raw 16bit CD-ROM header for this sector. Returns an error if this is not a
     /// CD-ROM track (per sub-Q). If the header wasn't available in the original image format, it
     /// will be created on the fly.
@@ -319,6 +535,65 @@ impl Sector {
         Ok(CdRomHeader { msf, mode })
     }
 
+    /// Overwrite the mode byte of the CD-ROM header. If the mode changes, the EDC/ECC is
+    /// regenerated to match the new layout. Setting `CdRomMode::Empty` clears the sector payload
+    /// (bytes 16 to 2351) since empty sectors contain no data and no EDC/ECC.
+    ///
+    /// Returns `CdError::BadFormat` if this is not a CD-ROM sector or if `mode` is not
+    /// `CdRomMode::Empty` and doesn't match the sector's format (for instance setting
+    /// `CdRomMode::Mode1` on a `TrackFormat::Mode2Xa` sector).
+    pub fn set_cdrom_mode(&mut self, mode: CdRomMode) -> CdResult<()> {
+        let format_mode = match self.format.cdrom_mode() {
+            Some(m) => m,
+            None => return Err(CdError::BadFormat),
+        };
+
+        if mode == CdRomMode::Empty {
+            self.data[15] = mode as u8;
+            for b in self.data[16..].iter_mut() {
+                *b = 0;
+            }
+
+            return Ok(());
+        }
+
+        if mode != format_mode {
+            return Err(CdError::BadFormat);
+        }
+
+        if self.data[15] != mode as u8 {
+            self.data[15] = mode as u8;
+            self.write_edc_ecc();
+        }
+
+        Ok(())
+    }
+
+    /// Make sure that the MSF in the CD-ROM header matches the one in the Q subchannel data.
+    /// Returns `CdError::HeaderMsfMismatch` if they differ, or the same errors as
+    /// `Sector::cdrom_header` if the header can't be parsed.
+    ///
+    /// In the lead-in only the last digit of the minutes is stored in the header (see
+    /// `Sector::write_headers`) so the tens are ignored.
+    pub fn validate_position(&self) -> CdResult<()> {
+        let header = self.cdrom_header()?.msf;
+        let subq = self.q.amsf();
+
+        let matches = if self.q.is_lead_in() {
+            header.minutes() == subq.minutes() % 10
+                && header.seconds() == subq.seconds()
+                && header.frames() == subq.frames()
+        } else {
+            header == subq
+        };
+
+        if matches {
+            Ok(())
+        } else {
+            Err(CdError::HeaderMsfMismatch { header, subq })
+        }
+    }
+
     /// Retrieve the CD-ROM XA Mode2 subheader. Returns `CdError::BadFormat` if this is not a
     /// CD-ROM XA Mode 2 sector.
     pub fn mode2_xa_subheader(&self) -> CdResult<XaSubHeader> {
@@ -331,6 +606,19 @@ impl Sector {
         Ok(XaSubHeader(*array_ref![self.data, 16, 8]))
     }
 
+    /// Retrieve both copies of the CD-ROM XA Mode2 subheader. Each `XaSubHeader` returned
+    /// contains one of the copies repeated twice, so that its accessors return the values of
+    /// that copy. Returns `CdError::BadFormat` if this is not a CD-ROM XA Mode 2 sector.
+    ///
+    /// See `XaSubHeader::copies_agree` to simply check that both copies are identical.
+    pub fn mode2_xa_subheader_copies(&self) -> CdResult<(XaSubHeader, XaSubHeader)> {
+        let subheader = self.mode2_xa_subheader()?;
+
+        let copy = |c: &[u8]| XaSubHeader([c[0], c[1], c[2], c[3], c[0], c[1], c[2], c[3]]);
+
+        Ok((copy(&subheader.0[..4]), copy(&subheader.0[4..])))
+    }
+
     /// Retrieve a CD-ROM XA Mode 2 payload. Returns `CdError::BadFormat` if this is not a Mode 2
     /// sector.
     ///
@@ -348,6 +636,63 @@ impl Sector {
     }
 }
 
+/// Two sectors are equal if they have the same data, Q subchannel and format. The raw subchannel
+/// data and the synthesized flag are not compared.
+impl PartialEq for Sector {
+    fn eq(&self, other: &Sector) -> bool {
+        self.data[..] == other.data[..] && self.q == other.q && self.format == other.format
+    }
+}
+
+impl Eq for Sector {}
+
+impl Hash for Sector {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.data.hash(state);
+        self.q.hash(state);
+        self.format.hash(state);
+    }
+}
+
+/// Tag used to store `format` in the compact serialization format
+fn format_tag(format: TrackFormat) -> u8 {
+    match format {
+        TrackFormat::Audio => 0,
+        TrackFormat::Mode1 => 1,
+        TrackFormat::Mode2 => 2,
+        TrackFormat::Mode2Xa => 3,
+        TrackFormat::Mode2CdI => 4,
+    }
+}
+
+/// Reverse of `format_tag`
+fn format_from_tag(tag: u8) -> Option<TrackFormat> {
+    let format = match tag {
+        0 => TrackFormat::Audio,
+        1 => TrackFormat::Mode1,
+        2 => TrackFormat::Mode2,
+        3 => TrackFormat::Mode2Xa,
+        4 => TrackFormat::Mode2CdI,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+/// Make sure that the Q subchannel data is compatible with the track format
+fn check_q_format(q: &Q, format: TrackFormat) -> CdResult<()> {
+    let fmt_ok = match format {
+        TrackFormat::Audio => q.is_audio(),
+        _ => q.is_data(),
+    };
+
+    if fmt_ok {
+        Ok(())
+    } else {
+        Err(CdError::BadFormat)
+    }
+}
+
 /// Decoded CD-ROM sector header
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -385,9 +730,16 @@ pub enum CdRomMode {
 /// The subheader starts at byte 16 of CD-ROM XA sectors, just after the CD-ROM header.
 /// The data is copied twice for data integrity but both copies should be identical
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct XaSubHeader([u8; 8]);
 
 impl XaSubHeader {
+    /// Returns true if both copies of the subheader are identical. A mismatch means that the
+    /// subheader is corrupted.
+    pub fn copies_agree(&self) -> bool {
+        self.0[..4] == self.0[4..]
+    }
+
     /// Return the first File Number
     pub fn file_number(&self) -> u8 {
         self.0[0]
@@ -477,6 +829,31 @@ impl XaCodingAudio {
     pub fn emphasis(self) -> bool {
         self.0 & (1 << 6) != 0
     }
+
+    /// Returns the raw 2-bit value of the stereo field (bits 0 and 1): 0 for mono, 1 for stereo,
+    /// the other values are reserved
+    pub fn stereo_raw(self) -> u8 {
+        self.0 & 3
+    }
+
+    /// Returns the raw 2-bit value of the sampling frequency field (bits 2 and 3): 0 for 37.8kHz,
+    /// 1 for 18.9kHz, the other values are reserved
+    pub fn sampling_frequency_raw(self) -> u8 {
+        (self.0 >> 2) & 3
+    }
+
+    /// Returns the raw 2-bit value of the bits per sample field (bits 4 and 5): 0 for 4 bits, 1
+    /// for 8 bits, the other values are reserved
+    pub fn bits_per_sample_raw(self) -> u8 {
+        (self.0 >> 4) & 3
+    }
+
+    /// Returns true if any reserved bit is set: the high bit of the stereo, sampling frequency or
+    /// bits per sample fields, or bit 7. In this case the values returned by `stereo`,
+    /// `sampling_frequency` and `bits_per_sample` are probably meaningless.
+    pub fn is_reserved(self) -> bool {
+        self.0 & 0b1010_1010 != 0
+    }
 }
 
 /// Possible values for the sampling frequency of an audio XA sector
@@ -565,6 +942,226 @@ pub enum XaForm {
     Form2 = 1,
 }
 
+/// Builds fully-specified CD-ROM XA (or CD-i) Mode 2 Form 2 sectors, for instance to author XA
+/// audio or video streams. The subheader is written twice, the Form 2 bit of the submode is always
+/// set and the EDC is computed over the result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mode2Form2Builder {
+    format: TrackFormat,
+    file_number: u8,
+    channel_number: u8,
+    submode: XaSubmode,
+    coding_info: u8,
+}
+
+impl Mode2Form2Builder {
+    /// Create a builder for `TrackFormat::Mode2Xa` sectors with a subheader containing only the
+    /// Form 2 submode bit
+    pub fn new() -> Mode2Form2Builder {
+        Mode2Form2Builder {
+            format: TrackFormat::Mode2Xa,
+            file_number: 0,
+            channel_number: 0,
+            submode: XaSubmode(1 << 5),
+            coding_info: 0,
+        }
+    }
+
+    /// Set the format of the sector, must be `TrackFormat::Mode2Xa` or `TrackFormat::Mode2CdI`
+    pub fn format(mut self, format: TrackFormat) -> Mode2Form2Builder {
+        self.format = format;
+        self
+    }
+
+    /// Set the File Number of the subheader
+    pub fn file_number(mut self, file_number: u8) -> Mode2Form2Builder {
+        self.file_number = file_number;
+        self
+    }
+
+    /// Set the Channel Number of the subheader
+    pub fn channel_number(mut self, channel_number: u8) -> Mode2Form2Builder {
+        self.channel_number = channel_number;
+        self
+    }
+
+    /// Set the Submode of the subheader. The Form 2 bit is set by `build` if it's missing.
+    pub fn submode(mut self, submode: XaSubmode) -> Mode2Form2Builder {
+        self.submode = submode;
+        self
+    }
+
+    /// Set the raw Coding Information of the subheader
+    pub fn coding_info(mut self, coding_info: u8) -> Mode2Form2Builder {
+        self.coding_info = coding_info;
+        self
+    }
+
+    /// Build a sector at the position given by `q` containing `payload`, with a valid CD-ROM
+    /// header and EDC.
+    ///
+    /// Returns `CdError::BadFormat` if the format isn't Mode 2 XA or CD-i or if `q` is not
+    /// compatible with it.
+    pub fn build(&self, q: Q, payload: &[u8; 2324]) -> CdResult<Sector> {
+        if !matches!(self.format, TrackFormat::Mode2Xa | TrackFormat::Mode2CdI) {
+            return Err(CdError::BadFormat);
+        }
+
+        let mut sector = Sector::uninitialized(q, self.format)?;
+
+        let subheader = [
+            self.file_number,
+            self.channel_number,
+            self.submode.0 | (1 << 5),
+            self.coding_info,
+        ];
+
+        sector.data[16..20].copy_from_slice(&subheader);
+        sector.data[20..24].copy_from_slice(&subheader);
+        sector.data[24..2348].copy_from_slice(payload);
+
+        sector.write_headers();
+        sector.write_edc_ecc();
+
+        Ok(sector)
+    }
+}
+
+impl Default for Mode2Form2Builder {
+    fn default() -> Mode2Form2Builder {
+        Mode2Form2Builder::new()
+    }
+}
+
+/// Summary of the CD-ROM XA submodes found in a set of sectors, used to figure out how to demux a
+/// track
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct XaStreamInfo {
+    /// Number of Mode 2 XA sectors tallied
+    pub sectors: u32,
+    /// Number of sectors skipped because they're not Mode 2 XA sectors
+    pub non_xa: u32,
+    /// Number of sectors with the Real-Time bit set
+    pub real_time: u32,
+    /// Number of sectors with the End Of Record bit set
+    pub end_of_record: u32,
+    /// Number of sectors with the End Of File bit set
+    pub end_of_file: u32,
+    /// Number of Form 2 sectors
+    pub form2: u32,
+    /// One entry per file/channel pair found, sorted by file then channel number
+    pub streams: Vec<XaStream>,
+}
+
+/// Sectors of a single file/channel pair in an `XaStreamInfo`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct XaStream {
+    /// File Number in the sub-header
+    pub file: u8,
+    /// Channel Number in the sub-header
+    pub channel: u8,
+    /// Number of sectors with the Data bit set
+    pub data: u32,
+    /// Number of sectors with the Audio bit set
+    pub audio: u32,
+    /// Number of sectors with the Video bit set
+    pub video: u32,
+}
+
+impl XaStreamInfo {
+    /// Create an empty summary
+    pub fn new() -> XaStreamInfo {
+        XaStreamInfo::default()
+    }
+
+    /// Read all the sectors of `track` and tally their submodes
+    pub fn scan_track<I: Image + ?Sized>(image: &mut I, track: Bcd) -> CdResult<XaStreamInfo> {
+        let t = image.toc().track(track)?;
+        let range = MsfRange::new(t.start, t.start + t.length);
+
+        let mut info = XaStreamInfo::new();
+
+        for msf in range {
+            let sector = image.read_sector(DiscPosition::Program(msf))?;
+
+            info.add(&sector);
+        }
+
+        Ok(info)
+    }
+
+    /// Tally the submode of `sector`
+    pub fn add(&mut self, sector: &Sector) {
+        let subheader = match sector.mode2_xa_subheader() {
+            Ok(s) => s,
+            Err(_) => {
+                self.non_xa += 1;
+                return;
+            }
+        };
+
+        let submode = subheader.submode();
+
+        self.sectors += 1;
+
+        self.real_time += submode.real_time() as u32;
+        self.end_of_record += submode.end_of_record() as u32;
+        self.end_of_file += submode.end_of_file() as u32;
+        self.form2 += (submode.form() == XaForm::Form2) as u32;
+
+        let key = (subheader.file_number(), subheader.channel_number());
+
+        let pos = match self
+            .streams
+            .binary_search_by_key(&key, |s| (s.file, s.channel))
+        {
+            Ok(pos) => pos,
+            Err(pos) => {
+                let stream = XaStream {
+                    file: key.0,
+                    channel: key.1,
+                    ..XaStream::default()
+                };
+
+                self.streams.insert(pos, stream);
+                pos
+            }
+        };
+
+        let stream = &mut self.streams[pos];
+
+        stream.data += submode.data() as u32;
+        stream.audio += submode.audio() as u32;
+        stream.video += submode.video() as u32;
+    }
+
+    /// Total number of sectors with the Data bit set
+    pub fn data(&self) -> u32 {
+        self.streams.iter().map(|s| s.data).sum()
+    }
+
+    /// Total number of sectors with the Audio bit set
+    pub fn audio(&self) -> u32 {
+        self.streams.iter().map(|s| s.audio).sum()
+    }
+
+    /// Total number of sectors with the Video bit set
+    pub fn video(&self) -> u32 {
+        self.streams.iter().map(|s| s.video).sum()
+    }
+
+    /// Returns true if sectors from several file/channel pairs or of several kinds (data, audio,
+    /// video) are interleaved, in which case the track must be demuxed
+    pub fn is_interleaved(&self) -> bool {
+        let kinds = [self.data(), self.audio(), self.video()]
+            .iter()
+            .filter(|&&n| n > 0)
+            .count();
+
+        self.streams.len() > 1 || kinds > 1
+    }
+}
+
 #[test]
 fn empty_mode_1() {
     use bcd::Bcd;
@@ -783,3 +1380,706 @@ fn empty_mode_2_xa_form_1() {
 
     assert_eq!(data, &expected);
 }
+
+#[test]
+fn validate_position() {
+    use bcd::Bcd;
+    use subchannel::{AdrControl, QData};
+
+    let disc_msf = Msf::from_bcd(0x12, 0x34, 0x56).unwrap();
+
+    let qdata = QData::Mode1 {
+        track: Bcd::TABLE[1],
+        index: Bcd::TABLE[1],
+        track_msf: Msf::ZERO,
+        disc_msf,
+    };
+
+    let q = Q::from_qdata_mode1(qdata, AdrControl::DATA);
+    let mut sector = Sector::empty(q, TrackFormat::Mode1).unwrap();
+
+    assert!(sector.validate_position().is_ok());
+
+    // Corrupt the frame number
+    sector.data_2352_mut()[14] = 0x57;
+
+    match sector.validate_position() {
+        Err(CdError::HeaderMsfMismatch { header, subq }) => {
+            assert_eq!(header, Msf::from_bcd(0x12, 0x34, 0x57).unwrap());
+            assert_eq!(subq, disc_msf);
+        }
+        r => panic!("Unexpected result {:?}", r),
+    }
+
+    // Lead-in sectors only store the last digit of the minutes
+    let qdata = QData::Mode1TocLastTrack {
+        last_track: Bcd::TABLE[1],
+        lead_in_msf: Msf::from_bcd(0x99, 0x59, 0x70).unwrap(),
+    };
+
+    let q = Q::from_qdata_mode1(qdata, AdrControl::DATA);
+    let mut sector = Sector::empty(q, TrackFormat::Mode1).unwrap();
+
+    assert_eq!(sector.data_2352()[12], 0xa9);
+    assert!(sector.validate_position().is_ok());
+
+    sector.data_2352_mut()[13] = 0x58;
+    assert!(sector.validate_position().is_err());
+
+    // Audio sectors don't have a header
+    let qdata = QData::Mode1 {
+        track: Bcd::TABLE[1],
+        index: Bcd::TABLE[1],
+        track_msf: Msf::ZERO,
+        disc_msf,
+    };
+
+    let q = Q::from_qdata_mode1(qdata, AdrControl::AUDIO);
+    let sector = Sector::empty(q, TrackFormat::Audio).unwrap();
+
+    assert!(matches!(sector.validate_position(), Err(CdError::BadFormat)));
+}
+
+#[test]
+fn silence() {
+    use bcd::Bcd;
+    use subchannel::{AdrControl, QData};
+
+    let qdata = QData::Mode1 {
+        track: Bcd::ONE,
+        index: Bcd::ONE,
+        track_msf: Msf::ZERO,
+        disc_msf: Msf::from_bcd(0x00, 0x02, 0x00).unwrap(),
+    };
+
+    let sector = Sector::silence(Q::from_qdata_mode1(qdata.clone(), AdrControl::AUDIO)).unwrap();
+    assert!(sector.data_2352().iter().all(|&b| b == 0));
+
+    let data_q = Q::from_qdata_mode1(qdata, AdrControl::DATA);
+    assert!(matches!(Sector::silence(data_q), Err(CdError::BadFormat)));
+}
+
+#[test]
+fn set_cdrom_mode() {
+    use bcd::Bcd;
+    use subchannel::{AdrControl, QData};
+
+    let qdata = QData::Mode1 {
+        track: Bcd::ONE,
+        index: Bcd::ONE,
+        track_msf: Msf::ZERO,
+        disc_msf: Msf::from_bcd(0x00, 0x02, 0x00).unwrap(),
+    };
+    let q = Q::from_qdata_mode1(qdata, AdrControl::DATA);
+
+    // Mode 1 sector mis-tagged as Mode 2
+    let mut sector = Sector::empty(q.clone(), TrackFormat::Mode1).unwrap();
+    for (i, b) in sector.data_2352_mut()[16..2064].iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    sector.data_2352_mut()[15] = 2;
+
+    assert!(!sector.edc_valid());
+
+    sector.set_cdrom_mode(CdRomMode::Mode1).unwrap();
+
+    assert!(sector.cdrom_header().unwrap().mode == CdRomMode::Mode1);
+    assert!(sector.edc_valid());
+    assert_eq!(sector.data_2352()[16], 0);
+    assert_eq!(sector.data_2352()[2063], (2063 - 16) as u8);
+
+    // Incompatible with the track format
+    assert!(matches!(
+        sector.set_cdrom_mode(CdRomMode::Mode2),
+        Err(CdError::BadFormat)
+    ));
+
+    sector.set_cdrom_mode(CdRomMode::Empty).unwrap();
+    assert!(sector.cdrom_header().unwrap().mode == CdRomMode::Empty);
+    assert!(sector.data_2352()[16..].iter().all(|&b| b == 0));
+
+    // Not a CD-ROM sector
+    let q = Q::from_qdata_mode1(q.data().clone(), AdrControl::AUDIO);
+    let mut audio = Sector::silence(q).unwrap();
+    assert!(matches!(
+        audio.set_cdrom_mode(CdRomMode::Mode1),
+        Err(CdError::BadFormat)
+    ));
+}
+
+#[test]
+fn data_2448() {
+    use bcd::Bcd;
+    use subchannel::{AdrControl, QData};
+
+    let qdata = QData::Mode1 {
+        track: Bcd::ONE,
+        index: Bcd::ONE,
+        track_msf: Msf::from_bcd(0x00, 0x01, 0x02).unwrap(),
+        disc_msf: Msf::from_bcd(0x00, 0x03, 0x02).unwrap(),
+    };
+
+    let q = Q::from_qdata_mode1(qdata, AdrControl::DATA);
+    let sector = Sector::empty(q, TrackFormat::Mode1).unwrap();
+
+    let raw = sector.data_2448();
+
+    assert_eq!(raw[..2352], sector.data_2352()[..]);
+
+    let subchannel = *array_ref![raw, 2352, 96];
+    assert_eq!(&Q::from_raw_interleaved(subchannel).unwrap(), sector.q());
+}
+
+#[test]
+fn content_eq() {
+    use bcd::Bcd;
+    use subchannel::{AdrControl, QData};
+
+    let qdata = QData::Mode1 {
+        track: Bcd::ONE,
+        index: Bcd::ONE,
+        track_msf: Msf::ZERO,
+        disc_msf: Msf::from_bcd(0x00, 0x02, 0x00).unwrap(),
+    };
+    let q = Q::from_qdata_mode1(qdata, AdrControl::DATA);
+
+    let mut a = Sector::empty(q, TrackFormat::Mode1).unwrap();
+    for (i, b) in a.data_2352_mut()[16..2064].iter_mut().enumerate() {
+        *b = i as u8;
+    }
+
+    // `b` has the same payload but with an up-to-date EDC/ECC, `a`'s is stale
+    let mut b = a.clone();
+    b.write_edc_ecc();
+
+    assert!(a == a.clone());
+    assert!(a != b);
+    assert!(a.content_eq(&b));
+
+    // Raw subchannel data and the synthesized flag are ignored by `==`
+    let mut c = a.clone();
+    c.set_raw_subchannel(RawSubchannel::new([0xff; 96]));
+    c.set_synthesized();
+    assert!(a == c);
+
+    b.data_2352_mut()[100] ^= 1;
+    assert!(!a.content_eq(&b));
+}
+
+#[test]
+fn empty_mode_2() {
+    use bcd::Bcd;
+    use subchannel::{AdrControl, QData};
+
+    let qdata = QData::Mode1 {
+        track: Bcd::ONE,
+        index: Bcd::ONE,
+        track_msf: Msf::ZERO,
+        disc_msf: Msf::from_bcd(0x00, 0x02, 0x00).unwrap(),
+    };
+    let q = Q::from_qdata_mode1(qdata, AdrControl::DATA);
+
+    let mut sector = Sector::empty(q, TrackFormat::Mode2).unwrap();
+
+    assert_eq!(
+        sector.data_2352()[..16],
+        [0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0x02, 0, 2]
+    );
+    assert!(sector.data_2352()[16..].iter().all(|&b| b == 0));
+    assert!(sector.validate_position().is_ok());
+    assert!(sector.cdrom_header().unwrap().mode == CdRomMode::Mode2);
+    assert!(sector.edc_valid());
+
+    // The whole 2336 bytes are user data, nothing is overwritten by the EDC/ECC
+    for (i, b) in sector.data_2352_mut()[16..].iter_mut().enumerate() {
+        *b = i as u8;
+    }
+
+    let copy = sector.clone();
+
+    sector.write_headers();
+    sector.write_edc_ecc();
+
+    assert!(sector == copy);
+    assert!(sector.edc_valid());
+    assert!(matches!(sector.mode2_xa_payload(), Err(CdError::BadFormat)));
+}
+
+#[test]
+fn normalize() {
+    use bcd::Bcd;
+    use subchannel::{AdrControl, QData};
+
+    let qdata = QData::Mode1 {
+        track: Bcd::ONE,
+        index: Bcd::ONE,
+        track_msf: Msf::ZERO,
+        disc_msf: Msf::from_bcd(0x00, 0x02, 0x10).unwrap(),
+    };
+    let q = Q::from_qdata_mode1(qdata, AdrControl::DATA);
+
+    for &format in &[TrackFormat::Mode1, TrackFormat::Mode2Xa] {
+        let mut sector = Sector::uninitialized(q.clone(), format).unwrap();
+
+        for (i, b) in sector.data_2352_mut()[24..2048].iter_mut().enumerate() {
+            *b = (i * 3) as u8;
+        }
+
+        assert!(sector.cdrom_header().is_err());
+
+        sector.normalize().unwrap();
+
+        assert!(sector.validate_position().is_ok());
+        assert!(sector.edc_valid());
+        assert_eq!(sector.data_2352()[100], ((100 - 24) * 3) as u8);
+    }
+}
+
+#[test]
+fn xa_stream_info() {
+    use subchannel::{AdrControl, QData};
+
+    let qdata = QData::Mode1 {
+        track: Bcd::ONE,
+        index: Bcd::ONE,
+        track_msf: Msf::ZERO,
+        disc_msf: Msf::from_bcd(0x00, 0x02, 0x00).unwrap(),
+    };
+    let q = Q::from_qdata_mode1(qdata, AdrControl::DATA);
+
+    let xa_sector = |file: u8, channel: u8, submode: u8| {
+        let mut sector = Sector::uninitialized(q.clone(), TrackFormat::Mode2Xa).unwrap();
+
+        for &off in &[16, 20] {
+            sector.data_2352_mut()[off..off + 4].copy_from_slice(&[file, channel, submode, 0]);
+        }
+
+        sector.normalize().unwrap();
+        sector
+    };
+
+    let mut info = XaStreamInfo::new();
+
+    // A video stream on channel 0 interleaved with audio on channel 1, followed by a data file
+    info.add(&xa_sector(1, 0, 0x42));
+    info.add(&xa_sector(1, 0, 0x42));
+    info.add(&xa_sector(1, 1, 0x64));
+    info.add(&xa_sector(1, 0, 0x42));
+    info.add(&xa_sector(1, 1, 0xe5));
+    info.add(&xa_sector(2, 0, 0x08));
+    info.add(&xa_sector(2, 0, 0x89));
+    info.add(&Sector::empty(q.clone(), TrackFormat::Mode1).unwrap());
+
+    assert_eq!(info.sectors, 7);
+    assert_eq!(info.non_xa, 1);
+    assert_eq!(info.real_time, 5);
+    assert_eq!(info.end_of_record, 2);
+    assert_eq!(info.end_of_file, 2);
+    assert_eq!(info.form2, 2);
+    assert_eq!(info.data(), 2);
+    assert_eq!(info.audio(), 2);
+    assert_eq!(info.video(), 3);
+    assert!(info.is_interleaved());
+
+    assert_eq!(
+        info.streams,
+        vec![
+            XaStream {
+                file: 1,
+                channel: 0,
+                data: 0,
+                audio: 0,
+                video: 3,
+            },
+            XaStream {
+                file: 1,
+                channel: 1,
+                data: 0,
+                audio: 2,
+                video: 0,
+            },
+            XaStream {
+                file: 2,
+                channel: 0,
+                data: 2,
+                audio: 0,
+                video: 0,
+            },
+        ]
+    );
+
+    let mut single = XaStreamInfo::new();
+    single.add(&xa_sector(1, 0, 0x08));
+    single.add(&xa_sector(1, 0, 0x89));
+    assert!(!single.is_interleaved());
+}
+
+#[test]
+fn xa_coding_audio() {
+    // Stereo, 18.9kHz, 4 bits, emphasis
+    let coding = XaCodingAudio(0b0100_0101);
+
+    assert!(!coding.is_reserved());
+    assert!(coding.stereo());
+    assert!(coding.emphasis());
+    assert_eq!(coding.stereo_raw(), 1);
+    assert_eq!(coding.sampling_frequency_raw(), 1);
+    assert_eq!(coding.bits_per_sample_raw(), 0);
+
+    for &(raw, stereo, freq, bits) in &[
+        (0b0000_0010, 2, 0, 0),
+        (0b0000_1011, 3, 2, 0),
+        (0b0010_0000, 0, 0, 2),
+        (0b1000_0000, 0, 0, 0),
+    ] {
+        let coding = XaCodingAudio(raw);
+
+        assert!(coding.is_reserved());
+        assert_eq!(coding.stereo_raw(), stereo);
+        assert_eq!(coding.sampling_frequency_raw(), freq);
+        assert_eq!(coding.bits_per_sample_raw(), bits);
+    }
+
+    // The convenience accessors still ignore the reserved bits
+    assert!(XaCodingAudio(0b0000_0011).stereo());
+    assert!(!XaCodingAudio(0b0000_0010).stereo());
+}
+
+#[test]
+fn compact_serialization() {
+    use subchannel::{AdrControl, QData};
+
+    let q = |ctrl| {
+        let qdata = QData::Mode1 {
+            track: Bcd::ONE,
+            index: Bcd::ONE,
+            track_msf: Msf::from_bcd(0x00, 0x01, 0x02).unwrap(),
+            disc_msf: Msf::from_bcd(0x00, 0x03, 0x02).unwrap(),
+        };
+
+        Q::from_qdata_mode1(qdata, ctrl)
+    };
+
+    let mut mode1 = Sector::empty(q(AdrControl::DATA), TrackFormat::Mode1).unwrap();
+    mode1.data_2352_mut()[100] = 0x42;
+    mode1.write_edc_ecc();
+
+    let mut audio = Sector::silence(q(AdrControl::AUDIO)).unwrap();
+    for (i, b) in audio.data_2352_mut().iter_mut().enumerate() {
+        *b = (i * 3) as u8;
+    }
+
+    let mut with_sub = audio.clone();
+    with_sub.set_raw_subchannel(RawSubchannel::new([0x3f; 96]));
+
+    for s in &[mode1, audio, with_sub] {
+        let bytes = s.serialize_compact();
+
+        let expected_len = if s.raw_subchannel().is_some() {
+            Sector::COMPACT_SIZE + 96
+        } else {
+            Sector::COMPACT_SIZE
+        };
+
+        assert_eq!(bytes.len(), expected_len);
+
+        let d = Sector::deserialize_compact(&bytes).unwrap();
+
+        assert!(d == *s);
+
+        // Truncated
+        assert!(matches!(
+            Sector::deserialize_compact(&bytes[..bytes.len() - 1]),
+            Err(CdError::BadFormat)
+        ));
+
+        // Bad format tag
+        let mut bad = bytes.clone();
+        bad[2364] = 0xff;
+        assert!(matches!(
+            Sector::deserialize_compact(&bad),
+            Err(CdError::BadFormat)
+        ));
+    }
+
+    // Audio Q data with a data format
+    let mut bytes = Sector::silence(q(AdrControl::AUDIO))
+        .unwrap()
+        .serialize_compact();
+    bytes[2364] = format_tag(TrackFormat::Mode1);
+    assert!(Sector::deserialize_compact(&bytes).is_err());
+}
+
+#[test]
+fn audio_sector() {
+    use subchannel::{AdrControl, QData};
+
+    let disc_msf = Msf::from_bcd(0x00, 0x03, 0x02).unwrap();
+
+    let q = |ctrl| {
+        let qdata = QData::Mode1 {
+            track: Bcd::ONE,
+            index: Bcd::ONE,
+            track_msf: Msf::from_bcd(0x00, 0x01, 0x02).unwrap(),
+            disc_msf,
+        };
+
+        Q::from_qdata_mode1(qdata, ctrl)
+    };
+
+    let audio_q = q(AdrControl::AUDIO);
+
+    let empty = Sector::empty(audio_q.clone(), TrackFormat::Audio).unwrap();
+    let silence = Sector::silence(audio_q.clone()).unwrap();
+
+    // No header or EDC/ECC is generated for audio
+    assert!(empty == silence);
+    assert!(empty.data_2352().iter().all(|&b| b == 0));
+    assert!(empty.is_blank());
+    assert!(empty.edc_valid());
+
+    // Q and format must agree
+    for &format in &[
+        TrackFormat::Mode1,
+        TrackFormat::Mode2,
+        TrackFormat::Mode2Xa,
+        TrackFormat::Mode2CdI,
+    ] {
+        assert!(matches!(
+            Sector::empty(audio_q.clone(), format),
+            Err(CdError::BadFormat)
+        ));
+        assert!(Sector::empty(q(AdrControl::DATA), format)
+            .unwrap()
+            .is_blank());
+    }
+    assert!(matches!(
+        Sector::empty(q(AdrControl::DATA), TrackFormat::Audio),
+        Err(CdError::BadFormat)
+    ));
+    assert!(matches!(
+        Sector::silence(q(AdrControl::DATA)),
+        Err(CdError::BadFormat)
+    ));
+
+    let mut sector = silence;
+    for (i, b) in sector.data_2352_mut().iter_mut().enumerate() {
+        *b = (i * 7) as u8;
+    }
+    let samples = *sector.data_2352();
+
+    assert!(!sector.is_blank());
+
+    // The CD-ROM accessors all fail
+    assert!(matches!(
+        sector.cd_rom_header_raw(),
+        Err(CdError::BadFormat)
+    ));
+    assert!(matches!(sector.cdrom_header(), Err(CdError::BadFormat)));
+    assert!(matches!(
+        sector.validate_position(),
+        Err(CdError::BadFormat)
+    ));
+    assert!(matches!(
+        sector.mode2_xa_subheader(),
+        Err(CdError::BadFormat)
+    ));
+    assert!(matches!(sector.mode2_xa_payload(), Err(CdError::BadFormat)));
+    assert!(matches!(
+        sector.set_cdrom_mode(CdRomMode::Empty),
+        Err(CdError::BadFormat)
+    ));
+
+    // Regenerating the sector doesn't touch the samples
+    sector.write_headers();
+    sector.write_edc_ecc();
+    sector.normalize().unwrap();
+
+    assert_eq!(sector.data_2352()[..], samples[..]);
+    assert!(sector.edc_valid());
+    assert_eq!(sector.q().amsf(), disc_msf);
+}
+
+#[test]
+fn edc_ecc_regions() {
+    use subchannel::{AdrControl, QData};
+
+    let qdata = QData::Mode1 {
+        track: Bcd::ONE,
+        index: Bcd::ONE,
+        track_msf: Msf::ZERO,
+        disc_msf: Msf::from_bcd(0x00, 0x02, 0x00).unwrap(),
+    };
+    let q = Q::from_qdata_mode1(qdata, AdrControl::DATA);
+
+    // Format, form 2 flag, bytes covered by the EDC
+    for &(format, form2, start, end) in &[
+        (TrackFormat::Mode1, false, 0, 2064),
+        (TrackFormat::Mode2Xa, false, 16, 2072),
+        (TrackFormat::Mode2Xa, true, 16, 2348),
+        (TrackFormat::Mode2CdI, false, 16, 2072),
+    ] {
+        let mut sector = Sector::empty(q.clone(), format).unwrap();
+
+        for (i, b) in sector.data_2352_mut()[24..2048].iter_mut().enumerate() {
+            *b = (i * 7) as u8;
+        }
+
+        if form2 {
+            sector.data_2352_mut()[18] |= 1 << 5;
+            sector.data_2352_mut()[22] |= 1 << 5;
+        }
+
+        sector.write_edc_ecc();
+
+        let edc = sector.edc_bytes().unwrap();
+
+        assert_eq!(edc, crc32(&sector.data_2352()[start..end]).to_le_bytes());
+        assert_eq!(edc, sector.data_2352()[end..end + 4]);
+
+        if form2 {
+            assert!(matches!(sector.ecc_bytes(), Err(CdError::BadFormat)));
+        } else {
+            let ecc = *sector.ecc_bytes().unwrap();
+
+            assert_eq!(ecc[..], sector.data_2352()[2076..]);
+            assert!(ecc.iter().any(|&b| b != 0));
+        }
+
+        assert!(sector.edc_valid());
+        sector.data_2352_mut()[100] ^= 1;
+        assert!(!sector.edc_valid());
+    }
+
+    let mode2 = Sector::empty(q, TrackFormat::Mode2).unwrap();
+
+    assert!(matches!(mode2.edc_bytes(), Err(CdError::BadFormat)));
+    assert!(matches!(mode2.ecc_bytes(), Err(CdError::BadFormat)));
+}
+
+#[test]
+fn mode2_form2_builder() {
+    use bcd::Bcd;
+    use subchannel::{AdrControl, QData};
+
+    let msf = Msf::from_sector_index(200).unwrap();
+    let q = Q::from_qdata_mode1(
+        QData::Mode1 {
+            track: Bcd::ONE,
+            index: Bcd::ONE,
+            track_msf: Msf::from_sector_index(50).unwrap(),
+            disc_msf: msf,
+        },
+        AdrControl::DATA,
+    );
+
+    let mut payload = [0; 2324];
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b = (i * 3) as u8;
+    }
+
+    // Real-Time, Audio, Form 2 bit missing
+    let sector = Mode2Form2Builder::new()
+        .file_number(1)
+        .channel_number(3)
+        .submode(XaSubmode(0x44))
+        .coding_info(0x01)
+        .build(q.clone(), &payload)
+        .unwrap();
+
+    assert_eq!(sector.format(), TrackFormat::Mode2Xa);
+    assert!(sector.validate_position().is_ok());
+    assert!(sector.edc_valid());
+    assert!(sector.edc_bytes().unwrap().iter().any(|&b| b != 0));
+
+    let subheader = sector.mode2_xa_subheader().unwrap();
+    assert_eq!(subheader.file_number(), 1);
+    assert_eq!(subheader.channel_number(), 3);
+    assert_eq!(subheader.submode(), XaSubmode(0x64));
+    assert_eq!(subheader.submode().form(), XaForm::Form2);
+    assert_eq!(
+        subheader.coding_info(),
+        XaCodingInfo::Audio(XaCodingAudio(0x01))
+    );
+    assert_eq!(sector.data_2352()[16..20], sector.data_2352()[20..24]);
+    assert_eq!(sector.mode2_xa_payload().unwrap(), &payload[..]);
+
+    let cdi = Mode2Form2Builder::new()
+        .format(TrackFormat::Mode2CdI)
+        .build(q.clone(), &payload)
+        .unwrap();
+
+    assert_eq!(cdi.format(), TrackFormat::Mode2CdI);
+    assert_eq!(cdi.data_2352()[18], 0x20);
+    assert!(cdi.edc_valid());
+
+    assert!(matches!(
+        Mode2Form2Builder::new()
+            .format(TrackFormat::Mode1)
+            .build(q, &payload),
+        Err(CdError::BadFormat)
+    ));
+}
+
+#[test]
+fn xa_subheader_copies() {
+    use bcd::Bcd;
+    use subchannel::{AdrControl, QData};
+
+    let msf = Msf::from_sector_index(200).unwrap();
+    let q = Q::from_qdata_mode1(
+        QData::Mode1 {
+            track: Bcd::ONE,
+            index: Bcd::ONE,
+            track_msf: Msf::from_sector_index(50).unwrap(),
+            disc_msf: msf,
+        },
+        AdrControl::DATA,
+    );
+
+    let mut sector = Mode2Form2Builder::new()
+        .file_number(1)
+        .channel_number(2)
+        .submode(XaSubmode(0x64))
+        .build(q, &[0; 2324])
+        .unwrap();
+
+    let subheader = sector.mode2_xa_subheader().unwrap();
+    assert!(subheader.copies_agree());
+
+    let (first, second) = sector.mode2_xa_subheader_copies().unwrap();
+    assert_eq!(first, second);
+    assert_eq!(first, subheader);
+
+    // Corrupt the channel number of the second copy
+    sector.data_2352_mut()[21] = 7;
+
+    assert!(!sector.mode2_xa_subheader().unwrap().copies_agree());
+
+    let (first, second) = sector.mode2_xa_subheader_copies().unwrap();
+    assert_ne!(first, second);
+    assert!(first.copies_agree() && second.copies_agree());
+    assert_eq!(first.channel_number(), 2);
+    assert_eq!(second.channel_number(), 7);
+    assert_eq!(second.file_number(), 1);
+    assert_eq!(second.submode(), XaSubmode(0x64));
+
+    let audio = Sector::empty(
+        Q::from_qdata_mode1(
+            QData::Mode1 {
+                track: Bcd::ONE,
+                index: Bcd::ONE,
+                track_msf: Msf::ZERO,
+                disc_msf: msf,
+            },
+            AdrControl::AUDIO,
+        ),
+        TrackFormat::Audio,
+    )
+    .unwrap();
+
+    assert!(matches!(
+        audio.mode2_xa_subheader_copies(),
+        Err(CdError::BadFormat)
+    ));
+}
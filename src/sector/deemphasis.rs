@@ -0,0 +1,161 @@
+//! De-emphasis filter for CD-DA audio.
+//!
+//! Audio tracks with the pre-emphasis flag set in their control bits have been recorded with a
+//! 50/15µs high-frequency boost which must be compensated for on playback. This module implements
+//! the corresponding first-order shelving filter at 44.1kHz, obtained from the analog transfer
+//! function `(1 + s * 15µs) / (1 + s * 50µs)` with the bilinear transform.
+
+use sector::Sector;
+
+/// Time constant of the pole of the emphasis filter
+const T1: f32 = 50e-6;
+/// Time constant of the zero of the emphasis filter
+const T2: f32 = 15e-6;
+/// CD-DA sampling frequency
+const SAMPLE_RATE: f32 = 44_100.;
+
+/// Stateful de-emphasis filter for 16-bit stereo samples. The same instance should be used for
+/// consecutive samples of a track to avoid discontinuities at the sector boundaries.
+#[derive(Clone, Debug)]
+pub struct DeEmphasis {
+    b0: f32,
+    b1: f32,
+    a1: f32,
+    /// Previous input sample for each channel
+    x1: [f32; 2],
+    /// Previous output sample for each channel
+    y1: [f32; 2],
+}
+
+impl DeEmphasis {
+    /// Create a new filter with a zeroed state
+    pub fn new() -> DeEmphasis {
+        let k = 2. * SAMPLE_RATE;
+        let norm = 1. + k * T1;
+
+        DeEmphasis {
+            b0: (1. + k * T2) / norm,
+            b1: (1. - k * T2) / norm,
+            a1: (1. - k * T1) / norm,
+            x1: [0.; 2],
+            y1: [0.; 2],
+        }
+    }
+
+    /// Reset the state of the filter, for instance when seeking to a new position
+    pub fn reset(&mut self) {
+        self.x1 = [0.; 2];
+        self.y1 = [0.; 2];
+    }
+
+    /// Filter `samples` in place. Each entry is a left, right pair.
+    pub fn process(&mut self, samples: &mut [[i16; 2]]) {
+        for sample in samples.iter_mut() {
+            for (c, s) in sample.iter_mut().enumerate() {
+                let x = f32::from(*s);
+                let y = self.b0 * x + self.b1 * self.x1[c] - self.a1 * self.y1[c];
+
+                self.x1[c] = x;
+                self.y1[c] = y;
+
+                *s = y.round().clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16;
+            }
+        }
+    }
+
+    /// Filter the audio samples of `sector` in place if it's an audio sector with the
+    /// pre-emphasis flag set in its Q subchannel control bits. Returns true if the sector was
+    /// filtered.
+    pub fn process_sector(&mut self, sector: &mut Sector) -> bool {
+        if !sector.format().is_audio() || !sector.q().adr_control().pre_emphasis() {
+            return false;
+        }
+
+        let mut samples = [[0i16; 2]; 588];
+
+        for (s, b) in samples.iter_mut().zip(sector.data_2352().chunks_exact(4)) {
+            s[0] = i16::from_le_bytes([b[0], b[1]]);
+            s[1] = i16::from_le_bytes([b[2], b[3]]);
+        }
+
+        self.process(&mut samples);
+
+        for (s, b) in samples
+            .iter()
+            .zip(sector.data_2352_mut().chunks_exact_mut(4))
+        {
+            b[..2].copy_from_slice(&s[0].to_le_bytes());
+            b[2..].copy_from_slice(&s[1].to_le_bytes());
+        }
+
+        true
+    }
+}
+
+impl Default for DeEmphasis {
+    fn default() -> DeEmphasis {
+        DeEmphasis::new()
+    }
+}
+
+#[test]
+fn step_response() {
+    let mut filter = DeEmphasis::new();
+
+    let mut samples = [[10_000i16, -10_000]; 64];
+
+    filter.process(&mut samples);
+
+    // y[0] = b0 * x, y[1] = (b0 + b1) * x - a1 * y[0]
+    assert_eq!(samples[0], [4294, -4294]);
+    assert_eq!(samples[1], [6403, -6403]);
+
+    // Unity gain at DC
+    assert_eq!(samples[63], [10_000, -10_000]);
+
+    // Gain of 15/50 at the Nyquist frequency
+    filter.reset();
+
+    let mut samples = [[0i16; 2]; 64];
+    for (i, s) in samples.iter_mut().enumerate() {
+        let v = if i % 2 == 0 { 10_000 } else { -10_000 };
+        *s = [v, v];
+    }
+
+    filter.process(&mut samples);
+
+    assert!((i32::from(samples[63][0]).abs() - 3000).abs() <= 1);
+}
+
+#[test]
+fn sector() {
+    use bcd::Bcd;
+    use msf::Msf;
+    use subchannel::{AdrControl, QData, Q};
+
+    let qdata = QData::Mode1 {
+        track: Bcd::ONE,
+        index: Bcd::ONE,
+        track_msf: Msf::ZERO,
+        disc_msf: Msf::from_bcd(0x00, 0x02, 0x00).unwrap(),
+    };
+
+    let mut filter = DeEmphasis::new();
+
+    let mut sector =
+        Sector::silence(Q::from_qdata_mode1(qdata.clone(), AdrControl::AUDIO)).unwrap();
+    sector.data_2352_mut()[1] = 0x10;
+
+    assert!(!filter.process_sector(&mut sector));
+    assert_eq!(sector.data_2352()[1], 0x10);
+
+    let mut ctrl = AdrControl::AUDIO;
+    ctrl.set_pre_emphasis(true);
+
+    let mut sector = Sector::silence(Q::from_qdata_mode1(qdata, ctrl)).unwrap();
+    sector.data_2352_mut()[1] = 0x10;
+
+    assert!(filter.process_sector(&mut sector));
+    // 0x1000 * b0
+    assert_eq!(sector.data_2352()[..4], [0xdf, 0x06, 0, 0]);
+}
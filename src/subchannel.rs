@@ -30,15 +30,18 @@
 //! and [Wikipedia's article on the subject]
 //! (https://en.wikipedia.org/wiki/Compact_Disc_subcode)
 
+use std::fmt;
+
 use bcd::Bcd;
 use msf::Msf;
 
-use {crc, CdError, CdResult, SessionFormat};
+use checksums::crc16_be;
+use {CdError, CdResult, SessionFormat};
 
 /// Full contents of a Q subchannel frame, parsed. From this structure we should be able to
 /// regenerate the raw Subchannel Q data losslessly
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Q {
     /// Decoded payload
     data: QData,
@@ -88,6 +91,13 @@ impl Q {
         self.data.to_raw(self.adr_control)
     }
 
+    /// Generate the raw interleaved representation of this Q subchannel data: 96 bytes containing
+    /// one bit of each subchannel. Only the Q bits (bit 6) are set, the other subchannels are left
+    /// to 0. This is the reverse of `Q::from_raw_interleaved`.
+    pub fn to_raw_interleaved(&self) -> [u8; 96] {
+        RawSubchannel::from_q(self.to_raw()).0
+    }
+
     /// Returns true if this is a data sector
     pub fn is_data(&self) -> bool {
         self.adr_control.is_data()
@@ -103,6 +113,11 @@ impl Q {
         &self.data
     }
 
+    /// Returns the ADR/Control byte
+    pub fn adr_control(&self) -> AdrControl {
+        self.adr_control
+    }
+
     /// Returns the value of A-MIN, A-SEC and A-FRAC
     pub fn amsf(&self) -> Msf {
         self.data.amsf()
@@ -128,7 +143,7 @@ impl Q {
 ///
 /// See section 22.3.2 of ECMA-130 for more details.
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum QData {
     /// Mode 1 data in the user data area
     Mode1 {
@@ -228,9 +243,7 @@ impl QData {
 
     /// Create a QData from raw subchannel Q data
     pub fn from_raw(raw: [u8; 12]) -> CdResult<QData> {
-        let crc = crc::crc16(&raw[..10]);
-
-        if crc.to_be_bytes() != raw[10..12] {
+        if crc16_be(&raw[..10]) != raw[10..12] {
             return Err(CdError::InvalidSubQCRC);
         }
 
@@ -489,7 +502,7 @@ impl QData {
             }
         }
 
-        let crc = crc::crc16(&subq[..10]).to_be_bytes();
+        let crc = crc16_be(&subq[..10]);
 
         subq[10] = crc[0];
         subq[11] = crc[1];
@@ -498,9 +511,264 @@ impl QData {
     }
 }
 
+/// Returns true if `mcn` is a valid media catalog number: 13 ASCII digits
+pub fn is_valid_mcn(mcn: &str) -> bool {
+    mcn.len() == 13 && mcn.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Returns true if `isrc` is a valid International Standard Recording Code: 5 uppercase ASCII
+/// letters or digits (country and owner codes) followed by 7 ASCII digits (year and serial
+/// number)
+pub fn is_valid_isrc(isrc: &str) -> bool {
+    let b = isrc.as_bytes();
+
+    b.len() == 12
+        && b[..5]
+            .iter()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+        && b[5..].iter().all(|c| c.is_ascii_digit())
+}
+
+/// Generate a raw Mode 2 Q frame containing the media catalog number `mcn`. `aframe` is the
+/// frame number of the absolute MSF of the sector (0 to 74), the other fields of the MSF are not
+/// stored in Mode 2 frames.
+///
+/// The red book requires Mode 2 frames to appear in at least one out of every 100 consecutive
+/// sectors of the program area, replacing the Mode 1 frames.
+///
+/// Returns `CdError::BadFormat` if `mcn` is not valid (see `is_valid_mcn`) and
+/// `CdError::InvalidMsf` if `aframe` is out of range.
+pub fn mcn_q_raw(mcn: &str, mut ctrl: AdrControl, aframe: u8) -> CdResult<[u8; 12]> {
+    if !is_valid_mcn(mcn) {
+        return Err(CdError::BadFormat);
+    }
+
+    let mut subq = [0u8; 12];
+
+    ctrl.set_mode(2);
+    subq[0] = ctrl.0;
+
+    for (i, d) in mcn.bytes().map(|b| b - b'0').enumerate() {
+        subq[1 + i / 2] |= d << if i & 1 == 0 { 4 } else { 0 };
+    }
+
+    finish_q_frame(subq, aframe)
+}
+
+/// Generate a raw Mode 3 Q frame containing the ISRC `isrc` of the current track. `aframe` is the
+/// frame number of the absolute MSF of the sector (0 to 74).
+///
+/// The red book requires Mode 3 frames to appear in at least one out of every 100 consecutive
+/// sectors of the track, replacing the Mode 1 frames.
+///
+/// Returns `CdError::BadFormat` if `isrc` is not valid (see `is_valid_isrc`) and
+/// `CdError::InvalidMsf` if `aframe` is out of range.
+pub fn isrc_q_raw(isrc: &str, mut ctrl: AdrControl, aframe: u8) -> CdResult<[u8; 12]> {
+    if !is_valid_isrc(isrc) {
+        return Err(CdError::BadFormat);
+    }
+
+    let b = isrc.as_bytes();
+
+    // The country and owner codes are stored as 6-bit characters ('0' is 0, 'A' is 17), followed
+    // by two zero bits, then the year and serial number in BCD
+    let mut bits: u64 = 0;
+
+    for &c in &b[..5] {
+        bits = (bits << 6) | u64::from(c - b'0');
+    }
+
+    bits <<= 2;
+
+    for &c in &b[5..] {
+        bits = (bits << 4) | u64::from(c - b'0');
+    }
+
+    // 60 bits of data followed by 4 zero bits
+    bits <<= 4;
+
+    let mut subq = [0u8; 12];
+
+    ctrl.set_mode(3);
+    subq[0] = ctrl.0;
+    subq[1..9].copy_from_slice(&bits.to_be_bytes());
+
+    finish_q_frame(subq, aframe)
+}
+
+/// Returns true if `subq` is a Mode 2 (media catalog number) or Mode 3 (ISRC) Q frame with a valid
+/// CRC. These frames can't be represented by `Q`, which only decodes Mode 1 position data.
+pub fn is_mcn_or_isrc_q_raw(subq: &[u8; 12]) -> bool {
+    let mode = AdrControl(subq[0]).mode();
+
+    (mode == 2 || mode == 3) && crc16_be(&subq[..10]) == subq[10..]
+}
+
+/// Decode the media catalog number stored in the raw Mode 2 Q frame `subq`, see `mcn_q_raw`.
+///
+/// Returns `None` if the CRC is invalid, if this is not a Mode 2 frame or if the MCN is not valid.
+/// An all-zero MCN, used by discs without a catalog number, also returns `None`.
+pub fn mcn_from_q_raw(subq: &[u8; 12]) -> Option<String> {
+    if AdrControl(subq[0]).mode() != 2 || crc16_be(&subq[..10]) != subq[10..] {
+        return None;
+    }
+
+    let mcn: String = (0..13)
+        .map(|i| {
+            let b = subq[1 + i / 2];
+            let d = if i & 1 == 0 { b >> 4 } else { b & 0xf };
+
+            (b'0' + d) as char
+        })
+        .collect();
+
+    if is_valid_mcn(&mcn) && mcn.bytes().any(|b| b != b'0') {
+        Some(mcn)
+    } else {
+        None
+    }
+}
+
+/// Decode the ISRC stored in the raw Mode 3 Q frame `subq`, see `isrc_q_raw`.
+///
+/// Returns `None` if the CRC is invalid, if this is not a Mode 3 frame or if the ISRC is not
+/// valid.
+pub fn isrc_from_q_raw(subq: &[u8; 12]) -> Option<String> {
+    if AdrControl(subq[0]).mode() != 3 || crc16_be(&subq[..10]) != subq[10..] {
+        return None;
+    }
+
+    let bits = u64::from_be_bytes(*array_ref![subq, 1, 8]) >> 4;
+
+    let mut isrc = String::with_capacity(12);
+
+    for i in (0..5).rev() {
+        let c = ((bits >> (30 + i * 6)) & 0x3f) as u8;
+
+        isrc.push((b'0' + c) as char);
+    }
+
+    for i in (0..7).rev() {
+        let d = ((bits >> (i * 4)) & 0xf) as u8;
+
+        isrc.push((b'0' + d) as char);
+    }
+
+    if is_valid_isrc(&isrc) {
+        Some(isrc)
+    } else {
+        None
+    }
+}
+
+/// Store `aframe` in the Mode 2/3 Q frame `subq` and compute its CRC
+fn finish_q_frame(mut subq: [u8; 12], aframe: u8) -> CdResult<[u8; 12]> {
+    subq[9] = match Bcd::from_binary(aframe) {
+        Some(f) if aframe < 75 => f.bcd(),
+        _ => return Err(CdError::InvalidMsf),
+    };
+
+    let crc = crc16_be(&subq[..10]);
+
+    subq[10] = crc[0];
+    subq[11] = crc[1];
+
+    Ok(subq)
+}
+
+/// Returns the value of the P subchannel flag in the raw interleaved subchannel data `raw` (bit 7
+/// of every byte). The flag is set during pauses between tracks and should have the same value for
+/// the 96 bits of a sector, if it's not the case the majority wins.
+pub fn p_flag(raw: &[u8; 96]) -> bool {
+    let set = raw.iter().filter(|&&b| b & 0x80 != 0).count();
+
+    set > raw.len() / 2
+}
+
+/// Returns true if the 96 P subchannel bits of `raw` all have the same value, see `p_flag`
+pub fn p_flag_consistent(raw: &[u8; 96]) -> bool {
+    let first = raw[0] & 0x80;
+
+    raw.iter().all(|&b| b & 0x80 == first)
+}
+
+/// Raw interleaved subchannel data for a single sector: 96 bytes, each containing one bit of
+/// every subchannel (P in bit 7, Q in bit 6 and R to W in bits 5 to 0)
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RawSubchannel(
+    #[cfg_attr(feature = "serde", serde(with = "serde_big_array::BigArray"))] [u8; 96],
+);
+
+impl RawSubchannel {
+    /// Wrap 96 bytes of raw interleaved subchannel data
+    pub fn new(raw: [u8; 96]) -> RawSubchannel {
+        RawSubchannel(raw)
+    }
+
+    /// Interleave the 12 bytes of raw Q subchannel data `subq`, the other subchannels are set to 0
+    pub fn from_q(subq: [u8; 12]) -> RawSubchannel {
+        let mut raw = [0u8; 96];
+
+        for (bit, r) in raw.iter_mut().enumerate() {
+            if subq[bit / 8] & (1 << (7 - (bit & 7))) != 0 {
+                *r = 0x40;
+            }
+        }
+
+        RawSubchannel(raw)
+    }
+
+    /// Returns the raw interleaved data
+    pub fn as_bytes(&self) -> &[u8; 96] {
+        &self.0
+    }
+
+    /// Returns the 12 bytes of P subchannel data
+    pub fn p(&self) -> [u8; 12] {
+        self.deinterleave(7)
+    }
+
+    /// Returns the 12 bytes of raw Q subchannel data. Use `Q::from_raw_interleaved` to decode it.
+    pub fn q(&self) -> [u8; 12] {
+        self.deinterleave(6)
+    }
+
+    /// Returns the R to W subchannels: the 96 bytes with the P and Q bits masked. This is the
+    /// layout of the CD+G and CD-TEXT packs.
+    pub fn rw(&self) -> [u8; 96] {
+        let mut rw = self.0;
+
+        for b in rw.iter_mut() {
+            *b &= 0x3f;
+        }
+
+        rw
+    }
+
+    /// Extract the 12 bytes of the subchannel stored in bit `bit`
+    fn deinterleave(&self, bit: u8) -> [u8; 12] {
+        let mut out = [0u8; 12];
+
+        for (i, &r) in self.0.iter().enumerate() {
+            if r & (1 << bit) != 0 {
+                out[i / 8] |= 1 << (7 - (i & 7));
+            }
+        }
+
+        out
+    }
+}
+
+impl fmt::Debug for RawSubchannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RawSubchannel").field(&&self.0[..]).finish()
+    }
+}
+
 /// The first byte of subchannel Q data, containing the mode and various attributes
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct AdrControl(u8);
 
 impl AdrControl {
@@ -574,6 +842,12 @@ impl AdrControl {
         }
     }
 
+    /// Returns the 4 control bits (four-channel audio, data, digital copy permitted and
+    /// pre-emphasis) in the low nibble of the returned value
+    pub fn control(&self) -> u8 {
+        self.0 >> 4
+    }
+
     /// Retrieve the mode of the data specified by this Q-subchannel.
     ///
     /// The Q subchannel has several modes (see section 5.4.3 of ECMA-395). Mode 1 is used to store
@@ -655,225 +929,237 @@ fn subq_raw_rw() {
         assert_eq!(q, qr);
 
         let subq_generated = q.to_raw();
-        assert_eq!(subq, subq_generated)
+        assert_eq!(subq, subq_generated);
+
+        let interleaved = q.to_raw_interleaved();
+        for (&r, &i) in raw.iter().zip(interleaved.iter()) {
+            assert_eq!(r & 0x40, i);
+        }
     }
 }
 
+/// Lead-in ToC frames dumped from Ridge Racer on the PlayStation with the CRC manually computed
+/// since the decoder doesn't output it
+#[cfg(test)]
+pub(crate) const RIDGE_RACER_LEAD_IN: [[u8; 12]; 69] = [
+    [
+        0x41, 0x00, 0xa0, 0x07, 0x13, 0x29, 0x00, 0x01, 0x20, 0x00, 0x38, 0x77,
+    ],
+    [
+        0x41, 0x00, 0xa0, 0x07, 0x13, 0x30, 0x00, 0x01, 0x20, 0x00, 0x94, 0x51,
+    ],
+    [
+        0x41, 0x00, 0xa0, 0x07, 0x13, 0x31, 0x00, 0x01, 0x20, 0x00, 0x3e, 0x00,
+    ],
+    [
+        0x01, 0x00, 0xa1, 0x07, 0x13, 0x32, 0x00, 0x20, 0x00, 0x00, 0x52, 0x0b,
+    ],
+    [
+        0x01, 0x00, 0xa1, 0x07, 0x13, 0x33, 0x00, 0x20, 0x00, 0x00, 0xf8, 0x5a,
+    ],
+    [
+        0x01, 0x00, 0xa1, 0x07, 0x13, 0x34, 0x00, 0x20, 0x00, 0x00, 0x9f, 0x8e,
+    ],
+    [
+        0x01, 0x00, 0xa2, 0x07, 0x13, 0x35, 0x00, 0x69, 0x48, 0x74, 0xc4, 0xe0,
+    ],
+    [
+        0x01, 0x00, 0xa2, 0x07, 0x13, 0x36, 0x00, 0x69, 0x48, 0x74, 0x2a, 0x32,
+    ],
+    [
+        0x01, 0x00, 0xa2, 0x07, 0x13, 0x37, 0x00, 0x69, 0x48, 0x74, 0x80, 0x63,
+    ],
+    [
+        0x41, 0x00, 0x01, 0x07, 0x13, 0x38, 0x00, 0x00, 0x02, 0x00, 0x00, 0xf2,
+    ],
+    [
+        0x41, 0x00, 0x01, 0x07, 0x13, 0x39, 0x00, 0x00, 0x02, 0x00, 0xaa, 0xa3,
+    ],
+    [
+        0x41, 0x00, 0x01, 0x07, 0x13, 0x40, 0x00, 0x00, 0x02, 0x00, 0x1f, 0x59,
+    ],
+    [
+        0x01, 0x00, 0x02, 0x07, 0x13, 0x41, 0x00, 0x01, 0x06, 0x51, 0xbe, 0x47,
+    ],
+    [
+        0x01, 0x00, 0x02, 0x07, 0x13, 0x42, 0x00, 0x01, 0x06, 0x51, 0x50, 0x95,
+    ],
+    [
+        0x01, 0x00, 0x02, 0x07, 0x13, 0x43, 0x00, 0x01, 0x06, 0x51, 0xfa, 0xc4,
+    ],
+    [
+        0x01, 0x00, 0x03, 0x07, 0x13, 0x44, 0x00, 0x01, 0x15, 0x63, 0x9a, 0xf2,
+    ],
+    [
+        0x01, 0x00, 0x03, 0x07, 0x13, 0x45, 0x00, 0x01, 0x15, 0x63, 0x30, 0xa3,
+    ],
+    [
+        0x01, 0x00, 0x03, 0x07, 0x13, 0x46, 0x00, 0x01, 0x15, 0x63, 0xde, 0x71,
+    ],
+    [
+        0x01, 0x00, 0x04, 0x07, 0x13, 0x47, 0x00, 0x02, 0x58, 0x64, 0xe1, 0x1f,
+    ],
+    [
+        0x01, 0x00, 0x04, 0x07, 0x13, 0x48, 0x00, 0x02, 0x58, 0x64, 0x84, 0xe6,
+    ],
+    [
+        0x01, 0x00, 0x04, 0x07, 0x13, 0x49, 0x00, 0x02, 0x58, 0x64, 0x2e, 0xb7,
+    ],
+    [
+        0x01, 0x00, 0x05, 0x07, 0x13, 0x50, 0x00, 0x05, 0x15, 0x64, 0x3b, 0x42,
+    ],
+    [
+        0x01, 0x00, 0x05, 0x07, 0x13, 0x51, 0x00, 0x05, 0x15, 0x64, 0x91, 0x13,
+    ],
+    [
+        0x01, 0x00, 0x05, 0x07, 0x13, 0x52, 0x00, 0x05, 0x15, 0x64, 0x7f, 0xc1,
+    ],
+    [
+        0x01, 0x00, 0x06, 0x07, 0x13, 0x53, 0x00, 0x10, 0x17, 0x65, 0xc3, 0x35,
+    ],
+    [
+        0x01, 0x00, 0x06, 0x07, 0x13, 0x54, 0x00, 0x10, 0x17, 0x65, 0xa4, 0xe1,
+    ],
+    [
+        0x01, 0x00, 0x06, 0x07, 0x13, 0x55, 0x00, 0x10, 0x17, 0x65, 0x0e, 0xb0,
+    ],
+    [
+        0x01, 0x00, 0x07, 0x07, 0x13, 0x56, 0x00, 0x15, 0x19, 0x66, 0x5f, 0x2d,
+    ],
+    [
+        0x01, 0x00, 0x07, 0x07, 0x13, 0x57, 0x00, 0x15, 0x19, 0x66, 0xf5, 0x7c,
+    ],
+    [
+        0x01, 0x00, 0x07, 0x07, 0x13, 0x58, 0x00, 0x15, 0x19, 0x66, 0x90, 0x85,
+    ],
+    [
+        0x01, 0x00, 0x08, 0x07, 0x13, 0x59, 0x00, 0x20, 0x21, 0x67, 0x51, 0x5e,
+    ],
+    [
+        0x01, 0x00, 0x08, 0x07, 0x13, 0x60, 0x00, 0x20, 0x21, 0x67, 0xf5, 0xcc,
+    ],
+    [
+        0x01, 0x00, 0x08, 0x07, 0x13, 0x61, 0x00, 0x20, 0x21, 0x67, 0x5f, 0x9d,
+    ],
+    [
+        0x01, 0x00, 0x09, 0x07, 0x13, 0x62, 0x00, 0x25, 0x23, 0x68, 0x8a, 0xe1,
+    ],
+    [
+        0x01, 0x00, 0x09, 0x07, 0x13, 0x63, 0x00, 0x25, 0x23, 0x68, 0x20, 0xb0,
+    ],
+    [
+        0x01, 0x00, 0x09, 0x07, 0x13, 0x64, 0x00, 0x25, 0x23, 0x68, 0x47, 0x64,
+    ],
+    [
+        0x01, 0x00, 0x10, 0x07, 0x13, 0x65, 0x00, 0x30, 0x25, 0x69, 0x9b, 0x9c,
+    ],
+    [
+        0x01, 0x00, 0x10, 0x07, 0x13, 0x66, 0x00, 0x30, 0x25, 0x69, 0x75, 0x4e,
+    ],
+    [
+        0x01, 0x00, 0x10, 0x07, 0x13, 0x67, 0x00, 0x30, 0x25, 0x69, 0xdf, 0x1f,
+    ],
+    [
+        0x01, 0x00, 0x11, 0x07, 0x13, 0x68, 0x00, 0x35, 0x09, 0x36, 0xfe, 0x54,
+    ],
+    [
+        0x01, 0x00, 0x11, 0x07, 0x13, 0x69, 0x00, 0x35, 0x09, 0x36, 0x54, 0x05,
+    ],
+    [
+        0x01, 0x00, 0x11, 0x07, 0x13, 0x70, 0x00, 0x35, 0x09, 0x36, 0xf8, 0x23,
+    ],
+    [
+        0x01, 0x00, 0x12, 0x07, 0x13, 0x71, 0x00, 0x40, 0x11, 0x37, 0x33, 0x04,
+    ],
+    [
+        0x01, 0x00, 0x12, 0x07, 0x13, 0x72, 0x00, 0x40, 0x11, 0x37, 0xdd, 0xd6,
+    ],
+    [
+        0x01, 0x00, 0x12, 0x07, 0x13, 0x73, 0x00, 0x40, 0x11, 0x37, 0x77, 0x87,
+    ],
+    [
+        0x01, 0x00, 0x13, 0x07, 0x13, 0x74, 0x00, 0x45, 0x13, 0x38, 0x2b, 0xfd,
+    ],
+    [
+        0x01, 0x00, 0x13, 0x07, 0x14, 0x00, 0x00, 0x45, 0x13, 0x38, 0x77, 0x3c,
+    ],
+    [
+        0x01, 0x00, 0x13, 0x07, 0x14, 0x01, 0x00, 0x45, 0x13, 0x38, 0xdd, 0x6d,
+    ],
+    [
+        0x01, 0x00, 0x14, 0x07, 0x14, 0x02, 0x00, 0x50, 0x15, 0x39, 0xe6, 0xb3,
+    ],
+    [
+        0x01, 0x00, 0x14, 0x07, 0x14, 0x03, 0x00, 0x50, 0x15, 0x39, 0x4c, 0xe2,
+    ],
+    [
+        0x01, 0x00, 0x14, 0x07, 0x14, 0x04, 0x00, 0x50, 0x15, 0x39, 0x2b, 0x36,
+    ],
+    [
+        0x01, 0x00, 0x15, 0x07, 0x14, 0x05, 0x00, 0x55, 0x17, 0x40, 0xa4, 0x98,
+    ],
+    [
+        0x01, 0x00, 0x15, 0x07, 0x14, 0x06, 0x00, 0x55, 0x17, 0x40, 0x4a, 0x4a,
+    ],
+    [
+        0x01, 0x00, 0x15, 0x07, 0x14, 0x07, 0x00, 0x55, 0x17, 0x40, 0xe0, 0x1b,
+    ],
+    [
+        0x01, 0x00, 0x16, 0x07, 0x14, 0x08, 0x00, 0x60, 0x19, 0x41, 0x50, 0xec,
+    ],
+    [
+        0x01, 0x00, 0x16, 0x07, 0x14, 0x09, 0x00, 0x60, 0x19, 0x41, 0xfa, 0xbd,
+    ],
+    [
+        0x01, 0x00, 0x16, 0x07, 0x14, 0x10, 0x00, 0x60, 0x19, 0x41, 0x56, 0x9b,
+    ],
+    [
+        0x01, 0x00, 0x17, 0x07, 0x14, 0x11, 0x00, 0x60, 0x36, 0x42, 0x9d, 0xa2,
+    ],
+    [
+        0x01, 0x00, 0x17, 0x07, 0x14, 0x12, 0x00, 0x60, 0x36, 0x42, 0x73, 0x70,
+    ],
+    [
+        0x01, 0x00, 0x17, 0x07, 0x14, 0x13, 0x00, 0x60, 0x36, 0x42, 0xd9, 0x21,
+    ],
+    [
+        0x01, 0x00, 0x18, 0x07, 0x14, 0x14, 0x00, 0x61, 0x37, 0x05, 0x5b, 0x15,
+    ],
+    [
+        0x01, 0x00, 0x18, 0x07, 0x14, 0x15, 0x00, 0x61, 0x37, 0x05, 0xf1, 0x44,
+    ],
+    [
+        0x01, 0x00, 0x18, 0x07, 0x14, 0x16, 0x00, 0x61, 0x37, 0x05, 0x1f, 0x96,
+    ],
+    [
+        0x01, 0x00, 0x19, 0x07, 0x14, 0x17, 0x00, 0x63, 0x34, 0x22, 0x9d, 0xa2,
+    ],
+    [
+        0x01, 0x00, 0x19, 0x07, 0x14, 0x18, 0x00, 0x63, 0x34, 0x22, 0xf8, 0x5b,
+    ],
+    [
+        0x01, 0x00, 0x19, 0x07, 0x14, 0x19, 0x00, 0x63, 0x34, 0x22, 0x52, 0x0a,
+    ],
+    [
+        0x01, 0x00, 0x20, 0x07, 0x14, 0x20, 0x00, 0x66, 0x42, 0x49, 0x7d, 0x8f,
+    ],
+    [
+        0x01, 0x00, 0x20, 0x07, 0x14, 0x21, 0x00, 0x66, 0x42, 0x49, 0xd7, 0xde,
+    ],
+    [
+        0x01, 0x00, 0x20, 0x07, 0x14, 0x22, 0x00, 0x66, 0x42, 0x49, 0x39, 0x0c,
+    ],
+];
+
 #[test]
 fn subq_lead_in() {
-    // Dumped from Ridge Racer on the PlayStation with the CRC manually computed since the decoder
-    // doesn't output it
-    let toc = &[
-        [
-            0x41, 0x00, 0xa0, 0x07, 0x13, 0x29, 0x00, 0x01, 0x20, 0x00, 0x38, 0x77,
-        ],
-        [
-            0x41, 0x00, 0xa0, 0x07, 0x13, 0x30, 0x00, 0x01, 0x20, 0x00, 0x94, 0x51,
-        ],
-        [
-            0x41, 0x00, 0xa0, 0x07, 0x13, 0x31, 0x00, 0x01, 0x20, 0x00, 0x3e, 0x00,
-        ],
-        [
-            0x01, 0x00, 0xa1, 0x07, 0x13, 0x32, 0x00, 0x20, 0x00, 0x00, 0x52, 0x0b,
-        ],
-        [
-            0x01, 0x00, 0xa1, 0x07, 0x13, 0x33, 0x00, 0x20, 0x00, 0x00, 0xf8, 0x5a,
-        ],
-        [
-            0x01, 0x00, 0xa1, 0x07, 0x13, 0x34, 0x00, 0x20, 0x00, 0x00, 0x9f, 0x8e,
-        ],
-        [
-            0x01, 0x00, 0xa2, 0x07, 0x13, 0x35, 0x00, 0x69, 0x48, 0x74, 0xc4, 0xe0,
-        ],
-        [
-            0x01, 0x00, 0xa2, 0x07, 0x13, 0x36, 0x00, 0x69, 0x48, 0x74, 0x2a, 0x32,
-        ],
-        [
-            0x01, 0x00, 0xa2, 0x07, 0x13, 0x37, 0x00, 0x69, 0x48, 0x74, 0x80, 0x63,
-        ],
-        [
-            0x41, 0x00, 0x01, 0x07, 0x13, 0x38, 0x00, 0x00, 0x02, 0x00, 0x00, 0xf2,
-        ],
-        [
-            0x41, 0x00, 0x01, 0x07, 0x13, 0x39, 0x00, 0x00, 0x02, 0x00, 0xaa, 0xa3,
-        ],
-        [
-            0x41, 0x00, 0x01, 0x07, 0x13, 0x40, 0x00, 0x00, 0x02, 0x00, 0x1f, 0x59,
-        ],
-        [
-            0x01, 0x00, 0x02, 0x07, 0x13, 0x41, 0x00, 0x01, 0x06, 0x51, 0xbe, 0x47,
-        ],
-        [
-            0x01, 0x00, 0x02, 0x07, 0x13, 0x42, 0x00, 0x01, 0x06, 0x51, 0x50, 0x95,
-        ],
-        [
-            0x01, 0x00, 0x02, 0x07, 0x13, 0x43, 0x00, 0x01, 0x06, 0x51, 0xfa, 0xc4,
-        ],
-        [
-            0x01, 0x00, 0x03, 0x07, 0x13, 0x44, 0x00, 0x01, 0x15, 0x63, 0x9a, 0xf2,
-        ],
-        [
-            0x01, 0x00, 0x03, 0x07, 0x13, 0x45, 0x00, 0x01, 0x15, 0x63, 0x30, 0xa3,
-        ],
-        [
-            0x01, 0x00, 0x03, 0x07, 0x13, 0x46, 0x00, 0x01, 0x15, 0x63, 0xde, 0x71,
-        ],
-        [
-            0x01, 0x00, 0x04, 0x07, 0x13, 0x47, 0x00, 0x02, 0x58, 0x64, 0xe1, 0x1f,
-        ],
-        [
-            0x01, 0x00, 0x04, 0x07, 0x13, 0x48, 0x00, 0x02, 0x58, 0x64, 0x84, 0xe6,
-        ],
-        [
-            0x01, 0x00, 0x04, 0x07, 0x13, 0x49, 0x00, 0x02, 0x58, 0x64, 0x2e, 0xb7,
-        ],
-        [
-            0x01, 0x00, 0x05, 0x07, 0x13, 0x50, 0x00, 0x05, 0x15, 0x64, 0x3b, 0x42,
-        ],
-        [
-            0x01, 0x00, 0x05, 0x07, 0x13, 0x51, 0x00, 0x05, 0x15, 0x64, 0x91, 0x13,
-        ],
-        [
-            0x01, 0x00, 0x05, 0x07, 0x13, 0x52, 0x00, 0x05, 0x15, 0x64, 0x7f, 0xc1,
-        ],
-        [
-            0x01, 0x00, 0x06, 0x07, 0x13, 0x53, 0x00, 0x10, 0x17, 0x65, 0xc3, 0x35,
-        ],
-        [
-            0x01, 0x00, 0x06, 0x07, 0x13, 0x54, 0x00, 0x10, 0x17, 0x65, 0xa4, 0xe1,
-        ],
-        [
-            0x01, 0x00, 0x06, 0x07, 0x13, 0x55, 0x00, 0x10, 0x17, 0x65, 0x0e, 0xb0,
-        ],
-        [
-            0x01, 0x00, 0x07, 0x07, 0x13, 0x56, 0x00, 0x15, 0x19, 0x66, 0x5f, 0x2d,
-        ],
-        [
-            0x01, 0x00, 0x07, 0x07, 0x13, 0x57, 0x00, 0x15, 0x19, 0x66, 0xf5, 0x7c,
-        ],
-        [
-            0x01, 0x00, 0x07, 0x07, 0x13, 0x58, 0x00, 0x15, 0x19, 0x66, 0x90, 0x85,
-        ],
-        [
-            0x01, 0x00, 0x08, 0x07, 0x13, 0x59, 0x00, 0x20, 0x21, 0x67, 0x51, 0x5e,
-        ],
-        [
-            0x01, 0x00, 0x08, 0x07, 0x13, 0x60, 0x00, 0x20, 0x21, 0x67, 0xf5, 0xcc,
-        ],
-        [
-            0x01, 0x00, 0x08, 0x07, 0x13, 0x61, 0x00, 0x20, 0x21, 0x67, 0x5f, 0x9d,
-        ],
-        [
-            0x01, 0x00, 0x09, 0x07, 0x13, 0x62, 0x00, 0x25, 0x23, 0x68, 0x8a, 0xe1,
-        ],
-        [
-            0x01, 0x00, 0x09, 0x07, 0x13, 0x63, 0x00, 0x25, 0x23, 0x68, 0x20, 0xb0,
-        ],
-        [
-            0x01, 0x00, 0x09, 0x07, 0x13, 0x64, 0x00, 0x25, 0x23, 0x68, 0x47, 0x64,
-        ],
-        [
-            0x01, 0x00, 0x10, 0x07, 0x13, 0x65, 0x00, 0x30, 0x25, 0x69, 0x9b, 0x9c,
-        ],
-        [
-            0x01, 0x00, 0x10, 0x07, 0x13, 0x66, 0x00, 0x30, 0x25, 0x69, 0x75, 0x4e,
-        ],
-        [
-            0x01, 0x00, 0x10, 0x07, 0x13, 0x67, 0x00, 0x30, 0x25, 0x69, 0xdf, 0x1f,
-        ],
-        [
-            0x01, 0x00, 0x11, 0x07, 0x13, 0x68, 0x00, 0x35, 0x09, 0x36, 0xfe, 0x54,
-        ],
-        [
-            0x01, 0x00, 0x11, 0x07, 0x13, 0x69, 0x00, 0x35, 0x09, 0x36, 0x54, 0x05,
-        ],
-        [
-            0x01, 0x00, 0x11, 0x07, 0x13, 0x70, 0x00, 0x35, 0x09, 0x36, 0xf8, 0x23,
-        ],
-        [
-            0x01, 0x00, 0x12, 0x07, 0x13, 0x71, 0x00, 0x40, 0x11, 0x37, 0x33, 0x04,
-        ],
-        [
-            0x01, 0x00, 0x12, 0x07, 0x13, 0x72, 0x00, 0x40, 0x11, 0x37, 0xdd, 0xd6,
-        ],
-        [
-            0x01, 0x00, 0x12, 0x07, 0x13, 0x73, 0x00, 0x40, 0x11, 0x37, 0x77, 0x87,
-        ],
-        [
-            0x01, 0x00, 0x13, 0x07, 0x13, 0x74, 0x00, 0x45, 0x13, 0x38, 0x2b, 0xfd,
-        ],
-        [
-            0x01, 0x00, 0x13, 0x07, 0x14, 0x00, 0x00, 0x45, 0x13, 0x38, 0x77, 0x3c,
-        ],
-        [
-            0x01, 0x00, 0x13, 0x07, 0x14, 0x01, 0x00, 0x45, 0x13, 0x38, 0xdd, 0x6d,
-        ],
-        [
-            0x01, 0x00, 0x14, 0x07, 0x14, 0x02, 0x00, 0x50, 0x15, 0x39, 0xe6, 0xb3,
-        ],
-        [
-            0x01, 0x00, 0x14, 0x07, 0x14, 0x03, 0x00, 0x50, 0x15, 0x39, 0x4c, 0xe2,
-        ],
-        [
-            0x01, 0x00, 0x14, 0x07, 0x14, 0x04, 0x00, 0x50, 0x15, 0x39, 0x2b, 0x36,
-        ],
-        [
-            0x01, 0x00, 0x15, 0x07, 0x14, 0x05, 0x00, 0x55, 0x17, 0x40, 0xa4, 0x98,
-        ],
-        [
-            0x01, 0x00, 0x15, 0x07, 0x14, 0x06, 0x00, 0x55, 0x17, 0x40, 0x4a, 0x4a,
-        ],
-        [
-            0x01, 0x00, 0x15, 0x07, 0x14, 0x07, 0x00, 0x55, 0x17, 0x40, 0xe0, 0x1b,
-        ],
-        [
-            0x01, 0x00, 0x16, 0x07, 0x14, 0x08, 0x00, 0x60, 0x19, 0x41, 0x50, 0xec,
-        ],
-        [
-            0x01, 0x00, 0x16, 0x07, 0x14, 0x09, 0x00, 0x60, 0x19, 0x41, 0xfa, 0xbd,
-        ],
-        [
-            0x01, 0x00, 0x16, 0x07, 0x14, 0x10, 0x00, 0x60, 0x19, 0x41, 0x56, 0x9b,
-        ],
-        [
-            0x01, 0x00, 0x17, 0x07, 0x14, 0x11, 0x00, 0x60, 0x36, 0x42, 0x9d, 0xa2,
-        ],
-        [
-            0x01, 0x00, 0x17, 0x07, 0x14, 0x12, 0x00, 0x60, 0x36, 0x42, 0x73, 0x70,
-        ],
-        [
-            0x01, 0x00, 0x17, 0x07, 0x14, 0x13, 0x00, 0x60, 0x36, 0x42, 0xd9, 0x21,
-        ],
-        [
-            0x01, 0x00, 0x18, 0x07, 0x14, 0x14, 0x00, 0x61, 0x37, 0x05, 0x5b, 0x15,
-        ],
-        [
-            0x01, 0x00, 0x18, 0x07, 0x14, 0x15, 0x00, 0x61, 0x37, 0x05, 0xf1, 0x44,
-        ],
-        [
-            0x01, 0x00, 0x18, 0x07, 0x14, 0x16, 0x00, 0x61, 0x37, 0x05, 0x1f, 0x96,
-        ],
-        [
-            0x01, 0x00, 0x19, 0x07, 0x14, 0x17, 0x00, 0x63, 0x34, 0x22, 0x9d, 0xa2,
-        ],
-        [
-            0x01, 0x00, 0x19, 0x07, 0x14, 0x18, 0x00, 0x63, 0x34, 0x22, 0xf8, 0x5b,
-        ],
-        [
-            0x01, 0x00, 0x19, 0x07, 0x14, 0x19, 0x00, 0x63, 0x34, 0x22, 0x52, 0x0a,
-        ],
-        [
-            0x01, 0x00, 0x20, 0x07, 0x14, 0x20, 0x00, 0x66, 0x42, 0x49, 0x7d, 0x8f,
-        ],
-        [
-            0x01, 0x00, 0x20, 0x07, 0x14, 0x21, 0x00, 0x66, 0x42, 0x49, 0xd7, 0xde,
-        ],
-        [
-            0x01, 0x00, 0x20, 0x07, 0x14, 0x22, 0x00, 0x66, 0x42, 0x49, 0x39, 0x0c,
-        ],
-    ];
+    for &raw in RIDGE_RACER_LEAD_IN.iter() {
+        assert_eq!(::checksums::crc16_be(&raw[..10]), raw[10..12]);
+        assert_eq!(
+            ::checksums::crc16(&raw[..10]),
+            u16::from_be_bytes([raw[10], raw[11]])
+        );
 
-    for &raw in toc.iter() {
         let q = Q::from_raw(raw).unwrap();
         let q_generated = q.to_raw();
 
@@ -911,3 +1197,92 @@ fn subq_lead_out() {
         assert_eq!(raw, q_generated)
     }
 }
+
+#[test]
+fn mcn_isrc_frames() {
+    let mcn = mcn_q_raw("0123456789012", AdrControl::AUDIO, 0x21).unwrap();
+
+    assert_eq!(
+        mcn[..10],
+        [0x02, 0x01, 0x23, 0x45, 0x67, 0x89, 0x01, 0x20, 0x00, 0x33]
+    );
+    assert_eq!(mcn[10..], crc16_be(&mcn[..10]));
+
+    // Example from the red book: ISRC "NLA9S0800001" (letters are 'A' - '0' = 17)
+    let isrc = isrc_q_raw("NLA9S0800001", AdrControl::DATA, 74).unwrap();
+
+    let mut expected: u64 = 0;
+    for &c in &[30u64, 28, 17, 9, 35] {
+        expected = (expected << 6) | c;
+    }
+    expected = (((expected << 2) << 28) | 0x080_0001) << 4;
+
+    assert_eq!(isrc[0], 0x43);
+    assert_eq!(isrc[1..9], expected.to_be_bytes());
+    assert_eq!(isrc[9], 0x74);
+    assert_eq!(isrc[10..], crc16_be(&isrc[..10]));
+
+    assert_eq!(mcn_from_q_raw(&mcn).unwrap(), "0123456789012");
+    assert_eq!(isrc_from_q_raw(&isrc).unwrap(), "NLA9S0800001");
+    assert_eq!(isrc_from_q_raw(&mcn), None);
+    assert_eq!(mcn_from_q_raw(&isrc), None);
+
+    let mut bad_crc = mcn;
+    bad_crc[11] ^= 1;
+    assert_eq!(mcn_from_q_raw(&bad_crc), None);
+
+    let zero = mcn_q_raw("0000000000000", AdrControl::AUDIO, 0).unwrap();
+    assert_eq!(mcn_from_q_raw(&zero), None);
+
+    assert!(matches!(
+        mcn_q_raw("012345678901", AdrControl::AUDIO, 0),
+        Err(CdError::BadFormat)
+    ));
+    assert!(matches!(
+        isrc_q_raw("nla9s0800001", AdrControl::AUDIO, 0),
+        Err(CdError::BadFormat)
+    ));
+    assert!(matches!(
+        isrc_q_raw("NLA9S080000A", AdrControl::AUDIO, 0),
+        Err(CdError::BadFormat)
+    ));
+    assert!(matches!(
+        mcn_q_raw("0123456789012", AdrControl::AUDIO, 75),
+        Err(CdError::InvalidMsf)
+    ));
+}
+
+#[test]
+fn p_subchannel() {
+    let qdata = QData::Mode1 {
+        track: Bcd::from_binary(2).unwrap(),
+        index: Bcd::ZERO,
+        track_msf: Msf::from_bcd(0x00, 0x01, 0x00).unwrap(),
+        disc_msf: Msf::from_bcd(0x04, 0x12, 0x33).unwrap(),
+    };
+
+    // Pregap frame with the P flag set and some R-W data
+    let mut raw = Q::from_qdata_mode1(qdata, AdrControl::AUDIO).to_raw_interleaved();
+
+    for (i, r) in raw.iter_mut().enumerate() {
+        *r |= 0x80 | (i as u8 & 0x3f);
+    }
+
+    assert!(p_flag(&raw));
+    assert!(p_flag_consistent(&raw));
+    assert_eq!(RawSubchannel::new(raw).p(), [0xff; 12]);
+
+    // A few corrupted bits don't change the flag
+    raw[3] &= 0x7f;
+    raw[50] &= 0x7f;
+
+    assert!(p_flag(&raw));
+    assert!(!p_flag_consistent(&raw));
+
+    for r in raw.iter_mut() {
+        *r &= 0x7f;
+    }
+
+    assert!(!p_flag(&raw));
+    assert!(p_flag_consistent(&raw));
+}
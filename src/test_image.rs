@@ -0,0 +1,67 @@
+//! Synthetic disc image shared by the unit tests
+
+use bcd::Bcd;
+use subchannel::{QData, Q};
+use {CdError, CdResult, DiscPosition, Image, Msf, Sector, Toc, Track};
+
+/// Image whose sectors are generated on the fly from its ToC. Every sector is created with
+/// `Sector::empty` and then handed to `fill` along with its track and absolute MSF so that the
+/// test can set its contents.
+pub struct TestImage<F> {
+    toc: Toc,
+    fill: F,
+}
+
+impl<F> TestImage<F>
+where
+    F: FnMut(&Track, Msf, &mut Sector),
+{
+    /// Create an image containing `tracks`
+    pub fn new(tracks: Vec<Track>, fill: F) -> TestImage<F> {
+        TestImage {
+            toc: Toc::new(tracks).unwrap(),
+            fill,
+        }
+    }
+}
+
+impl<F> Image for TestImage<F>
+where
+    F: FnMut(&Track, Msf, &mut Sector),
+{
+    fn image_format(&self) -> String {
+        "Test".to_string()
+    }
+
+    fn read_sector(&mut self, position: DiscPosition) -> CdResult<Sector> {
+        let msf = match position {
+            DiscPosition::Program(msf) => msf,
+            DiscPosition::LeadIn(_) => return Err(CdError::Unsupported),
+        };
+
+        let t = self
+            .toc
+            .tracks()
+            .iter()
+            .rev()
+            .find(|t| t.start <= msf)
+            .ok_or(CdError::OutOfDiscPosition)?;
+
+        let qdata = QData::Mode1 {
+            track: t.track,
+            index: Bcd::ONE,
+            track_msf: msf - t.start,
+            disc_msf: msf,
+        };
+
+        let mut sector = Sector::empty(Q::from_qdata_mode1(qdata, t.control), t.format)?;
+
+        (self.fill)(t, msf, &mut sector);
+
+        Ok(sector)
+    }
+
+    fn toc(&self) -> &Toc {
+        &self.toc
+    }
+}
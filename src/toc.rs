@@ -1,22 +1,293 @@
-use std::fmt;
-use subchannel::{QData, Q};
-use {Bcd, CdError, CdResult, Msf, Sector, SessionFormat, Track, TrackFormat};
+use disc_position::Radius;
+use std::iter::FromIterator;
+use std::path::PathBuf;
+use std::{cmp, fmt};
+use subchannel::{AdrControl, QData, Q};
+use {Bcd, CdError, CdResult, DiscPosition, Msf, Sector, SessionFormat, Track, TrackFormat};
+
+#[cfg(test)]
+use sector::XaForm;
 
 /// Table of contents
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "TocRepr")
+)]
 #[derive(Clone, PartialEq, Eq)]
 pub struct Toc {
     /// Track list
     tracks: Vec<Track>,
+    /// Number of sectors in the lead-out
+    lead_out_length: Msf,
+    /// Contents of the lead-out sectors
+    lead_out_mode: LeadOutMode,
+    /// Session number of each track, in the same order as `tracks`
+    sessions: Vec<u8>,
+}
+
+/// Deserialized form of `Toc`, with defaults for the fields missing from ToCs serialized by older
+/// versions of this crate
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct TocRepr {
+    tracks: Vec<Track>,
+    #[serde(default = "Toc::default_lead_out_length")]
+    lead_out_length: Msf,
+    #[serde(default)]
+    lead_out_mode: LeadOutMode,
+    #[serde(default)]
+    sessions: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl From<TocRepr> for Toc {
+    fn from(repr: TocRepr) -> Toc {
+        // Every track must have a session, put them all in session 1 if they're missing
+        let sessions = if repr.sessions.len() == repr.tracks.len() {
+            repr.sessions
+        } else {
+            vec![1; repr.tracks.len()]
+        };
+
+        Toc {
+            tracks: repr.tracks,
+            lead_out_length: repr.lead_out_length,
+            lead_out_mode: repr.lead_out_mode,
+            sessions,
+        }
+    }
 }
 
 impl Toc {
+    /// Default length of the lead-out: 1 minute 30 seconds, the minimum length for the lead-out of
+    /// the first session of a disc
+    pub const DEFAULT_LEAD_OUT_LENGTH: Msf = match Msf::from_sector_index(90 * 75) {
+        Some(msf) => msf,
+        None => panic!("Invalid lead-out length"),
+    };
+
+    /// Used when deserializing a ToC serialized before the lead-out length was stored
+    #[cfg(feature = "serde")]
+    fn default_lead_out_length() -> Msf {
+        Toc::DEFAULT_LEAD_OUT_LENGTH
+    }
+
     pub(crate) fn new(tracks: Vec<Track>) -> CdResult<Toc> {
         if tracks.is_empty() {
             Err(CdError::EmptyToc)
         } else {
-            Ok(Toc { tracks })
+            Ok(Toc {
+                sessions: vec![1; tracks.len()],
+                tracks,
+                lead_out_length: Toc::DEFAULT_LEAD_OUT_LENGTH,
+                lead_out_mode: LeadOutMode::LastTrack,
+            })
+        }
+    }
+
+    /// Build a ToC from an explicit track list, for instance for synthetic images or tests.
+    /// `lead_out` is the position of the first sector of the lead-out, it must immediately follow
+    /// the last track.
+    ///
+    /// The tracks must be numbered from 1 without holes, sorted by position and must not overlap.
+    /// Gaps between tracks are allowed since the ToC of real discs often doesn't include the
+    /// pregaps in the length of the previous track. Empty tracks (with a length of 0) are
+    /// allowed, `Track::absolute_msf` returns `CdError::EndOfTrack` for any position within them.
+    /// The control bits must match the format of each track.
+    ///
+    /// Returns `CdError::EmptyToc` if `tracks` is empty and `CdError::BadImage` if the track list
+    /// is invalid.
+    pub fn from_tracks(tracks: Vec<Track>, lead_out: Msf) -> CdResult<Toc> {
+        let bad_toc = |desc: String| CdError::BadImage {
+            path: PathBuf::new(),
+            desc,
+        };
+
+        let mut end = Msf::ZERO;
+
+        for (i, t) in tracks.iter().enumerate() {
+            if t.track.binary() as usize != i + 1 {
+                return Err(bad_toc(format!(
+                    "Expected track {:02} got {}",
+                    i + 1,
+                    t.track
+                )));
+            }
+
+            if t.control.is_audio() != t.format.is_audio() {
+                return Err(bad_toc(format!(
+                    "Track {} control bits don't match its format",
+                    t.track
+                )));
+            }
+
+            if t.start < end {
+                return Err(bad_toc(format!(
+                    "Track {} overlaps the previous track",
+                    t.track
+                )));
+            }
+
+            end = match t.start.checked_add(t.length) {
+                Some(e) => e,
+                None => return Err(bad_toc(format!("Track {} is too long", t.track))),
+            };
         }
+
+        if !tracks.is_empty() && end != lead_out {
+            return Err(bad_toc(format!(
+                "Lead-out at {} but the last track ends at {}",
+                lead_out, end
+            )));
+        }
+
+        Toc::new(tracks)
+    }
+
+    /// Build a ToC from `tracks`, with the lead-out starting right after the last track. The
+    /// tracks are validated the same way as with `Toc::from_tracks`.
+    ///
+    /// The same conversion is available through `collect`:
+    ///
+    /// ```
+    /// # use cdimage::{CdResult, Toc, Track};
+    /// fn build_toc(tracks: &[Track]) -> CdResult<Toc> {
+    ///     tracks.iter().cloned().collect()
+    /// }
+    /// ```
+    pub fn try_from_iter<I>(tracks: I) -> CdResult<Toc>
+    where
+        I: IntoIterator<Item = Track>,
+    {
+        let tracks: Vec<Track> = tracks.into_iter().collect();
+
+        let lead_out = match tracks.last() {
+            Some(t) => t
+                .start
+                .checked_add(t.length)
+                .ok_or_else(|| CdError::BadImage {
+                    path: PathBuf::new(),
+                    desc: format!("Track {} is too long", t.track),
+                })?,
+            None => return Err(CdError::EmptyToc),
+        };
+
+        Toc::from_tracks(tracks, lead_out)
+    }
+
+    /// Rebuild a ToC from the raw Q subchannel `frames` of the lead-in of a single session, for
+    /// instance when an image format only stores the subchannel data. This is the reverse of
+    /// `Toc::build_toc_q`.
+    ///
+    /// Frames that can't be decoded (bad CRC, unsupported mode) or that aren't ToC entries are
+    /// ignored since raw subchannel dumps commonly contain a few bad frames, if an entry is
+    /// repeated the last occurrence wins. The format of the data tracks is deduced from the
+    /// session format found in the A0 entry. Since the lead-in only contains the position of
+    /// the INDEX 01 of every track, the length of a track includes the pregap of the next one.
+    ///
+    /// Returns `CdError::BadImage` if the A0, A1 or A2 entry or the entry for one of the tracks is
+    /// missing, or if the resulting track list is invalid (see `Toc::from_tracks`).
+    pub fn from_lead_in_q<I>(frames: I) -> CdResult<Toc>
+    where
+        I: IntoIterator<Item = [u8; 12]>,
+    {
+        let bad_toc = |desc: String| CdError::BadImage {
+            path: PathBuf::new(),
+            desc,
+        };
+
+        let mut first = None;
+        let mut last = None;
+        let mut lead_out = None;
+        let mut entries = vec![None; 99];
+
+        for raw in frames {
+            let q = match Q::from_raw(raw) {
+                Ok(q) => q,
+                Err(_) => continue,
+            };
+
+            match *q.data() {
+                QData::Mode1TocFirstTrack {
+                    first_track,
+                    session_format,
+                    ..
+                } => first = Some((first_track, session_format)),
+                QData::Mode1TocLastTrack { last_track, .. } => last = Some(last_track),
+                QData::Mode1TocLeadOut { lead_out_start, .. } => lead_out = Some(lead_out_start),
+                QData::Mode1Toc {
+                    track, index1_msf, ..
+                } => {
+                    if let Some(e) = (track.binary() as usize)
+                        .checked_sub(1)
+                        .and_then(|i| entries.get_mut(i))
+                    {
+                        *e = Some((index1_msf, q.adr_control()));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let (first, session_format) = first.ok_or_else(|| bad_toc("Missing A0 entry".into()))?;
+        let last = last.ok_or_else(|| bad_toc("Missing A1 entry".into()))?;
+        let lead_out = lead_out.ok_or_else(|| bad_toc("Missing A2 entry".into()))?;
+
+        if first.binary() == 0 || first > last {
+            return Err(bad_toc(format!("Invalid track range {}-{}", first, last)));
+        }
+
+        let data_format = match session_format {
+            SessionFormat::CdDaCdRom => TrackFormat::Mode1,
+            SessionFormat::Cdi => TrackFormat::Mode2CdI,
+            SessionFormat::CdXa => TrackFormat::Mode2Xa,
+        };
+
+        let mut tracks: Vec<Track> = Vec::new();
+
+        for t in first.binary()..=last.binary() {
+            let track = Bcd::from_binary(t).unwrap();
+
+            let (start, mut control) = match entries[t as usize - 1] {
+                Some(e) => e,
+                None => return Err(bad_toc(format!("Missing entry for track {}", track))),
+            };
+
+            // Only keep the control bits
+            control.set_mode(0);
+
+            let format = if control.is_audio() {
+                TrackFormat::Audio
+            } else {
+                data_format
+            };
+
+            if let Some(prev) = tracks.last_mut() {
+                prev.length = start.checked_sub(prev.start).ok_or_else(|| {
+                    bad_toc(format!(
+                        "Track {} starts before track {}",
+                        track, prev.track
+                    ))
+                })?;
+            }
+
+            tracks.push(Track {
+                track,
+                format,
+                start,
+                length: Msf::ZERO,
+                control,
+            });
+        }
+
+        if let Some(t) = tracks.last_mut() {
+            t.length = lead_out
+                .checked_sub(t.start)
+                .ok_or_else(|| bad_toc(format!("Lead-out starts before track {}", t.track)))?;
+        }
+
+        Toc::from_tracks(tracks, lead_out)
     }
 
     /// Return the Track description for the given `track_no`. Returns an error if `track_no` is 0
@@ -31,13 +302,68 @@ impl Toc {
         self.tracks.get((t - 1) as usize).ok_or(CdError::BadTrack)
     }
 
+    /// Change the format and control bits of `track`
+    pub(crate) fn set_track_format(
+        &mut self,
+        track_no: Bcd,
+        format: TrackFormat,
+        control: AdrControl,
+    ) -> CdResult<()> {
+        let t = track_no.binary();
+
+        let track = match t.checked_sub(1) {
+            Some(i) => self.tracks.get_mut(i as usize).ok_or(CdError::BadTrack)?,
+            None => return Err(CdError::BadTrack),
+        };
+
+        track.format = format;
+        track.control = control;
+
+        Ok(())
+    }
+
     /// Return the full track list
     pub fn tracks(&self) -> &[Track] {
         &self.tracks
     }
 
-    /// Generate a lead-in ToC sector for the given `index`.
+    /// Returns the tracks numbered from `first` to `last` inclusive, in order. Bounds outside of
+    /// the ToC are clamped to the available tracks and the iterator is empty if `first` is
+    /// greater than `last`.
+    pub fn tracks_in_range(&self, first: Bcd, last: Bcd) -> impl Iterator<Item = &Track> {
+        self.tracks
+            .iter()
+            .skip_while(move |t| t.track < first)
+            .take_while(move |t| t.track <= last)
+    }
+
+    /// Set the session number of every track. `sessions` must have one entry per track.
+    pub(crate) fn set_sessions(&mut self, sessions: Vec<u8>) {
+        assert_eq!(sessions.len(), self.tracks.len());
+
+        self.sessions = sessions;
+    }
+
+    /// Generate a lead-in ToC sector for the given `lead_in_msf`.
+    ///
+    /// Like real drives, every ToC entry is repeated 3 times in a row and the entries cycle in
+    /// the order A0 (first track), A1 (last track), A2 (lead-out start), then one entry per track.
+    /// The cycle is aligned on the end of the lead-in (99:59:74), which always contains the last
+    /// track's entry. This matches the lead-in of real discs dumped with their subchannel data.
     pub fn build_toc_sector(&self, lead_in_msf: Msf) -> CdResult<Sector> {
+        let (q, fmt) = self.toc_q_format(lead_in_msf);
+
+        Sector::empty(q, fmt)
+    }
+
+    /// Returns the Q subchannel data of the lead-in sector at `lead_in_msf`, see
+    /// `Toc::build_toc_sector`
+    pub fn build_toc_q(&self, lead_in_msf: Msf) -> Q {
+        self.toc_q_format(lead_in_msf).0
+    }
+
+    /// Returns the Q subchannel data and format of the lead-in sector at `lead_in_msf`
+    fn toc_q_format(&self, lead_in_msf: Msf) -> (Q, TrackFormat) {
         let index = (Msf::MAX - lead_in_msf).sector_index();
 
         // Number of entries in the raw ToC: one per track + first track + last track + lead-in
@@ -47,7 +373,7 @@ impl Toc {
         // We divide by 3 because each entry is usually repeated 3 times in a row
         let entry_off = nentries - ((index / 3) % nentries) - 1;
 
-        let (q, fmt) = match entry_off {
+        match entry_off {
             0 => {
                 let t = &self.tracks[0];
 
@@ -92,26 +418,52 @@ impl Toc {
 
                 (Q::from_qdata_mode1(qdata, t.control), t.format)
             }
-        };
-
-        Sector::empty(q, fmt)
+        }
     }
 
-    /// Generate a lead-out sector for the given position. Returns an error if position is before
-    /// the start of the lead-out.
+    /// Generate a lead-out sector for the given position. Returns `CdError::InvalidLeadOutPosition`
+    /// if the position is before the start of the lead-out and `CdError::OutOfDiscPosition` if it's
+    /// past its end (see `Toc::lead_out_end`).
     ///
-    /// Note that this function will keep generating a lead-out as far as 99:59:74 even though in
-    /// practice it doesn't make a lot of sense and on a real disc we'd long be out of bounds. On
-    /// the other hand there doesn't seem to be a very obvious reasonable choice for how long the
-    /// lead-out should be allowed to be since it'll vary depending on a vast number of factors and
-    /// the various CD image formats don't typically store this information anyway.
+    /// The various CD image formats don't typically store the length of the lead-out since it'll
+    /// vary depending on a vast number of factors, so by default we use the minimum length
+    /// mandated for the first session (`Toc::DEFAULT_LEAD_OUT_LENGTH`). It can be changed with
+    /// `Toc::set_lead_out_length`.
     pub fn build_lead_out_sector(&self, disc_msf: Msf) -> CdResult<Sector> {
+        let (q, format) = self.lead_out_q_format(disc_msf)?;
+
+        let mut sector = Sector::empty(q, format)?;
+
+        if self.lead_out_mode == LeadOutMode::Mode2Form1 {
+            // `Sector::write_headers` defaults to Form 2 in the lead-out
+            let data = sector.data_2352_mut();
+            data[18] = 0x08;
+            data[22] = 0x08;
+
+            sector.write_edc_ecc();
+        }
+
+        Ok(sector)
+    }
+
+    /// Returns the Q subchannel data of the lead-out sector at `disc_msf`, see
+    /// `Toc::build_lead_out_sector`
+    pub fn build_lead_out_q(&self, disc_msf: Msf) -> CdResult<Q> {
+        self.lead_out_q_format(disc_msf).map(|(q, _)| q)
+    }
+
+    /// Returns the Q subchannel data and format of the lead-out sector at `disc_msf`
+    fn lead_out_q_format(&self, disc_msf: Msf) -> CdResult<(Q, TrackFormat)> {
         let los = self.lead_out_start();
 
         if disc_msf < los {
             return Err(CdError::InvalidLeadOutPosition);
         }
 
+        if disc_msf >= self.lead_out_end() {
+            return Err(CdError::OutOfDiscPosition);
+        }
+
         let lead_out_msf = disc_msf - los;
 
         // The green book says that:
@@ -121,18 +473,41 @@ impl Toc {
         //   the program area of the disc is a CD-I track, then it is recommended that the lead-out
         //   area is encoded as Mode 2 Form 2.
         //
-        // I generalize the concept here by always setting the format of the lead-out to the format
-        // of the last track.
+        // I generalize the concept here by setting the format of the lead-out to the format of
+        // the last track by default.
         let t = self.tracks.last().unwrap();
 
+        let format = self.lead_out_format();
+
+        // Keep the control bits of the last track unless we changed from audio to data or
+        // vice-versa
+        let control = if format.is_audio() == t.format.is_audio() {
+            t.control
+        } else if format.is_audio() {
+            AdrControl::AUDIO
+        } else {
+            AdrControl::DATA
+        };
+
         let qdata = QData::Mode1LeadOut {
             lead_out_msf,
             disc_msf,
         };
 
-        let q = Q::from_qdata_mode1(qdata, t.control);
+        let q = Q::from_qdata_mode1(qdata, control);
+
+        Ok((q, format))
+    }
+
+    /// Generate the lead-out sector located `offset` sectors after the start of the lead-out.
+    /// Returns an error if the resulting position would be past 99:59:74.
+    pub fn lead_out_sector(&self, offset: Msf) -> CdResult<Sector> {
+        let disc_msf = self
+            .lead_out_start()
+            .checked_add(offset)
+            .ok_or(CdError::OutOfDiscPosition)?;
 
-        Sector::empty(q, t.format)
+        self.build_lead_out_sector(disc_msf)
     }
 
     /// Returns the MSF of the first sector in the lead-out
@@ -142,12 +517,108 @@ impl Toc {
         t.start + t.length
     }
 
+    /// Returns the MSF of the first sector past the end of the lead-out, or `Msf::MAX` if the
+    /// lead-out extends to the end of the addressable range
+    pub fn lead_out_end(&self) -> Msf {
+        self.lead_out_start()
+            .checked_add(self.lead_out_length)
+            .unwrap_or(Msf::MAX)
+    }
+
+    /// Clamp `pos` to the range of positions that can be read from the disc:
+    ///
+    /// * Lead-in positions before `DiscPosition::INNERMOST` are moved up to it
+    /// * Program area positions before the start of the first track are moved up to it
+    /// * Positions past the end of the lead-out are moved down to the last sector of the lead-out
+    pub fn clamp_position(&self, pos: DiscPosition) -> DiscPosition {
+        match pos {
+            DiscPosition::LeadIn(_) => cmp::max(pos, DiscPosition::INNERMOST),
+            DiscPosition::Program(msf) => {
+                let first = self.tracks[0].start;
+                let last = self
+                    .lead_out_end()
+                    .sector_index()
+                    .checked_sub(1)
+                    .and_then(Msf::from_sector_index)
+                    .unwrap_or(first);
+
+                DiscPosition::Program(cmp::max(cmp::min(msf, last), first))
+            }
+        }
+    }
+
+    /// Returns the number of sectors in the lead-out
+    pub fn lead_out_length(&self) -> Msf {
+        self.lead_out_length
+    }
+
+    /// Set the number of sectors in the lead-out. Defaults to `Toc::DEFAULT_LEAD_OUT_LENGTH`.
+    pub fn set_lead_out_length(&mut self, length: Msf) {
+        self.lead_out_length = length;
+    }
+
+    /// Returns the format of the lead-out sectors, see `Toc::set_lead_out_mode`
+    pub fn lead_out_format(&self) -> TrackFormat {
+        let last = self.tracks.last().unwrap().format;
+
+        // CD-i discs use CD-i sectors, every other disc CD-ROM XA ones
+        let xa = if self.session_format() == SessionFormat::Cdi {
+            TrackFormat::Mode2CdI
+        } else {
+            TrackFormat::Mode2Xa
+        };
+
+        match self.lead_out_mode {
+            LeadOutMode::LastTrack => last,
+            LeadOutMode::Audio => TrackFormat::Audio,
+            LeadOutMode::Mode1 => TrackFormat::Mode1,
+            LeadOutMode::Mode2 => TrackFormat::Mode2,
+            LeadOutMode::Mode2Form1 | LeadOutMode::Mode2Form2 => match last {
+                TrackFormat::Mode2Xa | TrackFormat::Mode2CdI => last,
+                _ => xa,
+            },
+            LeadOutMode::Format(format) => format,
+        }
+    }
+
+    /// Set the format of the lead-out sectors. By default the format of the last track is used.
+    ///
+    /// This is a shorthand for `Toc::set_lead_out_mode` with `LeadOutMode::Format`, CD-ROM XA and
+    /// CD-i lead-out sectors are Form 2.
+    pub fn set_lead_out_format(&mut self, format: TrackFormat) {
+        self.set_lead_out_mode(LeadOutMode::Format(format));
+    }
+
+    /// Returns the contents of the lead-out sectors
+    pub fn lead_out_mode(&self) -> LeadOutMode {
+        self.lead_out_mode
+    }
+
+    /// Set the contents of the lead-out sectors generated by `Toc::build_lead_out_sector`.
+    /// Defaults to `LeadOutMode::LastTrack`.
+    pub fn set_lead_out_mode(&mut self, mode: LeadOutMode) {
+        self.lead_out_mode = mode;
+    }
+
+    /// Returns the approximate length in mm of the spiral recorded from the start of the lead-in to
+    /// the start of the lead-out, see `DiscPosition::track_length_mm`
+    pub fn estimated_recorded_length_mm(&self) -> CdResult<u32> {
+        DiscPosition::Program(self.lead_out_start()).track_length_mm()
+    }
+
+    /// Returns the approximate radius of the outer edge of the program area (the start of the
+    /// lead-out), see `DiscPosition::disc_radius`. This can be used to estimate the physical size
+    /// of the recorded area, a standard disc never goes past `CD_PROGRAM_RADIUS_MAX`.
+    pub fn estimated_outer_radius(&self) -> CdResult<Radius> {
+        DiscPosition::Program(self.lead_out_start()).disc_radius()
+    }
+
     /// Return the session format for this ToC based on the format of its tracks
     pub fn session_format(&self) -> SessionFormat {
         for t in self.tracks.iter() {
             match t.format {
                 TrackFormat::Audio => (),
-                TrackFormat::Mode1 => return SessionFormat::CdDaCdRom,
+                TrackFormat::Mode1 | TrackFormat::Mode2 => return SessionFormat::CdDaCdRom,
                 TrackFormat::Mode2Xa => return SessionFormat::CdXa,
                 TrackFormat::Mode2CdI => return SessionFormat::Cdi,
             }
@@ -156,6 +627,304 @@ impl Toc {
         // No "special" track found, it's probably a conventional CD
         SessionFormat::CdDaCdRom
     }
+
+    /// Classify the disc based on the format of its tracks and its sessions, see `DiscType`
+    pub fn disc_type(&self) -> DiscType {
+        let audio = self.tracks.iter().filter(|t| t.format.is_audio()).count();
+        let tracks = self.tracks.len();
+
+        if audio == tracks {
+            return DiscType::AudioCd;
+        }
+
+        if audio == 0 {
+            return DiscType::DataCd;
+        }
+
+        let single_session = self.sessions.iter().all(|&s| s == self.sessions[0]);
+
+        if single_session {
+            // Data track 1 followed by audio tracks
+            if !self.tracks[0].format.is_audio() && audio == tracks - 1 {
+                return DiscType::MixedMode;
+            }
+
+            return DiscType::Unknown;
+        }
+
+        // Audio session(s) followed by a last session containing only data
+        let last_session = self.sessions[tracks - 1];
+
+        let cd_extra = self
+            .tracks
+            .iter()
+            .zip(self.sessions.iter())
+            .all(|(t, &s)| t.format.is_audio() == (s != last_session));
+
+        if cd_extra {
+            DiscType::CdExtra
+        } else {
+            DiscType::Unknown
+        }
+    }
+
+    /// Returns the gap between every pair of adjacent tracks: the number of sectors between the
+    /// end of a track (`start + length`) and the start of the next one. A negative gap means that
+    /// the tracks overlap.
+    pub fn gaps(&self) -> Vec<TrackGap> {
+        self.tracks
+            .windows(2)
+            .map(|w| {
+                let end = w[0].start.sector_index() as i64 + w[0].length.sector_index() as i64;
+
+                TrackGap {
+                    before: w[0].track,
+                    after: w[1].track,
+                    gap: w[1].start.sector_index() as i64 - end,
+                }
+            })
+            .collect()
+    }
+
+    /// Compare this ToC with `other` and return the list of differences. Tracks are paired by
+    /// number, tracks only present in one of the ToCs are only reported through
+    /// `TocDifference::TrackCount`. An empty list means that both ToCs are identical.
+    pub fn diff(&self, other: &Toc) -> Vec<TocDifference> {
+        let mut diffs = Vec::new();
+
+        if self.tracks.len() != other.tracks.len() {
+            diffs.push(TocDifference::TrackCount {
+                ours: self.tracks.len(),
+                theirs: other.tracks.len(),
+            });
+        }
+
+        for ours in self.tracks.iter() {
+            let theirs = match other.tracks.iter().find(|t| t.track == ours.track) {
+                Some(t) => t,
+                None => continue,
+            };
+
+            let track = ours.track;
+
+            if ours.start != theirs.start {
+                diffs.push(TocDifference::Start {
+                    track,
+                    ours: ours.start,
+                    theirs: theirs.start,
+                });
+            }
+
+            if ours.length != theirs.length {
+                diffs.push(TocDifference::Length {
+                    track,
+                    ours: ours.length,
+                    theirs: theirs.length,
+                });
+            }
+
+            if ours.format != theirs.format {
+                diffs.push(TocDifference::Format {
+                    track,
+                    ours: ours.format,
+                    theirs: theirs.format,
+                });
+            }
+
+            if ours.control != theirs.control {
+                diffs.push(TocDifference::Control {
+                    track,
+                    ours: ours.control,
+                    theirs: theirs.control,
+                });
+            }
+        }
+
+        let (ours, theirs) = (self.lead_out_start(), other.lead_out_start());
+
+        if ours != theirs {
+            diffs.push(TocDifference::LeadOut { ours, theirs });
+        }
+
+        diffs
+    }
+
+    /// Build the response to a SCSI/ATAPI `READ TOC/PMA/ATIP` command as described in the MMC
+    /// specification, including the 4-byte header. The first two bytes of the header contain the
+    /// length of the rest of the response, big-endian.
+    ///
+    /// The track descriptors always start with the first track of the disc, callers emulating a
+    /// drive have to truncate the response to the allocation length requested by the host.
+    pub fn to_mmc_toc(&self, format: MmcTocFormat) -> Vec<u8> {
+        let mut toc = vec![0; 4];
+
+        match format {
+            MmcTocFormat::Toc { msf } => {
+                toc[2] = self.tracks[0].track.binary();
+                toc[3] = self.tracks[self.tracks.len() - 1].track.binary();
+
+                for t in self.tracks.iter() {
+                    mmc_track_descriptor(&mut toc, t.control, t.track.binary(), t.start, msf);
+                }
+
+                // The lead-out uses the control bits of the last track
+                let last = &self.tracks[self.tracks.len() - 1];
+
+                mmc_track_descriptor(&mut toc, last.control, 0xaa, self.lead_out_start(), msf);
+            }
+            MmcTocFormat::SessionInfo { msf } => {
+                let last_session = self.sessions.iter().cloned().max().unwrap_or(1);
+
+                toc[2] = 1;
+                toc[3] = last_session;
+
+                // Descriptor for the first track of the last session
+                let pos = self
+                    .sessions
+                    .iter()
+                    .position(|&s| s == last_session)
+                    .unwrap_or(0);
+                let t = &self.tracks[pos];
+
+                mmc_track_descriptor(&mut toc, t.control, t.track.binary(), t.start, msf);
+            }
+        }
+
+        let len = (toc.len() - 2) as u16;
+
+        toc[..2].copy_from_slice(&len.to_be_bytes());
+
+        toc
+    }
+}
+
+/// Append an 8-byte MMC track descriptor to `toc`. The address is in binary MSF if `msf` is true,
+/// otherwise in big-endian LBA
+fn mmc_track_descriptor(toc: &mut Vec<u8>, control: AdrControl, track: u8, start: Msf, msf: bool) {
+    // The ADR is always 1 (Q mode 1) and is stored in the high nibble, unlike in the Q subchannel
+    toc.extend_from_slice(&[0, 0x10 | control.control(), track, 0]);
+
+    if msf {
+        toc.extend_from_slice(&[0, start.minutes(), start.seconds(), start.frames()]);
+    } else {
+        let lba = start.sector_index() as i32 - 150;
+
+        toc.extend_from_slice(&lba.to_be_bytes());
+    }
+}
+
+/// Format of the response built by `Toc::to_mmc_toc`, corresponding to the "Format" field of the
+/// MMC `READ TOC/PMA/ATIP` command. `msf` corresponds to the MSF bit of the command: if it's set
+/// the addresses are returned as binary MSF, otherwise as LBA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MmcTocFormat {
+    /// Format 0000b: one descriptor per track followed by the lead-out (track 0xAA)
+    Toc {
+        /// Return addresses as MSF instead of LBA
+        msf: bool,
+    },
+    /// Format 0001b: first and last session numbers followed by the descriptor of the first track
+    /// of the last session
+    SessionInfo {
+        /// Return addresses as MSF instead of LBA
+        msf: bool,
+    },
+}
+
+/// Gap between two adjacent tracks, as returned by `Toc::gaps`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrackGap {
+    /// Number of the first track
+    pub before: Bcd,
+    /// Number of the track following `before`
+    pub after: Bcd,
+    /// Number of sectors between the end of `before` and the start of `after`, negative if the
+    /// tracks overlap
+    pub gap: i64,
+}
+
+impl TrackGap {
+    /// Returns true if the tracks overlap
+    pub fn is_overlap(&self) -> bool {
+        self.gap < 0
+    }
+}
+
+/// Contents of the lead-out sectors generated by `Toc::build_lead_out_sector`, see
+/// `Toc::set_lead_out_mode`
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LeadOutMode {
+    /// Use the format of the last track, as recommended by the Green Book. If it's a CD-ROM XA or
+    /// CD-i track the lead-out sectors are Mode 2 Form 2. This is the default.
+    #[default]
+    LastTrack,
+    /// Digital silence
+    Audio,
+    /// Empty Mode 1 sectors
+    Mode1,
+    /// Empty plain (formless) Mode 2 sectors
+    Mode2,
+    /// Empty Mode 2 Form 1 sectors. They are CD-i sectors if the disc is a CD-i, CD-ROM XA ones
+    /// otherwise.
+    Mode2Form1,
+    /// Empty Mode 2 Form 2 sectors. They are CD-i sectors if the disc is a CD-i, CD-ROM XA ones
+    /// otherwise.
+    Mode2Form2,
+    /// Empty sectors of the given format, set by `Toc::set_lead_out_format`. CD-ROM XA and CD-i
+    /// sectors are Form 2.
+    Format(TrackFormat),
+}
+
+/// General layout of a disc, as returned by `Toc::disc_type`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiscType {
+    /// Audio tracks only (CD-DA)
+    AudioCd,
+    /// Data tracks only (CD-ROM, CD-ROM XA, CD-i...)
+    DataCd,
+    /// Single session disc with a data track 1 followed by audio tracks, common for video games
+    MixedMode,
+    /// Multisession disc with audio tracks in the first session(s) followed by a session
+    /// containing only data tracks, so that regular CD players only see the audio (also known as
+    /// Enhanced CD or CD Plus)
+    CdExtra,
+    /// Any other combination of audio and data tracks
+    Unknown,
+}
+
+/// A single difference between two ToCs, as returned by `Toc::diff`. `ours` is the value in the
+/// ToC `diff` was called on, `theirs` the value in the ToC it was compared with.
+#[allow(missing_docs)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TocDifference {
+    /// The ToCs don't have the same number of tracks
+    TrackCount { ours: usize, theirs: usize },
+    /// The track doesn't start at the same position
+    Start { track: Bcd, ours: Msf, theirs: Msf },
+    /// The track doesn't have the same length
+    Length { track: Bcd, ours: Msf, theirs: Msf },
+    /// The track doesn't have the same format
+    Format {
+        track: Bcd,
+        ours: TrackFormat,
+        theirs: TrackFormat,
+    },
+    /// The track doesn't have the same control bits
+    Control {
+        track: Bcd,
+        ours: AdrControl,
+        theirs: AdrControl,
+    },
+    /// The lead-out doesn't start at the same position
+    LeadOut { ours: Msf, theirs: Msf },
+}
+
+/// Build a ToC with `tracks.collect::<CdResult<Toc>>()`, see `Toc::try_from_iter`
+impl FromIterator<Track> for CdResult<Toc> {
+    fn from_iter<I: IntoIterator<Item = Track>>(tracks: I) -> CdResult<Toc> {
+        Toc::try_from_iter(tracks)
+    }
 }
 
 impl fmt::Debug for Toc {
@@ -180,6 +949,25 @@ impl fmt::Debug for Toc {
     }
 }
 
+/// Human-friendly listing of the tracks, grouped by session
+impl fmt::Display for Toc {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        // Sessions are numbered from 1, 0 can't match
+        let mut session = 0;
+
+        for (t, &s) in self.tracks.iter().zip(self.sessions.iter()) {
+            if s != session {
+                writeln!(fmt, "Session {:02}", s)?;
+                session = s;
+            }
+
+            writeln!(fmt, "  {}", t)?;
+        }
+
+        writeln!(fmt, "Lead-out {}", self.lead_out_start())
+    }
+}
+
 #[cfg(test)]
 fn ridgeracer_toc() -> Toc {
     let tracks = [
@@ -213,7 +1001,7 @@ fn ridgeracer_toc() -> Toc {
             let start = start.parse().unwrap();
             let length = length.parse().unwrap();
 
-            let mut control = if format.is_audio() {
+            let control = if format.is_audio() {
                 ::subchannel::AdrControl::AUDIO
             } else {
                 ::subchannel::AdrControl::DATA
@@ -273,6 +1061,303 @@ fn lead_out_generation() {
     }
 }
 
+#[test]
+fn clamp_position() {
+    let mut toc = ridgeracer_toc();
+    toc.set_lead_out_length(Msf::from_sector_index(100).unwrap());
+
+    let first = DiscPosition::Program(toc.tracks()[0].start);
+    let last = DiscPosition::Program(toc.lead_out_end() - Msf::from_sector_index(1).unwrap());
+
+    for &(pos, expected) in &[
+        (DiscPosition::Program(Msf::ZERO), first),
+        (first, first),
+        (last, last),
+        (
+            DiscPosition::Program(toc.lead_out_start()),
+            DiscPosition::Program(toc.lead_out_start()),
+        ),
+        (DiscPosition::Program(toc.lead_out_end()), last),
+        (DiscPosition::Program(Msf::MAX), last),
+        (DiscPosition::LeadIn(Msf::ZERO), DiscPosition::INNERMOST),
+        (DiscPosition::INNERMOST, DiscPosition::INNERMOST),
+        (
+            DiscPosition::LeadIn(Msf::MAX),
+            DiscPosition::LeadIn(Msf::MAX),
+        ),
+    ] {
+        assert_eq!(toc.clamp_position(pos), expected);
+    }
+}
+
+#[test]
+fn from_tracks() {
+    use subchannel::AdrControl;
+
+    let track = |n: u8, start: &str, length: &str, format: TrackFormat| Track {
+        track: Bcd::from_binary(n).unwrap(),
+        format,
+        start: start.parse().unwrap(),
+        length: length.parse().unwrap(),
+        control: if format.is_audio() {
+            AdrControl::AUDIO
+        } else {
+            AdrControl::DATA
+        },
+    };
+
+    let tracks = vec![
+        track(1, "00:02:00", "01:00:00", TrackFormat::Mode1),
+        track(2, "01:04:00", "00:30:00", TrackFormat::Audio),
+    ];
+
+    let toc = Toc::from_tracks(tracks.clone(), "01:34:00".parse().unwrap()).unwrap();
+
+    assert_eq!(toc.tracks().len(), 2);
+    assert_eq!(toc.lead_out_start(), "01:34:00".parse().unwrap());
+    assert_eq!(
+        toc.track(Bcd::from_binary(2).unwrap()).unwrap().format,
+        TrackFormat::Audio
+    );
+
+    // Lead-out not following the last track
+    assert!(matches!(
+        Toc::from_tracks(tracks, "01:35:00".parse().unwrap()),
+        Err(CdError::BadImage { .. })
+    ));
+
+    assert!(matches!(
+        Toc::from_tracks(Vec::new(), Msf::ZERO),
+        Err(CdError::EmptyToc)
+    ));
+
+    for tracks in [
+        // Overlapping tracks
+        vec![
+            track(1, "00:02:00", "01:00:00", TrackFormat::Mode1),
+            track(2, "01:01:00", "00:33:00", TrackFormat::Audio),
+        ],
+        // Bad numbering
+        vec![
+            track(1, "00:02:00", "01:00:00", TrackFormat::Mode1),
+            track(3, "01:02:00", "00:32:00", TrackFormat::Audio),
+        ],
+        // Control doesn't match the format
+        vec![Track {
+            control: AdrControl::AUDIO,
+            ..track(1, "00:02:00", "01:32:00", TrackFormat::Mode1)
+        }],
+    ] {
+        assert!(matches!(
+            Toc::from_tracks(tracks, "01:34:00".parse().unwrap()),
+            Err(CdError::BadImage { .. })
+        ));
+    }
+}
+
+#[test]
+fn collect_tracks() {
+    let toc = ridgeracer_toc();
+
+    let collected: Toc = toc
+        .tracks()
+        .iter()
+        .cloned()
+        .collect::<CdResult<_>>()
+        .unwrap();
+
+    assert!(collected.tracks() == toc.tracks());
+    assert_eq!(collected.lead_out_start(), toc.lead_out_start());
+
+    let tracks = toc.tracks().iter().take(3).cloned();
+    let collected = Toc::try_from_iter(tracks).unwrap();
+
+    assert!(collected.tracks() == &toc.tracks()[..3]);
+    assert_eq!(
+        collected.lead_out_start(),
+        toc.tracks()[2].start + toc.tracks()[2].length
+    );
+
+    // Tracks must be in order
+    let reversed = toc.tracks().iter().rev().cloned();
+
+    assert!(matches!(
+        reversed.collect::<CdResult<Toc>>(),
+        Err(CdError::BadImage { .. })
+    ));
+
+    assert!(matches!(
+        Toc::try_from_iter(Vec::new()),
+        Err(CdError::EmptyToc)
+    ));
+}
+
+#[test]
+fn lead_out_offset() {
+    let toc = ridgeracer_toc();
+    let los = toc.lead_out_start();
+
+    assert_eq!(los, "69:48:74".parse().unwrap());
+
+    for off in 0..2 {
+        let offset = Msf::from_sector_index(off).unwrap();
+        let s = toc.lead_out_sector(offset).unwrap();
+
+        assert!(s.q().is_lead_out());
+        assert_eq!(s.q().amsf(), los + offset);
+
+        let expected = toc.build_lead_out_sector(los + offset).unwrap();
+        assert_eq!(s.q(), expected.q());
+        assert_eq!(s.data_2352()[..], expected.data_2352()[..]);
+    }
+
+    assert!(toc.lead_out_sector(Msf::MAX).is_err());
+    assert!(toc.build_lead_out_sector(los - Msf::from_sector_index(1).unwrap()).is_err());
+}
+
+#[test]
+fn lead_out_length() {
+    let mut toc = ridgeracer_toc();
+    let los = toc.lead_out_start();
+    let one = Msf::from_sector_index(1).unwrap();
+
+    assert_eq!(toc.lead_out_length(), "01:30:00".parse().unwrap());
+    assert_eq!(toc.lead_out_end(), "71:18:74".parse().unwrap());
+
+    assert!(toc.build_lead_out_sector(toc.lead_out_end() - one).is_ok());
+    assert!(matches!(
+        toc.build_lead_out_sector(toc.lead_out_end()),
+        Err(CdError::OutOfDiscPosition)
+    ));
+
+    toc.set_lead_out_length(Msf::from_sector_index(10).unwrap());
+    assert_eq!(
+        toc.lead_out_end(),
+        los + Msf::from_sector_index(10).unwrap()
+    );
+
+    for off in 0..10 {
+        assert!(toc
+            .lead_out_sector(Msf::from_sector_index(off).unwrap())
+            .is_ok());
+    }
+    assert!(matches!(
+        toc.lead_out_sector(Msf::from_sector_index(10).unwrap()),
+        Err(CdError::OutOfDiscPosition)
+    ));
+
+    // The last track is audio, use a Mode 2 Form 2 lead-out instead
+    assert_eq!(toc.lead_out_format(), TrackFormat::Audio);
+    toc.set_lead_out_format(TrackFormat::Mode2Xa);
+
+    let s = toc.lead_out_sector(Msf::ZERO).unwrap();
+    assert_eq!(s.format(), TrackFormat::Mode2Xa);
+    assert!(s.q().is_data());
+    assert_eq!(
+        s.mode2_xa_subheader().unwrap().submode().form(),
+        XaForm::Form2
+    );
+    assert!(s.edc_valid());
+}
+
+#[test]
+fn lead_out_mode() {
+    use sector::{CdRomMode, XaSubmode};
+
+    let mut toc = ridgeracer_toc();
+
+    // The last track is audio
+    assert_eq!(toc.lead_out_mode(), LeadOutMode::LastTrack);
+    let s = toc.lead_out_sector(Msf::ZERO).unwrap();
+    assert_eq!(s.format(), TrackFormat::Audio);
+    assert!(s.cdrom_header().is_err());
+
+    toc.set_lead_out_mode(LeadOutMode::Mode1);
+    let s = toc.lead_out_sector(Msf::ZERO).unwrap();
+    assert_eq!(s.format(), TrackFormat::Mode1);
+    assert!(s.cdrom_header().unwrap().mode == CdRomMode::Mode1);
+    assert!(s.validate_position().is_ok());
+    assert!(s.edc_valid());
+
+    toc.set_lead_out_mode(LeadOutMode::Mode2);
+    let s = toc.lead_out_sector(Msf::ZERO).unwrap();
+    assert_eq!(s.format(), TrackFormat::Mode2);
+    assert!(s.cdrom_header().unwrap().mode == CdRomMode::Mode2);
+
+    for &(mode, form) in &[
+        (LeadOutMode::Mode2Form1, XaForm::Form1),
+        (LeadOutMode::Mode2Form2, XaForm::Form2),
+    ] {
+        toc.set_lead_out_mode(mode);
+
+        let s = toc
+            .lead_out_sector(Msf::from_sector_index(3).unwrap())
+            .unwrap();
+        assert_eq!(s.format(), TrackFormat::Mode2Xa);
+        assert!(s.cdrom_header().unwrap().mode == CdRomMode::Mode2);
+        assert!(s.q().is_data());
+
+        let subheader = s.mode2_xa_subheader().unwrap();
+        assert!(subheader.copies_agree());
+        assert!(subheader.submode().data());
+        assert_eq!(subheader.submode().form(), form);
+        assert!(s.edc_valid());
+    }
+
+    toc.set_lead_out_mode(LeadOutMode::Audio);
+    let s = toc.lead_out_sector(Msf::ZERO).unwrap();
+    assert_eq!(s.format(), TrackFormat::Audio);
+    assert!(!s.q().is_data());
+
+    // The shorthand keeps the exact format and picks Form 2 for XA and CD-i
+    for &format in &[TrackFormat::Mode2Xa, TrackFormat::Mode2CdI] {
+        toc.set_lead_out_format(format);
+        assert_eq!(toc.lead_out_mode(), LeadOutMode::Format(format));
+        assert_eq!(toc.lead_out_format(), format);
+
+        let s = toc.lead_out_sector(Msf::ZERO).unwrap();
+        assert_eq!(s.format(), format);
+        assert_eq!(XaSubmode(s.data_2352()[18]).form(), XaForm::Form2);
+    }
+
+    toc.set_lead_out_format(TrackFormat::Mode1);
+    assert_eq!(toc.lead_out_format(), TrackFormat::Mode1);
+}
+
+#[test]
+fn lead_in_cadence() {
+    let toc = ridgeracer_toc();
+    let ntracks = toc.tracks().len() as u32;
+
+    // 3 repetitions of every track entry plus A0, A1 and A2
+    let cycle = 3 * (ntracks + 3);
+
+    let point = |index: u32| {
+        let msf = Msf::MAX - Msf::from_sector_index(index).unwrap();
+        let s = toc.build_toc_sector(msf).unwrap();
+
+        assert!(s.q().is_lead_in());
+
+        s.q().to_raw()[2]
+    };
+
+    // Walk back two full cycles from the end of the lead-in
+    let points: Vec<u8> = (0..cycle * 2).rev().map(point).collect();
+
+    let mut expected = Vec::new();
+    for _ in 0..2 {
+        for p in [0xa0, 0xa1, 0xa2]
+            .iter()
+            .cloned()
+            .chain(toc.tracks().iter().map(|t| t.track.bcd()))
+        {
+            expected.extend_from_slice(&[p, p, p]);
+        }
+    }
+
+    assert_eq!(points, expected);
+}
+
 #[test]
 fn lead_in_generation() {
     use DiscPosition;
@@ -547,3 +1632,400 @@ fn lead_in_generation() {
         p = p.next().unwrap();
     }
 }
+
+#[test]
+fn diff() {
+    let toc = ridgeracer_toc();
+
+    assert!(toc.diff(&toc.clone()).is_empty());
+
+    let mut tracks = toc.tracks().to_vec();
+    let old_len = tracks[3].length;
+    let new_len = old_len - Msf::from_sector_index(1).unwrap();
+    tracks[3].length = new_len;
+    let other = Toc::new(tracks).unwrap();
+
+    assert_eq!(
+        toc.diff(&other),
+        vec![TocDifference::Length {
+            track: Bcd::from_binary(4).unwrap(),
+            ours: old_len,
+            theirs: new_len,
+        }]
+    );
+
+    // Drop the last track
+    let mut tracks = toc.tracks().to_vec();
+    tracks.pop();
+    let other = Toc::new(tracks).unwrap();
+
+    assert_eq!(
+        other.diff(&toc),
+        vec![
+            TocDifference::TrackCount {
+                ours: toc.tracks().len() - 1,
+                theirs: toc.tracks().len(),
+            },
+            TocDifference::LeadOut {
+                ours: other.lead_out_start(),
+                theirs: toc.lead_out_start(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn display() {
+    let track = |n, format, start: &str, length: &str| {
+        let control = if format == TrackFormat::Audio {
+            AdrControl::AUDIO
+        } else {
+            AdrControl::DATA
+        };
+
+        Track {
+            track: Bcd::from_binary(n).unwrap(),
+            format,
+            start: start.parse().unwrap(),
+            length: length.parse().unwrap(),
+            control,
+        }
+    };
+
+    let tracks = vec![
+        track(1, TrackFormat::Audio, "00:02:00", "00:30:00"),
+        track(2, TrackFormat::Audio, "00:32:00", "03:14:10"),
+        track(3, TrackFormat::Mode2Xa, "03:46:10", "10:00:00"),
+    ];
+
+    let mut toc = Toc::from_tracks(tracks, "13:46:10".parse().unwrap()).unwrap();
+
+    assert_eq!(
+        toc.tracks()[1].to_string(),
+        "Track 02 [AUDIO] 00:32:00 +03:14:10"
+    );
+
+    assert_eq!(
+        toc.to_string(),
+        "Session 01\n\
+         \x20 Track 01 [AUDIO] 00:02:00 +00:30:00\n\
+         \x20 Track 02 [AUDIO] 00:32:00 +03:14:10\n\
+         \x20 Track 03 [MODE2/XA] 03:46:10 +10:00:00\n\
+         Lead-out 13:46:10\n"
+    );
+
+    toc.set_sessions(vec![1, 1, 2]);
+
+    assert_eq!(
+        toc.to_string(),
+        "Session 01\n\
+         \x20 Track 01 [AUDIO] 00:02:00 +00:30:00\n\
+         \x20 Track 02 [AUDIO] 00:32:00 +03:14:10\n\
+         Session 02\n\
+         \x20 Track 03 [MODE2/XA] 03:46:10 +10:00:00\n\
+         Lead-out 13:46:10\n"
+    );
+}
+
+#[test]
+fn tracks_in_range() {
+    let toc = ridgeracer_toc();
+
+    let bcd = |b| Bcd::from_binary(b).unwrap();
+    let numbers = |first, last| {
+        toc.tracks_in_range(bcd(first), bcd(last))
+            .map(|t| t.track.binary())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(numbers(2, 4), vec![2, 3, 4]);
+    assert_eq!(numbers(3, 3), vec![3]);
+    assert_eq!(numbers(4, 2), Vec::<u8>::new());
+    assert_eq!(numbers(0, 2), vec![1, 2]);
+    assert_eq!(numbers(18, 99), vec![18, 19, 20]);
+    assert_eq!(numbers(21, 99), Vec::<u8>::new());
+}
+
+#[test]
+fn mmc_toc() {
+    let mut toc = ridgeracer_toc();
+
+    let response = toc.to_mmc_toc(MmcTocFormat::Toc { msf: false });
+
+    assert_eq!(response.len(), 4 + 8 * 21);
+    assert_eq!(
+        u16::from_be_bytes([response[0], response[1]]) as usize,
+        response.len() - 2
+    );
+    assert_eq!(response[2..4], [1, 20]);
+
+    let descriptors = response[4..].chunks(8).collect::<Vec<_>>();
+
+    for (t, d) in toc.tracks().iter().zip(descriptors.iter()) {
+        let lba = i32::from_be_bytes([d[4], d[5], d[6], d[7]]);
+
+        assert_eq!(d[1] >> 4, 1);
+        assert_eq!(d[1] & 0x4 != 0, t.control.is_data());
+        assert_eq!(d[2], t.track.binary());
+        assert_eq!(lba, t.start.sector_index() as i32 - 150);
+    }
+
+    // Track 1 is data, the others audio
+    assert_eq!(descriptors[0][..4], [0, 0x14, 1, 0]);
+    assert_eq!(descriptors[0][4..], [0, 0, 0, 0]);
+    assert_eq!(descriptors[1][..4], [0, 0x10, 2, 0]);
+
+    let lead_out = descriptors[20];
+    assert_eq!(lead_out[2], 0xaa);
+    assert_eq!(
+        i32::from_be_bytes([lead_out[4], lead_out[5], lead_out[6], lead_out[7]]),
+        toc.lead_out_start().sector_index() as i32 - 150
+    );
+
+    let response = toc.to_mmc_toc(MmcTocFormat::Toc { msf: true });
+    let los = toc.lead_out_start();
+
+    assert_eq!(response.len(), 4 + 8 * 21);
+    // Track 2 starts at 01:06:51
+    assert_eq!(response[12..20], [0, 0x10, 2, 0, 0, 1, 6, 51]);
+    assert_eq!(
+        response[response.len() - 4..],
+        [0, los.minutes(), los.seconds(), los.frames()]
+    );
+
+    assert_eq!(
+        toc.to_mmc_toc(MmcTocFormat::SessionInfo { msf: true }),
+        vec![0, 10, 1, 1, 0, 0x14, 1, 0, 0, 0, 2, 0]
+    );
+
+    let mut sessions = vec![1; 20];
+    sessions[15..].iter_mut().for_each(|s| *s = 2);
+    toc.set_sessions(sessions);
+
+    let t16 = toc.tracks()[15].start;
+
+    assert_eq!(
+        toc.to_mmc_toc(MmcTocFormat::SessionInfo { msf: false }),
+        [
+            &[0, 10, 1, 2, 0, 0x10, 16, 0][..],
+            &(t16.sector_index() - 150).to_be_bytes()
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn disc_type() {
+    use self::TrackFormat::{Audio, Mode1, Mode2Xa};
+
+    fn toc(formats: &[TrackFormat], sessions: &[u8]) -> Toc {
+        let mut start = Msf::from_sector_index(150).unwrap();
+        let length = Msf::from_sector_index(1000).unwrap();
+
+        let tracks: Vec<_> = formats
+            .iter()
+            .enumerate()
+            .map(|(i, &format)| {
+                let t = Track {
+                    track: Bcd::from_binary(i as u8 + 1).unwrap(),
+                    format,
+                    start,
+                    length,
+                    control: if format.is_audio() {
+                        AdrControl::AUDIO
+                    } else {
+                        AdrControl::DATA
+                    },
+                };
+
+                start += length;
+
+                t
+            })
+            .collect();
+
+        let mut toc = Toc::from_tracks(tracks, start).unwrap();
+
+        toc.set_sessions(sessions.to_vec());
+
+        toc
+    }
+
+    for &(formats, sessions, expected) in &[
+        (
+            &[Audio, Audio, Audio][..],
+            &[1, 1, 1][..],
+            DiscType::AudioCd,
+        ),
+        (&[Mode1], &[1], DiscType::DataCd),
+        (&[Mode2Xa, Mode2Xa], &[1, 2], DiscType::DataCd),
+        (&[Mode1, Audio, Audio], &[1, 1, 1], DiscType::MixedMode),
+        (&[Mode2Xa, Audio], &[1, 1], DiscType::MixedMode),
+        (&[Audio, Audio, Mode2Xa], &[1, 1, 2], DiscType::CdExtra),
+        (
+            &[Audio, Audio, Mode1, Mode1],
+            &[1, 1, 2, 2],
+            DiscType::CdExtra,
+        ),
+        // Without session information the data track can't be told apart from a bogus layout
+        (&[Audio, Audio, Mode2Xa], &[1, 1, 1], DiscType::Unknown),
+        (&[Mode1, Audio, Mode1], &[1, 1, 1], DiscType::Unknown),
+        (&[Audio, Mode1, Audio], &[1, 2, 2], DiscType::Unknown),
+        (&[Mode1, Audio], &[1, 2], DiscType::Unknown),
+    ] {
+        assert_eq!(
+            toc(formats, sessions).disc_type(),
+            expected,
+            "{:?}",
+            formats
+        );
+    }
+
+    assert_eq!(ridgeracer_toc().disc_type(), DiscType::MixedMode);
+}
+
+#[test]
+fn from_lead_in_q() {
+    use subchannel::RIDGE_RACER_LEAD_IN;
+
+    let expected = ridgeracer_toc();
+
+    let toc = Toc::from_lead_in_q(RIDGE_RACER_LEAD_IN.iter().cloned()).unwrap();
+
+    assert_eq!(toc.tracks().len(), 20);
+    assert_eq!(toc.lead_out_start(), "69:48:74".parse().unwrap());
+    assert!(toc
+        .diff(&expected)
+        .iter()
+        .all(|d| matches!(d, TocDifference::Length { .. })));
+
+    for (t, e) in toc.tracks().iter().zip(expected.tracks()) {
+        assert!(t.format == e.format && t.control == e.control);
+    }
+
+    // The pregaps are included in the previous track
+    assert_eq!(toc.tracks()[0].length, "01:04:51".parse().unwrap());
+    assert_eq!(toc.tracks()[19].length, "03:06:25".parse().unwrap());
+
+    // Round-trip through the lead-in generator, corrupting a few frames along the way
+    let cycle = 3 * (expected.tracks().len() as u32 + 3);
+
+    let frames = (0..cycle).map(|index| {
+        let msf = Msf::MAX - Msf::from_sector_index(index).unwrap();
+        let mut raw = expected.build_toc_q(msf).to_raw();
+
+        if index % 3 == 0 {
+            raw[7] ^= 0x10;
+        }
+
+        raw
+    });
+
+    assert!(Toc::from_lead_in_q(frames).unwrap() == toc);
+
+    // Without the entry for track 5
+    let frames = RIDGE_RACER_LEAD_IN
+        .iter()
+        .cloned()
+        .filter(|raw| raw[2] != 0x05);
+
+    assert!(matches!(
+        Toc::from_lead_in_q(frames),
+        Err(CdError::BadImage { .. })
+    ));
+
+    assert!(matches!(
+        Toc::from_lead_in_q(Vec::new()),
+        Err(CdError::BadImage { .. })
+    ));
+}
+
+#[test]
+fn gaps() {
+    let toc = ridgeracer_toc();
+
+    let gaps = toc.gaps();
+
+    assert_eq!(gaps.len(), 19);
+    assert!(gaps.iter().all(|g| !g.is_overlap()));
+
+    let two = Bcd::from_binary(2).unwrap();
+
+    // Standard 2 second pregap not included in the previous track
+    assert_eq!(
+        gaps[0],
+        TrackGap {
+            before: Bcd::ONE,
+            after: two,
+            gap: 150,
+        }
+    );
+    assert!(gaps.iter().all(|g| g.gap == 150));
+
+    let mut tracks = toc.tracks().to_vec();
+
+    // Extend track 2 past the start of track 3
+    tracks[1].length = "00:09:22".parse().unwrap();
+
+    let toc = Toc::new(tracks).unwrap();
+    let gaps = toc.gaps();
+
+    assert_eq!(gaps[0].gap, 150);
+    assert_eq!(gaps[1].before, two);
+    assert_eq!(gaps[1].gap, -10);
+    assert!(gaps[1].is_overlap());
+    assert_eq!(gaps.iter().filter(|g| g.is_overlap()).count(), 1);
+}
+
+#[test]
+fn estimated_outer_radius() {
+    use disc_position::CD_PROGRAM_RADIUS_MAX;
+
+    let track = |length: &str| Track {
+        track: Bcd::ONE,
+        format: TrackFormat::Audio,
+        start: "00:02:00".parse().unwrap(),
+        length: length.parse().unwrap(),
+        control: AdrControl::AUDIO,
+    };
+
+    let short = Toc::new(vec![track("10:00:00")]).unwrap();
+    let sixty = Toc::new(vec![track("60:00:00")]).unwrap();
+
+    let r = sixty.estimated_outer_radius().unwrap();
+
+    assert!(r < CD_PROGRAM_RADIUS_MAX);
+    assert!(r > Radius::from_millis(50.));
+    assert!(short.estimated_outer_radius().unwrap() < r);
+
+    // The lead-out is 16mm further than the last sector of the track
+    let length = sixty.estimated_recorded_length_mm().unwrap();
+    let last = DiscPosition::Program("60:01:74".parse().unwrap());
+    assert_eq!(length, last.track_length_mm().unwrap() + 16);
+
+    // 1.2m/s for an hour, plus the lead-in
+    assert!(length > 4_320_000 && length < 4_600_000);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn deserialize_without_sessions() {
+    let toc = ridgeracer_toc();
+
+    let repr = TocRepr {
+        tracks: toc.tracks().to_vec(),
+        lead_out_length: Toc::default_lead_out_length(),
+        lead_out_mode: LeadOutMode::default(),
+        sessions: Vec::new(),
+    };
+
+    let deserialized = Toc::from(repr);
+
+    assert!(deserialized == toc);
+    assert_eq!(deserialized.disc_type(), toc.disc_type());
+    assert_eq!(deserialized.to_string(), toc.to_string());
+    assert_eq!(
+        deserialized.to_string().lines().count(),
+        2 + toc.tracks().len()
+    );
+}
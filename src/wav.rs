@@ -0,0 +1,89 @@
+//! Export of audio tracks to WAV files
+
+use std::io::Write;
+
+use msf::MsfRange;
+use sector::deemphasis::DeEmphasis;
+use {Bcd, CdError, CdResult, DiscPosition, GapPlacement, Image};
+
+/// Size of the header written before the PCM data
+pub const HEADER_SIZE: usize = 44;
+
+/// CD-DA sampling frequency
+const SAMPLE_RATE: u32 = 44_100;
+
+/// Size in bytes of a single stereo 16-bit sample
+const BLOCK_ALIGN: u16 = 4;
+
+/// Build the header of a 44.1kHz, 16-bit stereo PCM WAV file containing `data_len` bytes of
+/// samples
+pub fn header(data_len: u32) -> [u8; HEADER_SIZE] {
+    let mut h = [0; HEADER_SIZE];
+
+    h[0..4].copy_from_slice(b"RIFF");
+    h[4..8].copy_from_slice(&(data_len + HEADER_SIZE as u32 - 8).to_le_bytes());
+    h[8..12].copy_from_slice(b"WAVE");
+
+    h[12..16].copy_from_slice(b"fmt ");
+    // Size of the fmt chunk
+    h[16..20].copy_from_slice(&16u32.to_le_bytes());
+    // PCM
+    h[20..22].copy_from_slice(&1u16.to_le_bytes());
+    // Stereo
+    h[22..24].copy_from_slice(&2u16.to_le_bytes());
+    h[24..28].copy_from_slice(&SAMPLE_RATE.to_le_bytes());
+    h[28..32].copy_from_slice(&(SAMPLE_RATE * u32::from(BLOCK_ALIGN)).to_le_bytes());
+    h[32..34].copy_from_slice(&BLOCK_ALIGN.to_le_bytes());
+    // Bits per sample
+    h[34..36].copy_from_slice(&16u16.to_le_bytes());
+
+    h[36..40].copy_from_slice(b"data");
+    h[40..44].copy_from_slice(&data_len.to_le_bytes());
+
+    h
+}
+
+/// Options for `export_track`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct WavOptions {
+    /// Where the gaps between the tracks are placed, see `Image::track_range`. Defaults to
+    /// `GapPlacement::Omit`.
+    pub gap_placement: GapPlacement,
+    /// If true the sectors with the pre-emphasis flag set are filtered. Defaults to false.
+    pub de_emphasis: bool,
+}
+
+/// Write `track` to `out` as a WAV file. The sectors exported are the ones returned by
+/// `Image::track_range` for `options.gap_placement`.
+///
+/// Returns `CdError::BadFormat` if `track` is not an audio track.
+pub fn export_track<I: Image + ?Sized>(
+    image: &mut I,
+    track: Bcd,
+    out: &mut dyn Write,
+    options: &WavOptions,
+) -> CdResult<()> {
+    if !image.toc().track(track)?.format.is_audio() {
+        return Err(CdError::BadFormat);
+    }
+
+    let (start, end) = image.track_range(track, options.gap_placement)?;
+
+    let data_len = (end - start).sector_index() * 2352;
+
+    out.write_all(&header(data_len))?;
+
+    let mut filter = DeEmphasis::new();
+
+    for msf in MsfRange::new(start, end) {
+        let mut sector = image.read_sector(DiscPosition::Program(msf))?;
+
+        if options.de_emphasis {
+            filter.process_sector(&mut sector);
+        }
+
+        out.write_all(sector.data_2352())?;
+    }
+
+    Ok(())
+}